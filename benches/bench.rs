@@ -1,6 +1,12 @@
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use criterion::{
+    BenchmarkGroup, BenchmarkId, Criterion, black_box, criterion_group, criterion_main,
+    measurement::WallTime,
+};
 use pasta_curves::Fp;
-use zk_psi_verifier::{generate_proof, hash_to_field, setup_eq, verify_proof, PsiCircuit};
+use zk_psi_verifier::{
+    PackedPsiCircuit, PsiCircuit, SetCapacity, SortPsiCircuit, generate_proof, hash_to_field,
+    layout_size, min_k, setup_eq, setup_eq_with_capacity, setup_for, setup_sort, verify_proof,
+};
 
 fn bench_proof_generation(c: &mut Criterion) {
     let mut group = c.benchmark_group("proof_generation");
@@ -11,7 +17,7 @@ fn bench_proof_generation(c: &mut Criterion) {
             .map(|i| hash_to_field(i as u64))
             .collect();
 
-        let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+        let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
         let intersection_size = circuit.compute_intersection_size();
 
         let k = 12;
@@ -26,8 +32,9 @@ fn bench_proof_generation(c: &mut Criterion) {
                         black_box(set_a.clone()),
                         black_box(set_b.clone()),
                         black_box(intersection_size),
-                    );
-                    let public_inputs = vec![Fp::from(intersection_size)];
+                    )
+                    .unwrap();
+                    let public_inputs = circuit.public_inputs();
 
                     generate_proof(
                         black_box(&params),
@@ -53,14 +60,14 @@ fn bench_proof_verification(c: &mut Criterion) {
             .map(|i| hash_to_field(i as u64))
             .collect();
 
-        let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+        let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
         let intersection_size = circuit.compute_intersection_size();
 
         let k = 12;
         let (params, pk, vk) = setup_eq(k).expect("Setup failed");
 
-        let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
-        let public_inputs = vec![Fp::from(intersection_size)];
+        let circuit = PsiCircuit::new(set_a, set_b, intersection_size).unwrap();
+        let public_inputs = circuit.public_inputs();
         let proof =
             generate_proof(&params, &pk, circuit, &public_inputs).expect("Proof generation failed");
 
@@ -87,7 +94,7 @@ fn bench_proof_verification(c: &mut Criterion) {
 fn bench_setup(c: &mut Criterion) {
     let mut group = c.benchmark_group("setup");
 
-    for k in [10, 12, 14].iter() {
+    for k in [12, 13, 14].iter() {
         group.bench_with_input(
             BenchmarkId::from_parameter(format!("k={}", k)),
             k,
@@ -118,7 +125,8 @@ fn bench_intersection_computation(c: &mut Criterion) {
                         black_box(set_a.clone()),
                         black_box(set_b.clone()),
                         black_box(0),
-                    );
+                    )
+                    .unwrap();
                     circuit.compute_intersection_size()
                 });
             },
@@ -128,11 +136,167 @@ fn bench_intersection_computation(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_layouts(c: &mut Criterion) {
+    // Full 32x32 sets sharing 16 elements. Both layouts are fixed-shape, so
+    // the costs only depend on the maximum set size: the grid needs k = 12,
+    // the sorted merge k = 13.
+    let set_a: Vec<Fp> = (1..=32).map(hash_to_field).collect();
+    let set_b: Vec<Fp> = (17..=48).map(hash_to_field).collect();
+
+    let mut group = c.benchmark_group("layouts");
+    group.sample_size(10);
+
+    group.bench_function(BenchmarkId::new("setup", "grid"), |b| {
+        b.iter(|| setup_eq(black_box(12)).expect("Setup failed"))
+    });
+    group.bench_function(BenchmarkId::new("setup", "sort"), |b| {
+        b.iter(|| setup_sort(black_box(13), SetCapacity::default()).expect("Setup failed"))
+    });
+
+    let (grid_params, grid_pk, grid_vk) = setup_eq(12).expect("Setup failed");
+    let (sort_params, sort_pk, sort_vk) =
+        setup_sort(13, SetCapacity::default()).expect("Setup failed");
+
+    group.bench_function(BenchmarkId::new("proof_generation", "grid"), |b| {
+        b.iter(|| {
+            let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 16).unwrap();
+            let public_inputs = circuit.public_inputs();
+            generate_proof(&grid_params, &grid_pk, black_box(circuit), &public_inputs)
+                .expect("Proof generation failed")
+        })
+    });
+    group.bench_function(BenchmarkId::new("proof_generation", "sort"), |b| {
+        b.iter(|| {
+            let circuit = SortPsiCircuit::new(set_a.clone(), set_b.clone(), 16).unwrap();
+            let public_inputs = circuit.public_inputs();
+            generate_proof(&sort_params, &sort_pk, black_box(circuit), &public_inputs)
+                .expect("Proof generation failed")
+        })
+    });
+
+    let grid_circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 16).unwrap();
+    let grid_inputs = grid_circuit.public_inputs();
+    let grid_proof = generate_proof(&grid_params, &grid_pk, grid_circuit, &grid_inputs)
+        .expect("Proof generation failed");
+    let sort_circuit = SortPsiCircuit::new(set_a, set_b, 16).unwrap();
+    let sort_inputs = sort_circuit.public_inputs();
+    let sort_proof = generate_proof(&sort_params, &sort_pk, sort_circuit, &sort_inputs)
+        .expect("Proof generation failed");
+
+    group.bench_function(BenchmarkId::new("proof_verification", "grid"), |b| {
+        b.iter(|| {
+            verify_proof(&grid_params, &grid_vk, black_box(&grid_proof), &grid_inputs)
+                .expect("Verification failed")
+        })
+    });
+    group.bench_function(BenchmarkId::new("proof_verification", "sort"), |b| {
+        b.iter(|| {
+            verify_proof(&sort_params, &sort_vk, black_box(&sort_proof), &sort_inputs)
+                .expect("Verification failed")
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_row_packing(c: &mut Criterion) {
+    // Full 64x64 sets sharing 32 elements. Below that size the commitment
+    // hashing takes more rows than the grid, so packing saves no rows.
+    let capacity = SetCapacity::new(64, 64).unwrap();
+    let set_a: Vec<Fp> = (1..=64).map(hash_to_field).collect();
+    let set_b: Vec<Fp> = (33..=96).map(hash_to_field).collect();
+    let psi =
+        PsiCircuit::new_with_capacity(capacity, set_a, set_b, 32, Fp::one(), Fp::one()).unwrap();
+
+    let mut group = c.benchmark_group("row_packing");
+    group.sample_size(10);
+
+    bench_lanes::<1>(&mut group, &psi);
+    bench_lanes::<2>(&mut group, &psi);
+    bench_lanes::<4>(&mut group, &psi);
+    bench_lanes::<8>(&mut group, &psi);
+
+    group.finish();
+}
+
+/// Report the layout and proof size of a grid with `LANES` lanes, and
+/// benchmark its key generation and proving
+fn bench_lanes<const LANES: usize>(group: &mut BenchmarkGroup<WallTime>, psi: &PsiCircuit) {
+    let circuit = PackedPsiCircuit::<LANES>::new(psi.clone());
+    let size = layout_size(&circuit).expect("Layout failed");
+    let k = min_k(&circuit).expect("Layout failed");
+
+    let (params, pk, vk) = setup_for(k, &circuit).expect("Setup failed");
+    let public_inputs = circuit.public_inputs();
+    let proof = generate_proof(&params, &pk, circuit.clone(), &public_inputs)
+        .expect("Proof generation failed");
+    verify_proof(&params, &vk, &proof, &public_inputs).expect("Verification failed");
+    println!(
+        "{} lanes: {} rows (k = {}), {} advice columns, {} byte proof",
+        LANES,
+        size.rows,
+        k,
+        size.advice_columns,
+        proof.len()
+    );
+
+    group.bench_function(BenchmarkId::new("setup", LANES), |b| {
+        b.iter(|| setup_for(black_box(k), &circuit).expect("Setup failed"))
+    });
+    group.bench_function(BenchmarkId::new("proof_generation", LANES), |b| {
+        b.iter(|| {
+            generate_proof(&params, &pk, black_box(circuit.clone()), &public_inputs)
+                .expect("Proof generation failed")
+        })
+    });
+}
+
+fn bench_grid_assignment(c: &mut Criterion) {
+    // Full grids of half-overlapping sets. The whole grid is assigned in one
+    // region, so laying it out no longer shapes a region per comparison row.
+    let mut group = c.benchmark_group("grid_assignment");
+    group.sample_size(10);
+
+    for size in [32u64, 64] {
+        let capacity = SetCapacity::new(size as usize, size as usize).unwrap();
+        let set_a: Vec<Fp> = (1..=size).map(hash_to_field).collect();
+        let set_b: Vec<Fp> = (size / 2 + 1..=size / 2 + size)
+            .map(hash_to_field)
+            .collect();
+        let psi =
+            PsiCircuit::new_with_capacity(capacity, set_a, set_b, size / 2, Fp::one(), Fp::one())
+                .unwrap();
+        let k = min_k(&psi).expect("Layout failed");
+        let id = format!("{}x{}", size, size);
+
+        group.bench_function(BenchmarkId::new("layout", &id), |b| {
+            b.iter(|| layout_size(black_box(&psi)).expect("Layout failed"))
+        });
+        group.bench_function(BenchmarkId::new("setup", &id), |b| {
+            b.iter(|| setup_eq_with_capacity(black_box(k), capacity).expect("Setup failed"))
+        });
+
+        let (params, pk, _vk) = setup_eq_with_capacity(k, capacity).expect("Setup failed");
+        let public_inputs = psi.public_inputs();
+        group.bench_function(BenchmarkId::new("proof_generation", &id), |b| {
+            b.iter(|| {
+                generate_proof(&params, &pk, black_box(psi.clone()), &public_inputs)
+                    .expect("Proof generation failed")
+            })
+        });
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_proof_generation,
     bench_proof_verification,
     bench_setup,
-    bench_intersection_computation
+    bench_intersection_computation,
+    bench_layouts,
+    bench_row_packing,
+    bench_grid_assignment
 );
 criterion_main!(benches);
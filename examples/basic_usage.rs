@@ -1,5 +1,5 @@
 use pasta_curves::Fp;
-use zk_psi_verifier::{generate_proof, hash_to_field, setup_eq, verify_proof, PsiCircuit};
+use zk_psi_verifier::{PsiCircuit, generate_proof, hash_to_field, setup_eq, verify_proof};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("ZK-PSI Basic Example\n");
@@ -16,7 +16,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let set_b: Vec<Fp> = set_b_values.iter().map(|&x| hash_to_field(x)).collect();
 
     // Create circuit to compute intersection
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0)?;
     let intersection_size = circuit.compute_intersection_size();
 
     println!("\nIntersection size: {}", intersection_size);
@@ -24,14 +24,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Perform trusted setup
     println!("Performing trusted setup...");
-    let k = 10; // Circuit size parameter
+    let k = 12; // Circuit size parameter
     let (params, pk, vk) = setup_eq(k)?;
     println!("Setup complete\n");
 
     // Generate proof
     println!("Generating zero-knowledge proof...");
-    let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
-    let public_inputs = vec![Fp::from(intersection_size)];
+    let circuit = PsiCircuit::new(set_a, set_b, intersection_size)?;
+    let public_inputs = circuit.public_inputs();
 
     let proof = generate_proof(&params, &pk, circuit, &public_inputs)
         .map_err(|e| format!("Proof generation failed: {:?}", e))?;
@@ -1,5 +1,5 @@
 use pasta_curves::Fp;
-use zk_psi_verifier::{generate_proof, hash_string_to_field, setup_eq, verify_proof, PsiCircuit};
+use zk_psi_verifier::{PsiCircuit, generate_proof, hash_string_to_field, setup_eq, verify_proof};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("ZK-PSI String Sets Example\n");
@@ -24,21 +24,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .collect();
 
     // Compute intersection
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0)?;
     let intersection_size = circuit.compute_intersection_size();
 
     println!("\nCommon contacts: {}", intersection_size);
 
     // Setup
     println!("Performing trusted setup...");
-    let k = 10;
+    let k = 12;
     let (params, pk, vk) = setup_eq(k)?;
     println!("Setup complete\n");
 
     // Alice generates a proof
     println!("Alice generating proof of common contacts...");
-    let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
-    let public_inputs = vec![Fp::from(intersection_size)];
+    let circuit = PsiCircuit::new(set_a, set_b, intersection_size)?;
+    let public_inputs = circuit.public_inputs();
 
     let proof = generate_proof(&params, &pk, circuit, &public_inputs)
         .map_err(|e| format!("Proof generation failed: {:?}", e))?;
@@ -1,13 +1,31 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use pasta_curves::Fp;
-use std::fs;
+use clap::{Parser, Subcommand, ValueEnum};
+use ff::{Field, PrimeField};
+use halo2_proofs::{
+    plonk::{ProvingKey, VerifyingKey},
+    poly::commitment::Params,
+};
+use pasta_curves::{EqAffine, Fp};
+use rand::rngs::OsRng;
+use std::fs::File;
+use std::io::BufReader;
 use std::path::PathBuf;
 use std::time::Instant;
 
+#[cfg(feature = "submit")]
+use std::time::Duration;
+#[cfg(feature = "submit")]
+use zk_psi_verifier::submit::{SubmissionClient, SubmissionStatus};
 use zk_psi_verifier::{
-    PsiCircuit, draw_circuit, generate_proof, hash_string_to_field, hash_to_field, setup_eq,
-    verify_proof,
+    BLOCKLIST_COMMITMENT_ROW, COMMITMENT_A_ROW, COMMITMENT_B_ROW, DISJOINT_SET_COMMITMENT_ROW,
+    DisjointnessCircuit, INTERSECTION_SIZE_ROW, IntersectionSemantics, KeyCircuit, KeyConfig,
+    MAX_PARTIES, MAX_SET_SIZE, MULTI_COMMITMENTS_ROW, MULTI_COUNT_ROW, MultiPsiCircuit,
+    ProofEnvelope, ProofJson, PsiCircuit, SUBSET_COMMITMENT_ROW, SUPERSET_COMMITMENT_ROW,
+    SetCapacity, SubsetCircuit, ThresholdPsiCircuit, commit_blocklist_with_capacity,
+    commit_set_with_capacity, draw_circuit, generate_proof, hash_string_to_field, hash_to_field,
+    params_digest, params_k, prove_disjointness, prove_multi, prove_subset, prove_threshold,
+    read_params, read_proving_key, read_verifying_key, verify_disjointness, verify_multi,
+    verify_proof, verify_subset, verify_threshold,
 };
 
 #[derive(Parser)]
@@ -18,6 +36,24 @@ struct Cli {
     command: Commands,
 }
 
+/// How repeated elements are counted
+#[derive(Clone, Copy, ValueEnum)]
+enum Semantics {
+    /// Sets without duplicates
+    Set,
+    /// Duplicates count min(multiplicity in A, multiplicity in B) times
+    Multiset,
+}
+
+impl From<Semantics> for IntersectionSemantics {
+    fn from(semantics: Semantics) -> Self {
+        match semantics {
+            Semantics::Set => IntersectionSemantics::Set,
+            Semantics::Multiset => IntersectionSemantics::Multiset,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Generate a zero-knowledge proof for private set intersection
@@ -30,7 +66,7 @@ enum Commands {
         #[arg(short = 'b', long)]
         set_b: String,
 
-        /// Output file for the proof
+        /// Output file for the proof envelope
         #[arg(short, long, default_value = "proof.bin")]
         output: PathBuf,
 
@@ -42,20 +78,289 @@ enum Commands {
         #[arg(long, default_value = "./keys/params.bin")]
         params: PathBuf,
 
-        /// Output file for public inputs
-        #[arg(long, default_value = "public_inputs.bin")]
-        public_inputs_file: PathBuf,
+        /// Salt of the published commitment to set A (hex); random if omitted
+        #[arg(long)]
+        salt_a: Option<String>,
+
+        /// Salt of the published commitment to set B (hex); random if omitted
+        #[arg(long)]
+        salt_b: Option<String>,
+
+        /// Intersection semantics
+        #[arg(long, value_enum, default_value = "set")]
+        semantics: Semantics,
+
+        /// Only prove that the intersection size is at least this value,
+        /// keeping the exact size private
+        #[arg(long)]
+        min_intersection: Option<u64>,
     },
 
     /// Verify a zero-knowledge proof
     Verify {
-        /// Path to the proof file
+        /// Path to the proof envelope
+        #[arg(short, long)]
+        proof: PathBuf,
+
+        /// Path to the verifying key
+        #[arg(long, default_value = "./keys/verifying_key.bin")]
+        vk: PathBuf,
+
+        /// Path to the params file
+        #[arg(long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+
+        /// Expected (previously published) commitment to set A (hex)
+        #[arg(long)]
+        commitment_a: Option<String>,
+
+        /// Expected (previously published) commitment to set B (hex)
+        #[arg(long)]
+        commitment_b: Option<String>,
+
+        /// Intersection semantics the proof was generated under
+        #[arg(long, value_enum, default_value = "set")]
+        semantics: Semantics,
+
+        /// Verify a threshold proof that the intersection size is at least
+        /// this value
+        #[arg(long)]
+        min_intersection: Option<u64>,
+    },
+
+    /// Compute the commitment to a set, to be published before proving
+    Commit {
+        /// The set (comma-separated values)
+        #[arg(short, long)]
+        set: String,
+
+        /// Commitment salt (hex); random if omitted
+        #[arg(long)]
+        salt: Option<String>,
+
+        /// Maximum set size of the circuit the commitment is for, i.e. the
+        /// --max-a or --max-b passed to zk-psi-setup
+        #[arg(long, default_value_t = MAX_SET_SIZE)]
+        capacity: usize,
+    },
+
+    /// Prove or verify that a private set is disjoint from a public blocklist
+    Disjoint {
+        #[command(subcommand)]
+        action: DisjointAction,
+    },
+
+    /// Prove or verify that every element of one private set is in another
+    Subset {
+        #[command(subcommand)]
+        action: SubsetAction,
+    },
+
+    /// Prove or verify how many elements several private sets share
+    Multi {
+        #[command(subcommand)]
+        action: MultiAction,
+    },
+
+    /// Export a proof envelope as JSON with hex-encoded bytes
+    ExportJson {
+        /// Path to the proof envelope
+        #[arg(short, long)]
+        proof: PathBuf,
+
+        /// Output file for the JSON export
+        #[arg(short, long, default_value = "proof.json")]
+        output: PathBuf,
+    },
+
+    /// Submit a proof written by export-json to a gateway and wait until it
+    /// is finalized
+    #[cfg(feature = "submit")]
+    Submit {
+        /// Path to the JSON export
+        #[arg(short, long, default_value = "proof.json")]
+        proof: PathBuf,
+
+        /// URL of the gateway, e.g. https://gateway.example/rpc
+        #[arg(short, long)]
+        endpoint: String,
+
+        /// Seconds between status requests
+        #[arg(long, default_value_t = 5)]
+        poll_interval: u64,
+
+        /// Seconds to wait for the proof to be finalized
+        #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: u64,
+
+        /// Print the submission id and exit without waiting
+        #[arg(long)]
+        no_wait: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum MultiAction {
+    /// Generate a proof of the number of elements shared by the sets
+    Prove {
+        /// A set (comma-separated values); repeat once per party
+        #[arg(short, long = "set", required = true)]
+        sets: Vec<String>,
+
+        /// Count the elements in at least this many sets instead of in all
+        #[arg(long)]
+        min_sets: Option<usize>,
+
+        /// Salt of the published commitment to each set (hex), in the order
+        /// of the sets; random if omitted
+        #[arg(long = "salt")]
+        salts: Vec<String>,
+
+        /// Output file for the proof envelope
+        #[arg(short, long, default_value = "proof.bin")]
+        output: PathBuf,
+
+        /// Path to the proving key
+        #[arg(long, default_value = "./keys/proving_key.bin")]
+        pk: PathBuf,
+
+        /// Path to the params file
+        #[arg(long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+    },
+
+    /// Verify a multi-party proof
+    Verify {
+        /// Path to the proof envelope
         #[arg(short, long)]
         proof: PathBuf,
 
-        /// Path to public inputs file
+        /// Minimum number of sets the proof counted elements in; all if omitted
         #[arg(long)]
-        public_inputs: PathBuf,
+        min_sets: Option<usize>,
+
+        /// Expected (previously published) commitment to each set (hex), in
+        /// the order of the sets
+        #[arg(long = "commitment")]
+        commitments: Vec<String>,
+
+        /// Path to the verifying key
+        #[arg(long, default_value = "./keys/verifying_key.bin")]
+        vk: PathBuf,
+
+        /// Path to the params file
+        #[arg(long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum SubsetAction {
+    /// Generate a proof that set A is contained in set B
+    Prove {
+        /// Set that must be contained (comma-separated values)
+        #[arg(short = 'a', long)]
+        set_a: String,
+
+        /// Containing set (comma-separated values)
+        #[arg(short = 'b', long)]
+        set_b: String,
+
+        /// Salt of the published commitment to set A (hex); random if omitted
+        #[arg(long)]
+        salt_a: Option<String>,
+
+        /// Salt of the published commitment to set B (hex); random if omitted
+        #[arg(long)]
+        salt_b: Option<String>,
+
+        /// Intersection semantics
+        #[arg(long, value_enum, default_value = "set")]
+        semantics: Semantics,
+
+        /// Output file for the proof envelope
+        #[arg(short, long, default_value = "proof.bin")]
+        output: PathBuf,
+
+        /// Path to the proving key
+        #[arg(long, default_value = "./keys/proving_key.bin")]
+        pk: PathBuf,
+
+        /// Path to the params file
+        #[arg(long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+    },
+
+    /// Verify a subset proof
+    Verify {
+        /// Path to the proof envelope
+        #[arg(short, long)]
+        proof: PathBuf,
+
+        /// Expected (previously published) commitment to set A (hex)
+        #[arg(long)]
+        commitment_a: Option<String>,
+
+        /// Expected (previously published) commitment to set B (hex)
+        #[arg(long)]
+        commitment_b: Option<String>,
+
+        /// Intersection semantics the proof was generated under
+        #[arg(long, value_enum, default_value = "set")]
+        semantics: Semantics,
+
+        /// Path to the verifying key
+        #[arg(long, default_value = "./keys/verifying_key.bin")]
+        vk: PathBuf,
+
+        /// Path to the params file
+        #[arg(long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum DisjointAction {
+    /// Generate a proof that no element of the set is on the blocklist
+    Prove {
+        /// Private set (comma-separated values)
+        #[arg(short, long)]
+        set: String,
+
+        /// Public blocklist (comma-separated values)
+        #[arg(long)]
+        blocklist: String,
+
+        /// Salt of the published commitment to the set (hex); random if omitted
+        #[arg(long)]
+        salt: Option<String>,
+
+        /// Output file for the proof envelope
+        #[arg(short, long, default_value = "proof.bin")]
+        output: PathBuf,
+
+        /// Path to the proving key
+        #[arg(long, default_value = "./keys/proving_key.bin")]
+        pk: PathBuf,
+
+        /// Path to the params file
+        #[arg(long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+    },
+
+    /// Verify a disjointness proof against the blocklist
+    Verify {
+        /// Path to the proof envelope
+        #[arg(short, long)]
+        proof: PathBuf,
+
+        /// Public blocklist (comma-separated values)
+        #[arg(long)]
+        blocklist: String,
+
+        /// Expected (previously published) commitment to the set (hex)
+        #[arg(long)]
+        commitment: Option<String>,
 
         /// Path to the verifying key
         #[arg(long, default_value = "./keys/verifying_key.bin")]
@@ -84,13 +389,757 @@ fn parse_set(input: &str) -> Result<Vec<Fp>> {
         .collect()
 }
 
+/// Encode a field element as hex of its little-endian representation
+fn field_to_hex(value: &Fp) -> String {
+    value
+        .to_repr()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Decode a field element from hex of its little-endian representation
+fn field_from_hex(input: &str) -> Result<Fp> {
+    let input = input.trim().trim_start_matches("0x");
+    anyhow::ensure!(
+        input.len() == 64 && input.bytes().all(|b| b.is_ascii_hexdigit()),
+        "Expected 64 hex characters"
+    );
+
+    let mut repr = [0u8; 32];
+    for (i, byte) in repr.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&input[2 * i..2 * i + 2], 16)
+            .with_context(|| format!("Invalid hex in {:?}", input))?;
+    }
+
+    Option::from(Fp::from_repr(repr)).context("Value is not a canonical field element")
+}
+
+/// Parse an optional hex salt, drawing a random one if absent
+fn parse_salt(salt: Option<String>) -> Result<Fp> {
+    match salt {
+        Some(salt) => field_from_hex(&salt),
+        None => Ok(Fp::random(OsRng)),
+    }
+}
+
+/// Small public value, such as a count, of a proof
+fn field_to_u64(value: &Fp) -> Result<u64> {
+    let repr = value.to_repr();
+    anyhow::ensure!(
+        repr.as_ref()[8..].iter().all(|byte| *byte == 0),
+        "Public count out of range"
+    );
+    Ok(u64::from_le_bytes(repr.as_ref()[..8].try_into()?))
+}
+
+/// Hex encoding of a params or key digest
+fn digest_to_hex(digest: &[u8; 32]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Read the params written by zk-psi-setup
+fn read_params_file(params_path: &PathBuf) -> Result<Params<EqAffine>> {
+    let file = File::open(params_path)
+        .with_context(|| format!("Failed to read params from {:?}", params_path))?;
+    let params = read_params(&mut BufReader::new(file))
+        .context("Invalid params file, regenerate it with zk-psi-setup")?;
+    println!(
+        "Params loaded (k={}, digest {})",
+        params_k(&params),
+        digest_to_hex(&params_digest(&params))
+    );
+    Ok(params)
+}
+
+/// Circuit id in the envelopes of PSI proofs, or of threshold proofs
+fn psi_circuit_id(threshold: bool) -> &'static str {
+    match threshold {
+        true => "psi-threshold",
+        false => "psi",
+    }
+}
+
+/// Write the envelope of a proof of `circuit`
+fn write_envelope(
+    output: &PathBuf,
+    circuit: &str,
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    public_inputs: &[Fp],
+    proof: Vec<u8>,
+) -> Result<()> {
+    let envelope = ProofEnvelope::new(circuit, params, vk, public_inputs, proof);
+    let mut file =
+        File::create(output).with_context(|| format!("Failed to write proof to {:?}", output))?;
+    envelope
+        .write(&mut file)
+        .with_context(|| format!("Failed to write proof to {:?}", output))?;
+    println!("Proof saved to {:?}", output);
+    Ok(())
+}
+
+/// Read a proof envelope, failing unless it is for `circuit` and carries
+/// `count` public inputs
+fn read_envelope(
+    proof_path: &PathBuf,
+    circuit: &str,
+    count: impl Fn(usize) -> bool,
+) -> Result<(ProofEnvelope, Vec<Fp>)> {
+    let mut file = File::open(proof_path)
+        .with_context(|| format!("Failed to read proof from {:?}", proof_path))?;
+    let envelope = ProofEnvelope::read(&mut BufReader::new(&mut file))
+        .with_context(|| format!("Invalid proof file {:?}", proof_path))?;
+    anyhow::ensure!(
+        envelope.circuit == circuit,
+        "This is a {} proof, not a {} proof",
+        envelope.circuit,
+        circuit
+    );
+    let public_inputs = envelope.public_inputs()?;
+    anyhow::ensure!(
+        count(public_inputs.len()),
+        "Invalid number of public inputs ({}) for a {} proof",
+        public_inputs.len(),
+        circuit
+    );
+    println!(
+        "Proof loaded ({} bytes, k={}, params digest {}, key digest {})",
+        envelope.proof.len(),
+        envelope.k,
+        digest_to_hex(&envelope.params_digest),
+        digest_to_hex(&envelope.vk_digest)
+    );
+    Ok((envelope, public_inputs))
+}
+
+/// Fail unless a proof was produced against the loaded params and key
+fn check_envelope(
+    envelope: &ProofEnvelope,
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+) -> Result<()> {
+    envelope
+        .check(params, vk)
+        .context("The proof does not match the loaded params and verifying key")
+}
+
+/// Fail unless the sets fit the maximum sizes the keys were generated for
+fn check_capacity(capacity: SetCapacity, set_a: &[Fp], set_b: &[Fp]) -> Result<()> {
+    for (name, set, max) in [("A", set_a, capacity.max_a), ("B", set_b, capacity.max_b)] {
+        anyhow::ensure!(
+            set.len() <= max,
+            "Set {} has {} elements, more than the maximum of {} set up by zk-psi-setup",
+            name,
+            set.len(),
+            max
+        );
+    }
+    Ok(())
+}
+
+/// Name of the circuit of a key, with the zk-psi-setup options selecting it
+fn key_circuit_name(circuit: KeyCircuit) -> String {
+    match circuit {
+        KeyCircuit::Psi => "--circuit psi".to_string(),
+        KeyCircuit::Disjoint => "--circuit disjoint".to_string(),
+        KeyCircuit::Subset => "--circuit subset".to_string(),
+        KeyCircuit::Multi { parties, threshold } => format!(
+            "--circuit multi --parties {} --min-sets {}",
+            parties, threshold
+        ),
+    }
+}
+
+/// Fail unless a key file was generated for the circuit of this run
+fn check_key_config(
+    kind: &str,
+    stored: KeyConfig,
+    circuit: KeyCircuit,
+    semantics: IntersectionSemantics,
+    threshold: bool,
+) -> Result<()> {
+    anyhow::ensure!(
+        stored.circuit == circuit,
+        "The {} key was set up with {}, not {}; rerun zk-psi-setup with the matching options",
+        kind,
+        key_circuit_name(stored.circuit),
+        key_circuit_name(circuit)
+    );
+    if let KeyCircuit::Psi | KeyCircuit::Subset = circuit {
+        anyhow::ensure!(
+            stored.semantics == semantics,
+            "The {} key is for {:?} semantics, not {:?}; pass the matching --semantics or rerun \
+             zk-psi-setup",
+            kind,
+            stored.semantics,
+            semantics
+        );
+    }
+    anyhow::ensure!(
+        stored.threshold == threshold,
+        match stored.threshold {
+            true => format!(
+                "The {} key is for threshold proofs; pass --min-intersection",
+                kind
+            ),
+            false => format!(
+                "The {} key is not for threshold proofs; rerun zk-psi-setup with --threshold",
+                kind
+            ),
+        }
+    );
+    println!(
+        "Key loaded (k={}, max |A|={}, max |B|={})",
+        stored.k, stored.capacity.max_a, stored.capacity.max_b
+    );
+    Ok(())
+}
+
+/// Load the proving key written by zk-psi-setup for `circuit` with
+/// `semantics`, or the threshold variant of the PSI circuit
+fn load_proving_key(
+    pk_path: &PathBuf,
+    params: &Params<EqAffine>,
+    circuit: KeyCircuit,
+    semantics: IntersectionSemantics,
+    threshold: bool,
+) -> Result<(KeyConfig, ProvingKey<EqAffine>)> {
+    let mut file = File::open(pk_path)
+        .with_context(|| format!("Failed to read proving key from {:?}", pk_path))?;
+    let (config, pk) = read_proving_key(&mut file, params).with_context(|| {
+        format!(
+            "Invalid proving key {:?}, regenerate it with zk-psi-setup",
+            pk_path
+        )
+    })?;
+    check_key_config("proving", config, circuit, semantics, threshold)?;
+    Ok((config, pk))
+}
+
+/// Load the verifying key written by zk-psi-setup for `circuit` with
+/// `semantics`, or the threshold variant of the PSI circuit
+fn load_verifying_key(
+    vk_path: &PathBuf,
+    params: &Params<EqAffine>,
+    circuit: KeyCircuit,
+    semantics: IntersectionSemantics,
+    threshold: bool,
+) -> Result<(KeyConfig, VerifyingKey<EqAffine>)> {
+    let mut file = File::open(vk_path)
+        .with_context(|| format!("Failed to read verifying key from {:?}", vk_path))?;
+    let (config, vk) = read_verifying_key(&mut file, params).with_context(|| {
+        format!(
+            "Invalid verifying key {:?}, regenerate it with zk-psi-setup",
+            vk_path
+        )
+    })?;
+    check_key_config("verifying", config, circuit, semantics, threshold)?;
+    Ok((config, vk))
+}
+
+fn disjoint_prove_command(
+    set_str: String,
+    blocklist_str: String,
+    salt: Option<String>,
+    output: PathBuf,
+    pk_path: PathBuf,
+    params_path: PathBuf,
+) -> Result<()> {
+    println!("ZK-PSI Disjointness Proof Generation");
+
+    let start = Instant::now();
+    let set = parse_set(&set_str).context("Failed to parse set")?;
+    let blocklist = parse_set(&blocklist_str).context("Failed to parse blocklist")?;
+    let salt = parse_salt(salt).context("Failed to parse salt")?;
+
+    println!("  Set: {} elements", set.len());
+    println!("  Blocklist: {} elements", blocklist.len());
+
+    let params = read_params_file(&params_path)?;
+    let (config, pk) = load_proving_key(
+        &pk_path,
+        &params,
+        KeyCircuit::Disjoint,
+        IntersectionSemantics::Set,
+        false,
+    )?;
+    let capacity = config.capacity;
+    anyhow::ensure!(
+        set.len() <= capacity.max_a,
+        "Set has {} elements, more than the maximum of {} set up by zk-psi-setup",
+        set.len(),
+        capacity.max_a
+    );
+    anyhow::ensure!(
+        blocklist.len() <= capacity.max_b,
+        "Blocklist has {} elements, more than the maximum of {} set up by zk-psi-setup",
+        blocklist.len(),
+        capacity.max_b
+    );
+
+    let circuit = DisjointnessCircuit::new_with_capacity(capacity, set, blocklist, salt)?;
+    anyhow::ensure!(
+        circuit.is_disjoint(),
+        "The set shares elements with the blocklist"
+    );
+
+    let public_inputs = circuit.public_inputs();
+    println!(
+        "Set commitment: {}",
+        field_to_hex(&public_inputs[DISJOINT_SET_COMMITMENT_ROW])
+    );
+    println!(
+        "Blocklist commitment: {}",
+        field_to_hex(&public_inputs[BLOCKLIST_COMMITMENT_ROW])
+    );
+
+    println!("\nGenerating proof...");
+    let proof = prove_disjointness(&params, &pk, circuit, &public_inputs)
+        .map_err(|e| anyhow::anyhow!("Proof generation failed: {:?}", e))?;
+    println!("Proof size: {} bytes", proof.len());
+
+    write_envelope(
+        &output,
+        "disjointness",
+        &params,
+        pk.get_vk(),
+        &public_inputs,
+        proof,
+    )?;
+
+    println!("Total time: {:.2?}", start.elapsed());
+    println!("Proof Generated Successfully!");
+
+    Ok(())
+}
+
+fn disjoint_verify_command(
+    proof_path: PathBuf,
+    blocklist_str: String,
+    commitment: Option<String>,
+    vk_path: PathBuf,
+    params_path: PathBuf,
+) -> Result<()> {
+    println!("ZK-PSI Disjointness Proof Verification");
+
+    let start = Instant::now();
+    let (envelope, public_inputs) = read_envelope(&proof_path, "disjointness", |n| n == 2)?;
+    let params = read_params_file(&params_path)?;
+    let (config, vk) = load_verifying_key(
+        &vk_path,
+        &params,
+        KeyCircuit::Disjoint,
+        IntersectionSemantics::Set,
+        false,
+    )?;
+    check_envelope(&envelope, &params, &vk)?;
+
+    // The blocklist is public, so its commitment is recomputed rather than trusted
+    let blocklist = parse_set(&blocklist_str).context("Failed to parse blocklist")?;
+    let capacity = config.capacity;
+    anyhow::ensure!(
+        blocklist.len() <= capacity.max_b,
+        "Blocklist has {} elements, more than the maximum of {} set up by zk-psi-setup",
+        blocklist.len(),
+        capacity.max_b
+    );
+    anyhow::ensure!(
+        commit_blocklist_with_capacity(&blocklist, capacity.max_b)
+            == public_inputs[BLOCKLIST_COMMITMENT_ROW],
+        "Public inputs were produced for a different blocklist"
+    );
+    println!(
+        "Blocklist commitment matches ({} elements)",
+        blocklist.len()
+    );
+
+    let committed = field_to_hex(&public_inputs[DISJOINT_SET_COMMITMENT_ROW]);
+    match commitment {
+        Some(expected) => {
+            let expected = field_from_hex(&expected).context("Failed to parse commitment")?;
+            anyhow::ensure!(
+                expected == public_inputs[DISJOINT_SET_COMMITMENT_ROW],
+                "Set commitment in public inputs ({}) does not match the published one",
+                committed
+            );
+            println!("Set commitment matches the published one");
+        }
+        None => println!("Set commitment: {} (not checked)", committed),
+    }
+
+    match verify_disjointness(&params, &vk, &envelope.proof, &public_inputs) {
+        Ok(_) => {
+            println!("Valid proof!");
+            println!("The committed set shares no element with the blocklist");
+            println!("Total verification time: {:.2?}", start.elapsed());
+            Ok(())
+        }
+        Err(e) => {
+            println!("Invalid proof!");
+            Err(anyhow::anyhow!("Verification failed: {:?}", e))
+        }
+    }
+}
+
+/// Reject duplicated elements up front under set semantics, where the circuit
+/// would refuse them anyway
+fn check_no_duplicates(semantics: IntersectionSemantics, sets: [(&str, &[Fp]); 2]) -> Result<()> {
+    if semantics == IntersectionSemantics::Set {
+        for (name, set) in sets {
+            let has_duplicates = set.iter().enumerate().any(|(i, x)| set[..i].contains(x));
+            anyhow::ensure!(
+                !has_duplicates,
+                "Set {} contains duplicate elements; use --semantics multiset",
+                name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Check a commitment from the public inputs against a published one, if given
+fn check_commitment(name: &str, expected: Option<String>, committed: &Fp) -> Result<()> {
+    match expected {
+        Some(expected) => {
+            let expected = field_from_hex(&expected)
+                .with_context(|| format!("Failed to parse commitment {}", name))?;
+            anyhow::ensure!(
+                expected == *committed,
+                "Commitment {} in public inputs ({}) does not match the published one",
+                name,
+                field_to_hex(committed)
+            );
+            println!("Commitment {} matches the published one", name);
+        }
+        None => println!(
+            "Commitment {}: {} (not checked)",
+            name,
+            field_to_hex(committed)
+        ),
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn subset_prove_command(
+    set_a_str: String,
+    set_b_str: String,
+    salt_a: Option<String>,
+    salt_b: Option<String>,
+    semantics: Semantics,
+    output: PathBuf,
+    pk_path: PathBuf,
+    params_path: PathBuf,
+) -> Result<()> {
+    println!("ZK-PSI Subset Proof Generation");
+
+    let start = Instant::now();
+    let set_a = parse_set(&set_a_str).context("Failed to parse set A")?;
+    let set_b = parse_set(&set_b_str).context("Failed to parse set B")?;
+    println!("  Set A: {} elements", set_a.len());
+    println!("  Set B: {} elements", set_b.len());
+
+    let semantics = IntersectionSemantics::from(semantics);
+    let params = read_params_file(&params_path)?;
+    let (config, pk) = load_proving_key(&pk_path, &params, KeyCircuit::Subset, semantics, false)?;
+    let capacity = config.capacity;
+    check_capacity(capacity, &set_a, &set_b)?;
+    check_no_duplicates(semantics, [("A", &set_a), ("B", &set_b)])?;
+
+    let salt_a = parse_salt(salt_a).context("Failed to parse salt A")?;
+    let salt_b = parse_salt(salt_b).context("Failed to parse salt B")?;
+
+    let psi = PsiCircuit::new_with_capacity(capacity, set_a, set_b, 0, salt_a, salt_b)?
+        .with_semantics(semantics)?;
+    let circuit = SubsetCircuit::new(psi);
+    anyhow::ensure!(circuit.is_subset(), "Set A is not contained in set B");
+
+    let public_inputs = circuit.public_inputs();
+    println!(
+        "Commitment A: {}",
+        field_to_hex(&public_inputs[SUBSET_COMMITMENT_ROW])
+    );
+    println!(
+        "Commitment B: {}",
+        field_to_hex(&public_inputs[SUPERSET_COMMITMENT_ROW])
+    );
+
+    println!("\nGenerating proof...");
+    let proof = prove_subset(&params, &pk, circuit, &public_inputs)
+        .map_err(|e| anyhow::anyhow!("Proof generation failed: {:?}", e))?;
+    println!("Proof size: {} bytes", proof.len());
+
+    write_envelope(
+        &output,
+        "subset",
+        &params,
+        pk.get_vk(),
+        &public_inputs,
+        proof,
+    )?;
+
+    println!("Total time: {:.2?}", start.elapsed());
+    println!("Proof Generated Successfully!");
+
+    Ok(())
+}
+
+fn subset_verify_command(
+    proof_path: PathBuf,
+    commitment_a: Option<String>,
+    commitment_b: Option<String>,
+    semantics: Semantics,
+    vk_path: PathBuf,
+    params_path: PathBuf,
+) -> Result<()> {
+    println!("ZK-PSI Subset Proof Verification");
+
+    let start = Instant::now();
+    let (envelope, public_inputs) = read_envelope(&proof_path, "subset", |n| n == 2)?;
+
+    check_commitment("A", commitment_a, &public_inputs[SUBSET_COMMITMENT_ROW])?;
+    check_commitment("B", commitment_b, &public_inputs[SUPERSET_COMMITMENT_ROW])?;
+
+    let params = read_params_file(&params_path)?;
+    let (_config, vk) = load_verifying_key(
+        &vk_path,
+        &params,
+        KeyCircuit::Subset,
+        semantics.into(),
+        false,
+    )?;
+    check_envelope(&envelope, &params, &vk)?;
+
+    match verify_subset(&params, &vk, &envelope.proof, &public_inputs) {
+        Ok(_) => {
+            println!("Valid proof!");
+            println!(
+                "Every element of the set behind commitment A is in the set behind commitment B"
+            );
+            println!("Total verification time: {:.2?}", start.elapsed());
+            Ok(())
+        }
+        Err(e) => {
+            println!("Invalid proof!");
+            Err(anyhow::anyhow!("Verification failed: {:?}", e))
+        }
+    }
+}
+
+fn multi_prove_command(
+    set_strs: Vec<String>,
+    min_sets: Option<usize>,
+    salts: Vec<String>,
+    output: PathBuf,
+    pk_path: PathBuf,
+    params_path: PathBuf,
+) -> Result<()> {
+    println!("ZK-PSI Multi-Party Proof Generation");
+
+    let start = Instant::now();
+    anyhow::ensure!(
+        (2..=MAX_PARTIES).contains(&set_strs.len()),
+        "Between 2 and {} sets are supported, got {}",
+        MAX_PARTIES,
+        set_strs.len()
+    );
+    anyhow::ensure!(
+        salts.is_empty() || salts.len() == set_strs.len(),
+        "Pass either no salt or one salt per set"
+    );
+
+    let mut sets = vec![];
+    for (i, set_str) in set_strs.iter().enumerate() {
+        let set = parse_set(set_str).with_context(|| format!("Failed to parse set {}", i + 1))?;
+        let has_duplicates = set.iter().enumerate().any(|(j, x)| set[..j].contains(x));
+        anyhow::ensure!(!has_duplicates, "Set {} contains duplicate elements", i + 1);
+        println!("  Set {}: {} elements", i + 1, set.len());
+        sets.push(set);
+    }
+
+    let parties = sets.len();
+    let threshold = min_sets.unwrap_or(parties);
+    anyhow::ensure!(
+        (1..=parties).contains(&threshold),
+        "--min-sets must be between 1 and the number of sets ({})",
+        parties
+    );
+
+    let params = read_params_file(&params_path)?;
+    let (config, pk) = load_proving_key(
+        &pk_path,
+        &params,
+        KeyCircuit::Multi { parties, threshold },
+        IntersectionSemantics::Set,
+        false,
+    )?;
+    let max_size = config.capacity.max_a;
+    for (i, set) in sets.iter().enumerate() {
+        anyhow::ensure!(
+            set.len() <= max_size,
+            "Set {} has {} elements, more than the maximum of {} set up by zk-psi-setup",
+            i + 1,
+            set.len(),
+            max_size
+        );
+    }
+
+    let salts = if salts.is_empty() {
+        (0..parties)
+            .map(|_| parse_salt(None))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        salts
+            .into_iter()
+            .enumerate()
+            .map(|(i, salt)| {
+                parse_salt(Some(salt)).with_context(|| format!("Failed to parse salt {}", i + 1))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let circuit =
+        MultiPsiCircuit::new_with_capacity(max_size, sets, salts, 0)?.with_threshold(threshold)?;
+    let count = circuit.compute_intersection_size();
+    println!(
+        "Elements in at least {} of {} sets: {}",
+        threshold, parties, count
+    );
+
+    let circuit = MultiPsiCircuit {
+        intersection_size: count,
+        ..circuit
+    };
+    let public_inputs = circuit.public_inputs();
+    for i in 0..parties {
+        println!(
+            "Commitment {}: {}",
+            i + 1,
+            field_to_hex(&public_inputs[MULTI_COMMITMENTS_ROW + i])
+        );
+    }
+
+    println!("\nGenerating proof...");
+    let proof = prove_multi(&params, &pk, circuit, &public_inputs)
+        .map_err(|e| anyhow::anyhow!("Proof generation failed: {:?}", e))?;
+    println!("Proof size: {} bytes", proof.len());
+
+    write_envelope(
+        &output,
+        "multi",
+        &params,
+        pk.get_vk(),
+        &public_inputs,
+        proof,
+    )?;
+
+    println!("Total time: {:.2?}", start.elapsed());
+    println!("Proof Generated Successfully!");
+
+    Ok(())
+}
+
+fn multi_verify_command(
+    proof_path: PathBuf,
+    min_sets: Option<usize>,
+    commitments: Vec<String>,
+    vk_path: PathBuf,
+    params_path: PathBuf,
+) -> Result<()> {
+    println!("ZK-PSI Multi-Party Proof Verification");
+
+    let start = Instant::now();
+    // The count is followed by one commitment per set
+    let (envelope, public_inputs) = read_envelope(&proof_path, "multi", |n| {
+        (MULTI_COMMITMENTS_ROW + 2..=MULTI_COMMITMENTS_ROW + MAX_PARTIES).contains(&n)
+    })?;
+    let parties = public_inputs.len() - MULTI_COMMITMENTS_ROW;
+    let threshold = min_sets.unwrap_or(parties);
+    anyhow::ensure!(
+        (1..=parties).contains(&threshold),
+        "--min-sets must be between 1 and the number of sets ({})",
+        parties
+    );
+    anyhow::ensure!(
+        commitments.is_empty() || commitments.len() == parties,
+        "The proof covers {} sets but {} commitments were given",
+        parties,
+        commitments.len()
+    );
+
+    let mut expected = commitments.into_iter().map(Some).collect::<Vec<_>>();
+    expected.resize(parties, None);
+    for (i, expected) in expected.into_iter().enumerate() {
+        check_commitment(
+            &(i + 1).to_string(),
+            expected,
+            &public_inputs[MULTI_COMMITMENTS_ROW + i],
+        )?;
+    }
+
+    let params = read_params_file(&params_path)?;
+    let (_config, vk) = load_verifying_key(
+        &vk_path,
+        &params,
+        KeyCircuit::Multi { parties, threshold },
+        IntersectionSemantics::Set,
+        false,
+    )?;
+    check_envelope(&envelope, &params, &vk)?;
+
+    match verify_multi(&params, &vk, &envelope.proof, &public_inputs) {
+        Ok(_) => {
+            println!("Valid proof!");
+            println!(
+                "Elements in at least {} of the {} committed sets: {}",
+                threshold,
+                parties,
+                field_to_u64(&public_inputs[MULTI_COUNT_ROW])?
+            );
+            println!("Total verification time: {:.2?}", start.elapsed());
+            Ok(())
+        }
+        Err(e) => {
+            println!("Invalid proof!");
+            Err(anyhow::anyhow!("Verification failed: {:?}", e))
+        }
+    }
+}
+
+fn commit_command(set_str: String, salt: Option<String>, capacity: usize) -> Result<()> {
+    let set = parse_set(&set_str).context("Failed to parse set")?;
+    let salt = parse_salt(salt).context("Failed to parse salt")?;
+    anyhow::ensure!(
+        set.len() <= capacity,
+        "Set has {} elements, more than the capacity of {}",
+        set.len(),
+        capacity
+    );
+
+    println!("Set: {} elements", set.len());
+    println!("Salt: {}", field_to_hex(&salt));
+    println!(
+        "Commitment: {}",
+        field_to_hex(&commit_set_with_capacity(&set, salt, capacity))
+    );
+    println!("Keep the salt private; pass it to 'prove' to reuse this commitment.");
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 fn prove_command(
     set_a_str: String,
     set_b_str: String,
     output: PathBuf,
-    _pk_path: PathBuf,
+    pk_path: PathBuf,
     params_path: PathBuf,
-    public_inputs_file: PathBuf,
+    salt_a: Option<String>,
+    salt_b: Option<String>,
+    semantics: Semantics,
+    min_intersection: Option<u64>,
 ) -> Result<()> {
     println!("ZK-PSI Proof Generation");
 
@@ -102,104 +1151,174 @@ fn prove_command(
     println!("  Set A: {} elements", set_a.len());
     println!("  Set B: {} elements", set_b.len());
 
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
-    let intersection_size = circuit.compute_intersection_size();
+    let semantics = IntersectionSemantics::from(semantics);
+    check_no_duplicates(semantics, [("A", &set_a), ("B", &set_b)])?;
+
+    let salt_a = parse_salt(salt_a).context("Failed to parse salt A")?;
+    let salt_b = parse_salt(salt_b).context("Failed to parse salt B")?;
+
+    let params = read_params_file(&params_path)?;
+    println!("Loading proving key...");
+    let (config, pk) = load_proving_key(
+        &pk_path,
+        &params,
+        KeyCircuit::Psi,
+        semantics,
+        min_intersection.is_some(),
+    )?;
+    let (k, capacity) = (config.k, config.capacity);
+    check_capacity(capacity, &set_a, &set_b)?;
+
+    let intersection_size = semantics.intersection_size(&set_a, &set_b);
     println!("Intersection size: {}", intersection_size);
 
+    if let Some(threshold) = min_intersection {
+        anyhow::ensure!(
+            intersection_size >= threshold,
+            "Intersection size {} is below the threshold {}",
+            intersection_size,
+            threshold
+        );
+        println!(
+            "Proving only that the intersection size is at least {}",
+            threshold
+        );
+    }
+
     // Create circuit with correct intersection size
-    let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
+    let circuit =
+        PsiCircuit::new_with_capacity(capacity, set_a, set_b, intersection_size, salt_a, salt_b)?
+            .with_semantics(semantics)?;
+    let public_value = min_intersection.unwrap_or(intersection_size);
+    let mut public_inputs = circuit.public_inputs();
+    public_inputs[INTERSECTION_SIZE_ROW] = Fp::from(public_value);
+    println!(
+        "Commitment A: {}",
+        field_to_hex(&public_inputs[COMMITMENT_A_ROW])
+    );
+    println!(
+        "Commitment B: {}",
+        field_to_hex(&public_inputs[COMMITMENT_B_ROW])
+    );
 
-    let k_bytes = fs::read(&params_path)
-        .with_context(|| format!("Failed to read params from {:?}", params_path))?;
-    let k: u32 = bincode::deserialize(&k_bytes)?;
     draw_circuit(k, &circuit);
 
-    println!("Params loaded (k={})", k);
-    println!("Regenerating proving key...");
-
-    let (params, pk, _vk) =
-        setup_eq(k).map_err(|e| anyhow::anyhow!("Failed to setup keys: {:?}", e))?;
-
     println!("\nGenerating proof...");
     let proof_start = Instant::now();
 
-    let public_inputs = vec![Fp::from(intersection_size)];
-    let proof = generate_proof(&params, &pk, circuit, &public_inputs)
-        .map_err(|e| anyhow::anyhow!("Proof generation failed: {:?}", e))?;
+    let proof = match min_intersection {
+        Some(threshold) => prove_threshold(
+            &params,
+            &pk,
+            ThresholdPsiCircuit::new(circuit, threshold),
+            &public_inputs,
+        ),
+        None => generate_proof(&params, &pk, circuit, &public_inputs),
+    }
+    .map_err(|e| anyhow::anyhow!("Proof generation failed: {:?}", e))?;
 
     let proof_time = proof_start.elapsed();
     println!("Proof generated in {:.2?}", proof_time);
     println!("Proof size: {} bytes", proof.len());
 
-    fs::write(&output, &proof).with_context(|| format!("Failed to write proof to {:?}", output))?;
-    println!("Proof saved to {:?}", output);
-
-    // Save public inputs (convert Fp to bytes manually)
-    let public_inputs_bytes: Vec<u8> = intersection_size.to_le_bytes().to_vec();
-    fs::write(&public_inputs_file, &public_inputs_bytes)
-        .with_context(|| format!("Failed to write public inputs to {:?}", public_inputs_file))?;
-    println!("Public inputs saved to {:?}", public_inputs_file);
+    write_envelope(
+        &output,
+        psi_circuit_id(min_intersection.is_some()),
+        &params,
+        pk.get_vk(),
+        &public_inputs,
+        proof,
+    )?;
 
     let total_time = start.elapsed();
     println!("Total time: {:.2?}", total_time);
     println!("Proof generation time: {:.2?}", proof_time);
-    println!("Public intersection size: {}", intersection_size);
+    match min_intersection {
+        Some(threshold) => println!("Public threshold: {}", threshold),
+        None => println!("Public intersection size: {}", intersection_size),
+    }
     println!("Proof Generated Successfully!");
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn verify_command(
     proof_path: PathBuf,
-    public_inputs_path: PathBuf,
-    _vk_path: PathBuf,
+    vk_path: PathBuf,
     params_path: PathBuf,
+    commitment_a: Option<String>,
+    commitment_b: Option<String>,
+    semantics: Semantics,
+    min_intersection: Option<u64>,
 ) -> Result<()> {
     println!("ZK-PSI Proof Verification");
 
     let start = Instant::now();
 
-    let proof = fs::read(&proof_path)
-        .with_context(|| format!("Failed to read proof from {:?}", proof_path))?;
-    println!("Proof loaded ({} bytes)", proof.len());
-
-    let public_inputs_bytes = fs::read(&public_inputs_path)
-        .with_context(|| format!("Failed to read public inputs from {:?}", public_inputs_path))?;
+    let circuit_id = psi_circuit_id(min_intersection.is_some());
+    let (envelope, public_inputs) = read_envelope(&proof_path, circuit_id, |n| n == 3)?;
+    let intersection_size = field_to_u64(&public_inputs[INTERSECTION_SIZE_ROW])?;
 
-    // Parse intersection size from bytes
-    let intersection_size = u64::from_le_bytes(
-        public_inputs_bytes[..8]
-            .try_into()
-            .context("Invalid public inputs format")?,
-    );
-
-    let public_inputs = vec![Fp::from(intersection_size)];
+    // A threshold proof carries the threshold where the exact size would be
+    if let Some(threshold) = min_intersection {
+        anyhow::ensure!(
+            intersection_size == threshold,
+            "Public inputs are for threshold {}, not {}",
+            intersection_size,
+            threshold
+        );
+    }
 
-    // Load params and regenerate verifying key
-    let k_bytes = fs::read(&params_path)
-        .with_context(|| format!("Failed to read params from {:?}", params_path))?;
-    let k: u32 = bincode::deserialize(&k_bytes)?;
+    // Tie the proof to the commitments published by the prover beforehand
+    check_commitment("A", commitment_a, &public_inputs[COMMITMENT_A_ROW])?;
+    check_commitment("B", commitment_b, &public_inputs[COMMITMENT_B_ROW])?;
 
-    println!("Params loaded (k={})", k);
-    println!("Regenerating verifying key...");
+    // Load params, which must be the ones the proof was produced against, and
+    // the verifying key
+    let params = read_params_file(&params_path)?;
+    anyhow::ensure!(
+        params_digest(&params) == envelope.params_digest,
+        "The proof was produced with other params (digest {}) than {:?}",
+        digest_to_hex(&envelope.params_digest),
+        params_path
+    );
 
-    let (params, _pk, vk) =
-        setup_eq(k).map_err(|e| anyhow::anyhow!("Failed to setup keys: {:?}", e))?;
+    println!("Loading verifying key...");
+    let (_config, vk) = load_verifying_key(
+        &vk_path,
+        &params,
+        KeyCircuit::Psi,
+        semantics.into(),
+        min_intersection.is_some(),
+    )?;
+    check_envelope(&envelope, &params, &vk)?;
 
     println!("\nVerifying proof...");
     let verify_start = Instant::now();
 
-    match verify_proof(&params, &vk, &proof, &public_inputs) {
+    let result = match min_intersection {
+        Some(_) => verify_threshold(&params, &vk, &envelope.proof, &public_inputs),
+        None => verify_proof(&params, &vk, &envelope.proof, &public_inputs),
+    };
+    match result {
         Ok(_) => {
             let verify_time = verify_start.elapsed();
             println!("Verification completed in {:.2?}", verify_time);
 
             let total_time = start.elapsed();
             println!("Valid proof!");
-            println!(
-                "The prover knows two sets with intersection size: {}",
-                intersection_size
-            );
+            match min_intersection {
+                Some(threshold) => println!(
+                    "The prover knows two sets with intersection size at least: {}",
+                    threshold
+                ),
+                None => println!(
+                    "The prover knows two sets with intersection size: {}",
+                    intersection_size
+                ),
+            }
+            println!("The sets are the ones behind commitments A and B");
             println!("Total verification time: {:.2?}", total_time);
             Ok(())
         }
@@ -210,6 +1329,72 @@ fn verify_command(
     }
 }
 
+fn export_json_command(proof_path: PathBuf, output: PathBuf) -> Result<()> {
+    println!("ZK-PSI Proof Export");
+
+    let mut file = File::open(&proof_path)
+        .with_context(|| format!("Failed to read proof from {:?}", proof_path))?;
+    let envelope = ProofEnvelope::read(&mut BufReader::new(&mut file))
+        .with_context(|| format!("Invalid proof file {:?}", proof_path))?;
+
+    let export = ProofJson::from(&envelope);
+    std::fs::write(&output, export.to_json())
+        .with_context(|| format!("Failed to write the export to {:?}", output))?;
+    println!(
+        "Exported {} proof ({} public inputs) to {:?}",
+        export.circuit,
+        export.public_inputs.len(),
+        output
+    );
+    Ok(())
+}
+
+#[cfg(feature = "submit")]
+fn submit_command(
+    proof_path: PathBuf,
+    endpoint: String,
+    poll_interval: u64,
+    timeout: u64,
+    no_wait: bool,
+) -> Result<()> {
+    println!("ZK-PSI Proof Submission");
+
+    let json = std::fs::read_to_string(&proof_path)
+        .with_context(|| format!("Failed to read proof from {:?}", proof_path))?;
+    let proof = ProofJson::from_json(&json).with_context(|| {
+        format!(
+            "Invalid proof export {:?}, write it with export-json",
+            proof_path
+        )
+    })?;
+
+    let client = SubmissionClient::new(&endpoint)?
+        .with_poll_interval(Duration::from_secs(poll_interval))
+        .with_timeout(Duration::from_secs(timeout));
+    let id = client
+        .submit(&proof)
+        .with_context(|| format!("Failed to submit the proof to {}", endpoint))?;
+    println!("Submitted {} proof with id {}", proof.circuit, id);
+    if no_wait {
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    let mut last = None;
+    let status = client.wait(&id, |status| {
+        if last != Some(status) {
+            println!("Status: {:?} ({:.2?})", status, start.elapsed());
+            last = Some(status);
+        }
+    })?;
+    anyhow::ensure!(
+        status == SubmissionStatus::Finalized,
+        "The proof was rejected"
+    );
+    println!("The proof is finalized");
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
@@ -220,14 +1405,115 @@ fn main() -> Result<()> {
             output,
             pk,
             params,
-            public_inputs_file,
-        } => prove_command(set_a, set_b, output, pk, params, public_inputs_file),
+            salt_a,
+            salt_b,
+            semantics,
+            min_intersection,
+        } => prove_command(
+            set_a,
+            set_b,
+            output,
+            pk,
+            params,
+            salt_a,
+            salt_b,
+            semantics,
+            min_intersection,
+        ),
 
         Commands::Verify {
             proof,
-            public_inputs,
             vk,
             params,
-        } => verify_command(proof, public_inputs, vk, params),
+            commitment_a,
+            commitment_b,
+            semantics,
+            min_intersection,
+        } => verify_command(
+            proof,
+            vk,
+            params,
+            commitment_a,
+            commitment_b,
+            semantics,
+            min_intersection,
+        ),
+
+        Commands::Commit {
+            set,
+            salt,
+            capacity,
+        } => commit_command(set, salt, capacity),
+
+        Commands::Disjoint { action } => match action {
+            DisjointAction::Prove {
+                set,
+                blocklist,
+                salt,
+                output,
+                pk,
+                params,
+            } => disjoint_prove_command(set, blocklist, salt, output, pk, params),
+
+            DisjointAction::Verify {
+                proof,
+                blocklist,
+                commitment,
+                vk,
+                params,
+            } => disjoint_verify_command(proof, blocklist, commitment, vk, params),
+        },
+
+        Commands::Subset { action } => match action {
+            SubsetAction::Prove {
+                set_a,
+                set_b,
+                salt_a,
+                salt_b,
+                semantics,
+                output,
+                pk,
+                params,
+            } => subset_prove_command(set_a, set_b, salt_a, salt_b, semantics, output, pk, params),
+
+            SubsetAction::Verify {
+                proof,
+                commitment_a,
+                commitment_b,
+                semantics,
+                vk,
+                params,
+            } => subset_verify_command(proof, commitment_a, commitment_b, semantics, vk, params),
+        },
+
+        Commands::Multi { action } => match action {
+            MultiAction::Prove {
+                sets,
+                min_sets,
+                salts,
+                output,
+                pk,
+                params,
+            } => multi_prove_command(sets, min_sets, salts, output, pk, params),
+
+            MultiAction::Verify {
+                proof,
+                min_sets,
+                commitments,
+                vk,
+                params,
+            } => multi_verify_command(proof, min_sets, commitments, vk, params),
+        },
+
+        Commands::ExportJson { proof, output } => export_json_command(proof, output),
+
+        #[cfg(feature = "submit")]
+        Commands::Submit {
+            proof,
+            endpoint,
+            poll_interval,
+            timeout,
+            no_wait,
+        } => submit_command(proof, endpoint, poll_interval, timeout, no_wait),
     }
 }
@@ -1,16 +1,82 @@
 use anyhow::Result;
-use clap::Parser;
-use std::fs;
+use clap::{Parser, ValueEnum};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
-use zk_psi_verifier::setup_eq;
+use zk_psi_verifier::{
+    IntersectionSemantics, KeyCircuit, KeyConfig, MAX_K, MAX_PARTIES, MAX_SET_SIZE, SetCapacity,
+    params_digest, write_params, write_proving_key, write_verifying_key,
+};
+
+/// Circuit to generate keys for
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CircuitKind {
+    /// Intersection size proofs ('prove' and 'verify')
+    Psi,
+    /// Disjointness proofs ('disjoint'), with the set as A and the blocklist
+    /// as B
+    Disjoint,
+    /// Subset proofs ('subset')
+    Subset,
+    /// Multi-party proofs ('multi'), with every set of up to --max-a elements
+    Multi,
+}
+
+/// How repeated elements are counted
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Semantics {
+    /// Sets without duplicates
+    Set,
+    /// Duplicates count min(multiplicity in A, multiplicity in B) times
+    Multiset,
+}
+
+impl From<Semantics> for IntersectionSemantics {
+    fn from(semantics: Semantics) -> Self {
+        match semantics {
+            Semantics::Set => IntersectionSemantics::Set,
+            Semantics::Multiset => IntersectionSemantics::Multiset,
+        }
+    }
+}
 
 #[derive(Parser, Debug)]
 #[command(name = "setup")]
 #[command(about = "Generate and save proving/verifying keys for PSI circuit", long_about = None)]
 struct Args {
-    /// Circuit size parameter (k). Determines the number of rows: 2^k
-    #[arg(short, long, default_value = "12")]
-    k: u32,
+    /// Circuit size parameter (k). Determines the number of rows: 2^k.
+    /// Defaults to the smallest k that holds the maximum set sizes
+    #[arg(short, long)]
+    k: Option<u32>,
+
+    /// Maximum number of elements in set A
+    #[arg(long, default_value_t = MAX_SET_SIZE)]
+    max_a: usize,
+
+    /// Maximum number of elements in set B
+    #[arg(long, default_value_t = MAX_SET_SIZE)]
+    max_b: usize,
+
+    /// Intersection semantics of the proofs
+    #[arg(long, value_enum, default_value = "set")]
+    semantics: Semantics,
+
+    /// Generate keys for threshold proofs ('prove --min-intersection')
+    #[arg(long)]
+    threshold: bool,
+
+    /// Circuit the keys are for
+    #[arg(long, value_enum, default_value = "psi")]
+    circuit: CircuitKind,
+
+    /// Number of sets of multi-party proofs
+    #[arg(long, default_value_t = 3)]
+    parties: usize,
+
+    /// Minimum number of sets an element is counted in by multi-party proofs
+    /// ('multi --min-sets'); all sets if omitted
+    #[arg(long)]
+    min_sets: Option<usize>,
 
     /// Output directory for generated keys
     #[arg(short, long, default_value = "./keys")]
@@ -20,40 +86,106 @@ struct Args {
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    let capacity = SetCapacity::new(args.max_a, args.max_b)?;
+
+    anyhow::ensure!(
+        !args.threshold || matches!(args.circuit, CircuitKind::Psi),
+        "--threshold only applies to --circuit psi"
+    );
+    if let CircuitKind::Multi = args.circuit {
+        anyhow::ensure!(
+            (2..=MAX_PARTIES).contains(&args.parties),
+            "--parties must be between 2 and {}",
+            MAX_PARTIES
+        );
+        anyhow::ensure!(
+            args.min_sets
+                .is_none_or(|t| (1..=args.parties).contains(&t)),
+            "--min-sets must be between 1 and --parties ({})",
+            args.parties
+        );
+    }
+
+    let circuit = match args.circuit {
+        CircuitKind::Psi => KeyCircuit::Psi,
+        CircuitKind::Disjoint => KeyCircuit::Disjoint,
+        CircuitKind::Subset => KeyCircuit::Subset,
+        CircuitKind::Multi => KeyCircuit::Multi {
+            parties: args.parties,
+            threshold: args.min_sets.unwrap_or(args.parties),
+        },
+    };
+    let mut config = KeyConfig::new(0, capacity)
+        .with_circuit(circuit)
+        .with_semantics(args.semantics.into())
+        .with_threshold(args.threshold);
+    let required_k = config
+        .min_k()
+        .map_err(|e| anyhow::anyhow!("Failed to lay out the circuit: {:?}", e))?;
+    println!(
+        "Sets of up to {} and {} elements need k >= {}",
+        args.max_a, args.max_b, required_k
+    );
+
+    let k = args.k.unwrap_or(required_k);
+    config.k = k;
+    anyhow::ensure!(
+        k >= required_k,
+        "k={} is too small for these set sizes, use at least k={}",
+        k,
+        required_k
+    );
+    anyhow::ensure!(
+        k <= MAX_K,
+        "k={} is above the maximum of {} that params can be loaded with; use smaller maximum \
+         set sizes or fewer parties",
+        k,
+        MAX_K
+    );
+
     println!(
         "Generating setup with k={} (2^{} = {} rows)",
-        args.k,
-        args.k,
-        1u64 << args.k
+        k,
+        k,
+        1u64 << k
     );
 
     // Create output directory if it doesn't exist
     fs::create_dir_all(&args.output_dir)?;
 
     // Generate keys
-    let (_params, _pk, _vk) =
-        setup_eq(args.k).map_err(|e| anyhow::anyhow!("Failed to generate keys: {:?}", e))?;
+    let (params, pk, vk) = config
+        .setup()
+        .map_err(|e| anyhow::anyhow!("Failed to generate keys: {:?}", e))?;
 
     println!("Keys generated successfully");
 
-    // Save params (just save k value for reconstruction)
     let params_path = args.output_dir.join("params.bin");
-    let params_bytes = bincode::serialize(&args.k)?;
-    fs::write(&params_path, params_bytes)?;
+    let mut writer = BufWriter::new(File::create(&params_path)?);
+    write_params(&mut writer, &params)?;
+    writer.flush()?;
     println!("Saved params to {:?}", params_path);
+    let digest: String = params_digest(&params)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    println!("Params digest: {}", digest);
 
+    // halo2 cannot serialize keys: the key files pin the circuit and the
+    // digests of the params and verifying key, and loading them regenerates
+    // and checks the keys
     let pk_path = args.output_dir.join("proving_key.bin");
-    fs::write(&pk_path, b"PK_PLACEHOLDER")?;
-    println!("Proving key generated (not serialized - regenerate when needed)");
+    write_proving_key(&mut File::create(&pk_path)?, &params, &config, &pk)?;
+    println!("Saved proving key to {:?}", pk_path);
 
     let vk_path = args.output_dir.join("verifying_key.bin");
-    fs::write(&vk_path, b"VK_PLACEHOLDER")?;
-    println!("Verifying key generated (not serialized - regenerate when needed)");
+    write_verifying_key(&mut File::create(&vk_path)?, &params, &config, &vk)?;
+    println!("Saved verifying key to {:?}", vk_path);
 
     println!("\nSetup complete! Keys saved to {:?}", args.output_dir);
     println!("\nNext steps:");
-    println!("  1. Use 'cli prove' to generate proofs");
-    println!("  2. Use 'cli verify' to verify proofs");
+    println!("  1. Use 'cli prove' (or disjoint/subset/multi prove) to generate proofs");
+    println!("  2. Use 'cli verify' (or disjoint/subset/multi verify) to verify proofs");
 
     Ok(())
 }
@@ -0,0 +1,403 @@
+//! Chunked PSI proofs that chain per-chunk proofs over a large comparison grid
+
+use crate::circuits::lookup::{element_bound, element_key};
+use crate::proof::prove;
+use crate::{
+    CircuitError, ELEMENT_BITS, PsiChunkCircuit, SetCapacity, commit_set_with_capacity,
+    poseidon_hash_pair, verify_proof,
+};
+use ff::PrimeField;
+use halo2_proofs::plonk::{Error, ProvingKey, VerifyingKey};
+use pasta_curves::{EqAffine, Fp};
+use std::collections::HashSet;
+
+/// Next value of the hash chain of a [`ChunkedPsiProof`] after the chunk
+/// comparing the chunks of A and B with the given commitments
+pub fn chunk_chain_step(chain: Fp, commitment_a: Fp, commitment_b: Fp) -> Fp {
+    poseidon_hash_pair(poseidon_hash_pair(chain, commitment_a), commitment_b)
+}
+
+/// Hash chain of a [`ChunkedPsiProof`] over the chunks with the given
+/// commitments, comparing every chunk of A with every chunk of B in order
+///
+/// Verifiers recompute it from the published chunk commitments (see
+/// [`commit_chunks`]) to tie a chunked proof to the committed sets.
+pub fn chunk_chain(commitments_a: &[Fp], commitments_b: &[Fp]) -> Fp {
+    commitments_a
+        .iter()
+        .flat_map(|a| commitments_b.iter().map(move |b| (a, b)))
+        .fold(Fp::zero(), |chain, (a, b)| chunk_chain_step(chain, *a, *b))
+}
+
+/// Commitment to the bound before or after a chunk of a [`ChunkedPsiProof`],
+/// blinded with the salt of the chunk it follows
+///
+/// The bound is one more than the last element of the chunk, and the first
+/// chunk of a set starts from `chunk_bound(0, 0)`.
+pub fn chunk_bound(bound: Fp, salt: Fp) -> Fp {
+    poseidon_hash_pair(bound, salt)
+}
+
+/// Bound after a sorted chunk starting from `lower`
+pub(crate) fn upper_bound(chunk: &[Fp], lower: Fp) -> Fp {
+    chunk.last().map_or(lower, |last| last + Fp::one())
+}
+
+/// Commitments to the chunks of `set` of up to `capacity` elements, salted
+/// with one salt per chunk
+///
+/// The set is sorted in ascending order before it is split, as
+/// [`ChunkedPsiProof::prove`] does.
+pub fn commit_chunks(set: &[Fp], salts: &[Fp], capacity: usize) -> Vec<Fp> {
+    let set = sorted_set(set);
+    let chunks = split_chunks(&set, capacity);
+    assert_eq!(chunks.len(), salts.len(), "Every chunk needs a salt");
+
+    chunks
+        .iter()
+        .zip(salts)
+        .map(|(chunk, salt)| commit_set_with_capacity(chunk, *salt, capacity))
+        .collect()
+}
+
+/// The elements of `set` in ascending order
+pub(crate) fn sorted_set(set: &[Fp]) -> Vec<Fp> {
+    let mut sorted = set.to_vec();
+    sorted.sort_by_key(element_key);
+    sorted
+}
+
+/// Chunks of `set` of up to `capacity` elements; an empty set is one empty
+/// chunk
+fn split_chunks(set: &[Fp], capacity: usize) -> Vec<&[Fp]> {
+    if set.is_empty() {
+        vec![set]
+    } else {
+        set.chunks(capacity).collect()
+    }
+}
+
+/// Lower bounds of sorted chunks with the salts of their commitments, each
+/// chunk starting from the bound after the one before it
+fn chunk_lower_bounds(chunks: &[&[Fp]], salts: &[Fp]) -> Vec<(Fp, Fp)> {
+    let mut lower = (Fp::zero(), Fp::zero());
+    chunks
+        .iter()
+        .zip(salts)
+        .map(|(chunk, salt)| {
+            let current = lower;
+            lower = (upper_bound(chunk, lower.0), *salt);
+            current
+        })
+        .collect()
+}
+
+/// Proof of one chunk of a [`ChunkedPsiProof`] with its public inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsiChunkProof {
+    /// Running count before the chunk
+    pub count_in: u64,
+    /// Running count after the chunk
+    pub count_out: u64,
+    /// Hash chain before the chunk
+    pub chain_in: Fp,
+    /// Hash chain after the chunk
+    pub chain_out: Fp,
+    /// Commitment to the lower bound of the chunk of A
+    pub bound_a_in: Fp,
+    /// Commitment to the bound after the chunk of A
+    pub bound_a_out: Fp,
+    /// Commitment to the lower bound of the chunk of B
+    pub bound_b_in: Fp,
+    /// Commitment to the bound after the chunk of B
+    pub bound_b_out: Fp,
+    /// Proof of the [`PsiChunkCircuit`]
+    pub proof: Vec<u8>,
+}
+
+impl PsiChunkProof {
+    /// Public inputs of the chunk proof, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        vec![
+            Fp::from(self.count_in),
+            Fp::from(self.count_out),
+            self.chain_in,
+            self.chain_out,
+            self.bound_a_in,
+            self.bound_a_out,
+            self.bound_b_in,
+            self.bound_b_out,
+        ]
+    }
+}
+
+/// Reasons a [`ChunkedPsiProof`] cannot be created or is rejected
+#[derive(Debug)]
+pub enum ChunkedPsiError {
+    /// The element at `index` of set `set` (`'A'` or `'B'`) repeats an
+    /// earlier element
+    DuplicateElement {
+        /// Set holding the duplicate
+        set: char,
+        /// Index of the repeated element in the set
+        index: usize,
+    },
+    /// The chunk circuits cannot be built, e.g. for a missing salt
+    Circuit(CircuitError),
+    /// The element at `index` of set `set` is not below `2^ELEMENT_BITS`
+    ElementOutOfRange {
+        /// Set holding the element
+        set: char,
+        /// Index of the element in the set
+        index: usize,
+    },
+    /// The proof has no chunks
+    NoChunks,
+    /// The chunk at this index does not continue the running count and hash
+    /// chain of the previous one
+    Discontinuity(usize),
+    /// The final hash chain differs from the expected one
+    ChainMismatch,
+    /// The chunk at this index does not start from the bounds after the
+    /// previous chunks of its sets
+    Unordered(usize),
+    /// Creating or verifying the proof of the chunk at `chunk` failed
+    Proof {
+        /// Index of the chunk
+        chunk: usize,
+        /// Error of the proof system
+        error: Error,
+    },
+}
+
+impl std::fmt::Display for ChunkedPsiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateElement { set, index } => write!(
+                f,
+                "Element {} of set {} repeats an earlier element",
+                index, set
+            ),
+            Self::Circuit(error) => write!(f, "{}", error),
+            Self::ElementOutOfRange { set, index } => write!(
+                f,
+                "Element {} of set {} is not below 2^{}",
+                index, set, ELEMENT_BITS
+            ),
+            Self::NoChunks => write!(f, "The chunked proof has no chunks"),
+            Self::Discontinuity(chunk) => write!(
+                f,
+                "Chunk {} does not continue the count and chain of the previous chunk",
+                chunk
+            ),
+            Self::ChainMismatch => {
+                write!(f, "The hash chain of the chunks is not the expected one")
+            }
+            Self::Unordered(chunk) => write!(
+                f,
+                "Chunk {} does not start above the previous chunks of its sets",
+                chunk
+            ),
+            Self::Proof { chunk, error } => write!(f, "Proof of chunk {} failed: {}", chunk, error),
+        }
+    }
+}
+
+impl std::error::Error for ChunkedPsiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Circuit(error) => Some(error),
+            Self::Proof { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<CircuitError> for ChunkedPsiError {
+    fn from(error: CircuitError) -> Self {
+        Self::Circuit(error)
+    }
+}
+
+/// Intersection size of two sets beyond one circuit's capacity, proven chunk
+/// by chunk
+///
+/// Both sets are sorted, then A is split into chunks of `capacity.max_a`
+/// elements and B into chunks of `capacity.max_b`, and every chunk of A is
+/// compared with every chunk of B by a [`PsiChunkCircuit`]. The running count
+/// and hash chain of each chunk continue from the previous one, so the last
+/// count is the intersection size and the last chain binds the commitments
+/// to all chunks.
+///
+/// Each chunk circuit also proves its chunks ascend strictly from committed
+/// lower bounds and commits to the bounds after them. The verifier links
+/// every chunk's lower bounds to the bounds after the previous chunks of its
+/// sets, so the elements ascend across all chunks of a set and none can be
+/// counted twice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedPsiProof {
+    /// Maximum chunk sizes, which fix the chunk circuit
+    pub capacity: SetCapacity,
+    /// Proofs of the chunks, every chunk of A against every chunk of B
+    pub chunks: Vec<PsiChunkProof>,
+}
+
+impl ChunkedPsiProof {
+    /// Prove the intersection size of `set_a` and `set_b` chunk by chunk
+    ///
+    /// `salts_a` and `salts_b` hold one salt per chunk of each sorted set
+    /// (see [`commit_chunks`]), and the keys must come from [`setup_chunk`]
+    /// for `capacity`. Fails with [`ChunkedPsiError::DuplicateElement`] if a
+    /// set holds an element twice, and with
+    /// [`ChunkedPsiError::ElementOutOfRange`] if an element is not below
+    /// `2^ELEMENT_BITS`.
+    ///
+    /// [`setup_chunk`]: crate::setup_chunk
+    #[allow(clippy::too_many_arguments)]
+    pub fn prove(
+        params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+        pk: &ProvingKey<EqAffine>,
+        capacity: SetCapacity,
+        set_a: &[Fp],
+        set_b: &[Fp],
+        salts_a: &[Fp],
+        salts_b: &[Fp],
+    ) -> Result<Self, ChunkedPsiError> {
+        for (name, set) in [('A', set_a), ('B', set_b)] {
+            let mut seen = HashSet::new();
+            if let Some(index) = set.iter().position(|x| !seen.insert(x.to_repr())) {
+                return Err(ChunkedPsiError::DuplicateElement { set: name, index });
+            }
+            let bound = element_key(&element_bound());
+            if let Some(index) = set.iter().position(|x| element_key(x) >= bound) {
+                return Err(ChunkedPsiError::ElementOutOfRange { set: name, index });
+            }
+        }
+
+        let (set_a, set_b) = (sorted_set(set_a), sorted_set(set_b));
+        let chunks_a = split_chunks(&set_a, capacity.max_a);
+        let chunks_b = split_chunks(&set_b, capacity.max_b);
+        for (chunks, salts) in [(&chunks_a, salts_a), (&chunks_b, salts_b)] {
+            if chunks.len() != salts.len() {
+                return Err(ChunkedPsiError::Circuit(CircuitError::LengthMismatch {
+                    items: "salts",
+                    expected: chunks.len(),
+                    found: salts.len(),
+                }));
+            }
+        }
+
+        let lower_a = chunk_lower_bounds(&chunks_a, salts_a);
+        let lower_b = chunk_lower_bounds(&chunks_b, salts_b);
+
+        let mut count = 0;
+        let mut chain = Fp::zero();
+        let mut chunks = Vec::with_capacity(chunks_a.len() * chunks_b.len());
+        for ((chunk_a, salt_a), lower_a) in chunks_a.iter().zip(salts_a).zip(&lower_a) {
+            for ((chunk_b, salt_b), lower_b) in chunks_b.iter().zip(salts_b).zip(&lower_b) {
+                let circuit = PsiChunkCircuit::new(
+                    capacity,
+                    chunk_a.to_vec(),
+                    chunk_b.to_vec(),
+                    *salt_a,
+                    *salt_b,
+                    count,
+                    chain,
+                )?
+                .with_lower_bounds(*lower_a, *lower_b);
+                let (count_out, chain_out) = (circuit.count_out(), circuit.chain_out());
+                let [bound_a_in, bound_a_out, bound_b_in, bound_b_out] =
+                    circuit.bound_commitments();
+                let public_inputs = circuit.public_inputs();
+                let proof = prove(params, pk, circuit, &public_inputs).map_err(|error| {
+                    ChunkedPsiError::Proof {
+                        chunk: chunks.len(),
+                        error,
+                    }
+                })?;
+
+                chunks.push(PsiChunkProof {
+                    count_in: count,
+                    count_out,
+                    chain_in: chain,
+                    chain_out,
+                    bound_a_in,
+                    bound_a_out,
+                    bound_b_in,
+                    bound_b_out,
+                    proof,
+                });
+                (count, chain) = (count_out, chain_out);
+            }
+        }
+
+        Ok(Self { capacity, chunks })
+    }
+
+    /// The intersection size claimed by the last chunk
+    pub fn count(&self) -> u64 {
+        self.chunks.last().map_or(0, |chunk| chunk.count_out)
+    }
+
+    /// Verify every chunk proof, the continuity of counts and chain and the
+    /// order of the chunks, and return the intersection size
+    ///
+    /// `commitments_a` and `commitments_b` are the published chunk
+    /// commitments of both sets (see [`commit_chunks`]), from which the
+    /// expected hash chain is recomputed with [`chunk_chain`]. `vk` must come
+    /// from [`setup_chunk`] for the proof's capacity. A broken chain or
+    /// unlinked bounds fail before any proof is checked.
+    ///
+    /// [`setup_chunk`]: crate::setup_chunk
+    pub fn verify(
+        &self,
+        params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+        vk: &VerifyingKey<EqAffine>,
+        commitments_a: &[Fp],
+        commitments_b: &[Fp],
+    ) -> Result<u64, ChunkedPsiError> {
+        let Some(last) = self.chunks.last() else {
+            return Err(ChunkedPsiError::NoChunks);
+        };
+
+        // Each chunk continues from the previous one, the first from zero
+        let mut expected = (0, Fp::zero());
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if (chunk.count_in, chunk.chain_in) != expected {
+                return Err(ChunkedPsiError::Discontinuity(i));
+            }
+            expected = (chunk.count_out, chunk.chain_out);
+        }
+        let columns = commitments_b.len();
+        if self.chunks.len() != commitments_a.len() * columns
+            || last.chain_out != chunk_chain(commitments_a, commitments_b)
+        {
+            return Err(ChunkedPsiError::ChainMismatch);
+        }
+
+        // A chunk of A starts from the bound after the chunk of A before it
+        // in the same column of the grid, and a chunk of B from the bound
+        // after the chunk of B before it in the same row
+        let start = chunk_bound(Fp::zero(), Fp::zero());
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let lower_a = match i.checked_sub(columns) {
+                Some(above) => self.chunks[above].bound_a_out,
+                None => start,
+            };
+            let lower_b = match i % columns {
+                0 => start,
+                _ => self.chunks[i - 1].bound_b_out,
+            };
+            if (chunk.bound_a_in, chunk.bound_b_in) != (lower_a, lower_b) {
+                return Err(ChunkedPsiError::Unordered(i));
+            }
+        }
+
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            verify_proof(params, vk, &chunk.proof, &chunk.public_inputs())
+                .map_err(|error| ChunkedPsiError::Proof { chunk: i, error })?;
+        }
+
+        Ok(last.count_out)
+    }
+}
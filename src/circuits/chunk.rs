@@ -0,0 +1,356 @@
+//! Circuit over one chunk of the comparison grid of a chunked proof
+
+use crate::chunked::upper_bound;
+use crate::{
+    AssignedSet, ByteRangeConfig, CHUNK_BOUND_A_IN_ROW, CHUNK_BOUND_A_OUT_ROW,
+    CHUNK_BOUND_B_IN_ROW, CHUNK_BOUND_B_OUT_ROW, CHUNK_CHAIN_IN_ROW, CHUNK_CHAIN_OUT_ROW,
+    CHUNK_COUNT_IN_ROW, CHUNK_COUNT_OUT_ROW, CircuitError, ELEMENT_BITS, PsiCircuit, PsiConfig,
+    SetCapacity, chunk_bound, chunk_chain_step,
+};
+use halo2_proofs::circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error, Selector};
+use halo2_proofs::poly::Rotation;
+use pasta_curves::Fp;
+
+/// Configuration of [`PsiChunkCircuit`]: the PSI columns plus the gate
+/// ordering the elements of each chunk above a committed lower bound
+///
+/// The bound before a slot sits in the row above it. An active element must
+/// be at least that bound, shown by range checking the step `active * (value
+/// - bound_prev)`, and raises the bound to `value + 1`; padding slots keep it.
+/// Elements are range checked too, so the bounds never exceed
+/// `2^ELEMENT_BITS` and the steps cannot wrap around the field.
+#[derive(Debug, Clone)]
+pub struct PsiChunkConfig {
+    psi: PsiConfig,
+    /// Selector for the ordering gate of every slot of a chunk
+    q_order: Selector,
+    /// Byte table for the range checks of the elements and ordering steps
+    bytes: ByteRangeConfig,
+}
+
+impl PsiChunkConfig {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let psi = PsiConfig::configure(meta);
+        let q_order = meta.selector();
+        let bytes = ByteRangeConfig::configure(meta, psi.sum);
+
+        // Ordering columns: value and active in the set A slot columns, the
+        // step in b_picks and the bound in weighted
+        meta.create_gate("chunk order", |meta| {
+            let q = meta.query_selector(q_order);
+            let value = meta.query_advice(psi.set_a, Rotation::cur());
+            let active = meta.query_advice(psi.a_active, Rotation::cur());
+            let step = meta.query_advice(psi.b_picks, Rotation::cur());
+            let bound_prev = meta.query_advice(psi.weighted, Rotation::prev());
+            let bound = meta.query_advice(psi.weighted, Rotation::cur());
+
+            vec![
+                q.clone() * (step.clone() - active.clone() * (value - bound_prev.clone())),
+                q * (bound - bound_prev - step - active),
+            ]
+        });
+
+        Self {
+            psi,
+            q_order,
+            bytes,
+        }
+    }
+
+    /// Constrain the active elements of `set` to ascend strictly from
+    /// `lower`, and return the bound after them: one more than the last
+    /// active element, or `lower` if there is none
+    pub fn assign_order(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        set: &AssignedSet,
+        lower: &AssignedCell<Fp, Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let psi = &self.psi;
+        let (bound, steps) = layouter.assign_region(
+            || "chunk order",
+            |mut region| {
+                let mut bound = lower.copy_advice(|| "bound 0", &mut region, psi.weighted, 0)?;
+                let mut steps = Vec::with_capacity(set.slots.len());
+
+                for (i, slot) in set.slots.iter().enumerate() {
+                    let offset = i + 1;
+                    self.q_order.enable(&mut region, offset)?;
+
+                    let value = slot.value.copy_advice(
+                        || format!("value {}", i),
+                        &mut region,
+                        psi.set_a,
+                        offset,
+                    )?;
+                    let active = slot.active.copy_advice(
+                        || format!("active {}", i),
+                        &mut region,
+                        psi.a_active,
+                        offset,
+                    )?;
+                    let step = active.value().copied() * (value.value().copied() - bound.value());
+                    steps.push(region.assign_advice(
+                        || format!("step {}", i),
+                        psi.b_picks,
+                        offset,
+                        || step,
+                    )?);
+                    bound = region.assign_advice(
+                        || format!("bound {}", offset),
+                        psi.weighted,
+                        offset,
+                        || bound.value().copied() + step + active.value(),
+                    )?;
+                }
+                Ok((bound, steps))
+            },
+        )?;
+
+        for (i, (slot, step)) in set.slots.iter().zip(&steps).enumerate() {
+            self.bytes.assert_bytes(
+                layouter.namespace(|| format!("element {}", i)),
+                &slot.value,
+                ELEMENT_BITS / 8,
+            )?;
+            self.bytes.assert_bytes(
+                layouter.namespace(|| format!("step {}", i)),
+                step,
+                ELEMENT_BITS / 8,
+            )?;
+        }
+
+        Ok(bound)
+    }
+}
+
+/// PSI circuit over one chunk of the comparison grid of a [`ChunkedPsiProof`]
+///
+/// The circuit compares a chunk of A with a chunk of B under set semantics
+/// and adds their intersection size to a public running count. It also
+/// extends a public hash chain with the commitments to both chunks,
+/// `chain_out = H(H(chain_in, commitment A), commitment B)` (see
+/// [`chunk_chain_step`]), so the last chunk's chain binds every chunk in
+/// order. The public inputs are the running count and the chain before and
+/// after the chunk, from [`CHUNK_COUNT_IN_ROW`] to [`CHUNK_CHAIN_OUT_ROW`].
+///
+/// Each chunk must hold its elements in strictly ascending order, all at
+/// least a private lower bound and below `2^ELEMENT_BITS`. The circuit
+/// exposes commitments to the lower bounds and to the bounds after the
+/// chunks, from [`CHUNK_BOUND_A_IN_ROW`] to [`CHUNK_BOUND_B_OUT_ROW`], so a
+/// verifier linking each chunk's lower bound to the bound after the previous
+/// chunk of its set knows no element appears in two chunks.
+///
+/// [`ChunkedPsiProof`]: crate::ChunkedPsiProof
+#[derive(Debug, Clone, Default)]
+pub struct PsiChunkCircuit {
+    /// The two chunks and their salts; `psi.intersection_size` is not used
+    pub psi: PsiCircuit,
+    /// Running count before this chunk (public input)
+    pub count_in: u64,
+    /// Hash chain before this chunk (public input)
+    pub chain_in: Fp,
+    /// Lower bound of the chunk of A and the salt of its commitment
+    /// (private): the bound after the previous chunk of A with that chunk's
+    /// salt, or zero and zero for the first chunk
+    pub lower_a: (Fp, Fp),
+    /// Lower bound of the chunk of B and the salt of its commitment (private)
+    pub lower_b: (Fp, Fp),
+}
+
+impl PsiChunkCircuit {
+    /// Create a chunk circuit comparing `chunk_a` with `chunk_b`, continuing
+    /// from `count_in` and `chain_in`, for the first chunks of both sets
+    pub fn new(
+        capacity: SetCapacity,
+        chunk_a: Vec<Fp>,
+        chunk_b: Vec<Fp>,
+        salt_a: Fp,
+        salt_b: Fp,
+        count_in: u64,
+        chain_in: Fp,
+    ) -> Result<Self, CircuitError> {
+        let psi = PsiCircuit::new_with_capacity(capacity, chunk_a, chunk_b, 0, salt_a, salt_b)?;
+        Ok(Self {
+            psi,
+            count_in,
+            chain_in,
+            lower_a: (Fp::zero(), Fp::zero()),
+            lower_b: (Fp::zero(), Fp::zero()),
+        })
+    }
+
+    /// Start the chunks above the bounds after the previous chunks of their
+    /// sets, given with the salts of those chunks
+    pub fn with_lower_bounds(mut self, lower_a: (Fp, Fp), lower_b: (Fp, Fp)) -> Self {
+        self.lower_a = lower_a;
+        self.lower_b = lower_b;
+        self
+    }
+
+    /// A chunk circuit without witnesses for chunks of up to `capacity`
+    pub fn empty(capacity: SetCapacity) -> Self {
+        Self {
+            psi: PsiCircuit::empty(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Running count after this chunk (for witness generation)
+    pub fn count_out(&self) -> u64 {
+        self.count_in + self.psi.compute_intersection_size()
+    }
+
+    /// Hash chain after this chunk (for witness generation)
+    pub fn chain_out(&self) -> Fp {
+        chunk_chain_step(
+            self.chain_in,
+            self.psi.commitment_a(),
+            self.psi.commitment_b(),
+        )
+    }
+
+    /// Bounds after the chunks of A and B (for witness generation)
+    pub fn upper_bounds(&self) -> (Fp, Fp) {
+        (
+            upper_bound(&self.psi.set_a, self.lower_a.0),
+            upper_bound(&self.psi.set_b, self.lower_b.0),
+        )
+    }
+
+    /// Commitments to the bounds before and after the chunks of A and B, in
+    /// instance column order
+    pub fn bound_commitments(&self) -> [Fp; 4] {
+        let (upper_a, upper_b) = self.upper_bounds();
+        [
+            chunk_bound(self.lower_a.0, self.lower_a.1),
+            chunk_bound(upper_a, self.psi.salt_a),
+            chunk_bound(self.lower_b.0, self.lower_b.1),
+            chunk_bound(upper_b, self.psi.salt_b),
+        ]
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        let mut inputs = vec![
+            Fp::from(self.count_in),
+            Fp::from(self.count_out()),
+            self.chain_in,
+            self.chain_out(),
+        ];
+        inputs.extend(self.bound_commitments());
+        inputs
+    }
+}
+
+impl Circuit<Fp> for PsiChunkCircuit {
+    type Config = PsiChunkConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.psi.capacity)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiChunkConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        config.bytes.load(layouter.namespace(|| "bytes"))?;
+        let psi = &config.psi;
+
+        let (count_in, chain_in, lower_a, lower_b) = layouter.assign_region(
+            || "chunk inputs",
+            |mut region| {
+                let count = region.assign_advice_from_instance(
+                    || "count in",
+                    psi.instance,
+                    CHUNK_COUNT_IN_ROW,
+                    psi.prev_sum,
+                    0,
+                )?;
+                let chain = region.assign_advice_from_instance(
+                    || "chain in",
+                    psi.instance,
+                    CHUNK_CHAIN_IN_ROW,
+                    psi.salt,
+                    0,
+                )?;
+                let lower_a = region.assign_advice(
+                    || "lower bound A",
+                    psi.set_a,
+                    0,
+                    || Value::known(self.lower_a.0),
+                )?;
+                let lower_b = region.assign_advice(
+                    || "lower bound B",
+                    psi.set_b,
+                    0,
+                    || Value::known(self.lower_b.0),
+                )?;
+                Ok((count, chain, lower_a, lower_b))
+            },
+        )?;
+
+        let cells = self.psi.synthesize_intersection_from(
+            psi,
+            layouter.namespace(|| "psi"),
+            Some(&count_in),
+        )?;
+
+        let chain = psi.hash_pair(
+            layouter.namespace(|| "chain A"),
+            chain_in,
+            cells.commitment_a,
+        )?;
+        let chain = psi.hash_pair(layouter.namespace(|| "chain B"), chain, cells.commitment_b)?;
+
+        layouter.constrain_instance(cells.sum.cell(), psi.instance, CHUNK_COUNT_OUT_ROW)?;
+        layouter.constrain_instance(chain.cell(), psi.instance, CHUNK_CHAIN_OUT_ROW)?;
+
+        for (name, set, lower, salts, rows) in [
+            (
+                "A",
+                &cells.set_a,
+                lower_a,
+                (self.lower_a.1, self.psi.salt_a),
+                (CHUNK_BOUND_A_IN_ROW, CHUNK_BOUND_A_OUT_ROW),
+            ),
+            (
+                "B",
+                &cells.set_b,
+                lower_b,
+                (self.lower_b.1, self.psi.salt_b),
+                (CHUNK_BOUND_B_IN_ROW, CHUNK_BOUND_B_OUT_ROW),
+            ),
+        ] {
+            let upper = config.assign_order(
+                layouter.namespace(|| format!("order {}", name)),
+                set,
+                &lower,
+            )?;
+
+            for (bound, salt, row) in [(lower, salts.0, rows.0), (upper, salts.1, rows.1)] {
+                let salt = layouter.assign_region(
+                    || format!("bound salt {}", name),
+                    |mut region| {
+                        region.assign_advice(|| "salt", psi.salt, 0, || Value::known(salt))
+                    },
+                )?;
+                let commitment = psi.hash_pair(
+                    layouter.namespace(|| format!("bound {}", name)),
+                    bound,
+                    salt,
+                )?;
+                layouter.constrain_instance(commitment.cell(), psi.instance, row)?;
+            }
+        }
+
+        Ok(())
+    }
+}
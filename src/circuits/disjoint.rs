@@ -0,0 +1,144 @@
+//! Circuit proving a private set is disjoint from a public blocklist
+
+use crate::circuits::{check_max_size, check_set_size};
+use crate::{
+    BLOCKLIST_COMMITMENT_ROW, CircuitError, DISJOINT_SET_COMMITMENT_ROW, IntersectionSemantics,
+    PsiCircuit, PsiConfig, SetCapacity, commit_blocklist_with_capacity, commit_set_with_capacity,
+};
+use ff::Field;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use pasta_curves::Fp;
+use rand::rngs::OsRng;
+
+/// Circuit proving that a private set shares no element with a public blocklist
+///
+/// The set is laid out as set A and the blocklist as set B of the PSI grid,
+/// and the intersection size is constrained to zero instead of being exposed.
+/// The public inputs are the salted commitment to the private set and the
+/// commitment to the blocklist (see [`commit_blocklist`]). Duplicates are
+/// allowed on both sides since they cannot affect disjointness.
+///
+/// [`commit_blocklist`]: crate::commit_blocklist
+#[derive(Debug, Clone, Default)]
+pub struct DisjointnessCircuit {
+    /// Private set of hashed elements
+    pub set: Vec<Fp>,
+    /// Blinding salt for the commitment to the private set
+    pub salt: Fp,
+    /// Public blocklist of hashed elements
+    pub blocklist: Vec<Fp>,
+    /// Maximum sizes of the private set (`max_a`) and the blocklist (`max_b`)
+    pub capacity: SetCapacity,
+}
+
+impl DisjointnessCircuit {
+    /// Create a disjointness circuit with a fresh random salt
+    pub fn new(set: Vec<Fp>, blocklist: Vec<Fp>) -> Result<Self, CircuitError> {
+        Self::new_with_salt(set, blocklist, Fp::random(OsRng))
+    }
+
+    /// Create a disjointness circuit with the salt of a previously published
+    /// commitment
+    pub fn new_with_salt(set: Vec<Fp>, blocklist: Vec<Fp>, salt: Fp) -> Result<Self, CircuitError> {
+        Self::new_with_capacity(SetCapacity::default(), set, blocklist, salt)
+    }
+
+    /// Create a disjointness circuit laid out for a set of up to
+    /// `capacity.max_a` elements and a blocklist of up to `capacity.max_b`
+    /// elements
+    ///
+    /// Fails with [`CircuitError::SetTooLarge`] if the set or the blocklist
+    /// exceeds its maximum size.
+    pub fn new_with_capacity(
+        capacity: SetCapacity,
+        set: Vec<Fp>,
+        blocklist: Vec<Fp>,
+        salt: Fp,
+    ) -> Result<Self, CircuitError> {
+        check_max_size(capacity.max_a)?;
+        check_max_size(capacity.max_b)?;
+        check_set_size("set", &set, capacity.max_a)?;
+        check_set_size("blocklist", &blocklist, capacity.max_b)?;
+
+        Ok(Self {
+            set,
+            salt,
+            blocklist,
+            capacity,
+        })
+    }
+
+    /// Shape of a circuit over a set and a blocklist of up to the sizes of
+    /// `capacity`, e.g. for key generation
+    pub fn empty(capacity: SetCapacity) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    /// Whether the set and the blocklist are disjoint (for witness generation)
+    pub fn is_disjoint(&self) -> bool {
+        !self.set.iter().any(|x| self.blocklist.contains(x))
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        vec![
+            commit_set_with_capacity(&self.set, self.salt, self.capacity.max_a),
+            commit_blocklist_with_capacity(&self.blocklist, self.capacity.max_b),
+        ]
+    }
+
+    /// The PSI circuit over the set and the blocklist
+    fn psi(&self) -> PsiCircuit {
+        PsiCircuit {
+            set_a: self.set.clone(),
+            set_b: self.blocklist.clone(),
+            salt_a: self.salt,
+            semantics: IntersectionSemantics::Multiset,
+            capacity: self.capacity,
+            ..PsiCircuit::default()
+        }
+    }
+}
+
+impl Circuit<Fp> for DisjointnessCircuit {
+    type Config = PsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.capacity)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let cells = self
+            .psi()
+            .synthesize_intersection(&config, layouter.namespace(|| "psi"))?;
+
+        layouter.assign_region(
+            || "disjoint",
+            |mut region| region.constrain_constant(cells.sum.cell(), Fp::zero()),
+        )?;
+
+        layouter.constrain_instance(
+            cells.commitment_a.cell(),
+            config.instance,
+            DISJOINT_SET_COMMITMENT_ROW,
+        )?;
+        layouter.constrain_instance(
+            cells.commitment_b.cell(),
+            config.instance,
+            BLOCKLIST_COMMITMENT_ROW,
+        )
+    }
+}
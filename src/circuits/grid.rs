@@ -0,0 +1,1352 @@
+//! Comparison grid shared by the two-set circuits
+
+use crate::TALLY_BITS;
+use ff::{Field, PrimeField};
+use halo2_gadgets::poseidon::primitives::{ConstantLength, P128Pow5T3};
+use halo2_gadgets::poseidon::{Hash as PoseidonHash, Pow5Chip, Pow5Config};
+use halo2_proofs::circuit::{AssignedCell, Layouter, Region, Value};
+use halo2_proofs::plonk::{
+    Advice, Column, ConstraintSystem, Error, Expression, Instance, Selector,
+};
+use halo2_proofs::poly::Rotation;
+use pasta_curves::Fp;
+
+#[derive(Debug, Clone)]
+pub struct PsiConfig {
+    /// Advice columns for set A elements
+    pub(crate) set_a: Column<Advice>,
+    /// Advice columns for set B elements
+    pub(crate) set_b: Column<Advice>,
+    /// Advice column for set A slot flags (1 if the slot holds an element, 0 if padding)
+    pub(crate) a_active: Column<Advice>,
+    /// Advice column for set B slot flags (1 if the slot holds an element, 0 if padding)
+    pub(crate) b_active: Column<Advice>,
+    /// Advice column for set A usage flags (1 if the slot is paired in the matching)
+    pub(crate) a_used: Column<Advice>,
+    /// Advice column for set B usage flags (1 if the slot is paired in the matching)
+    pub(crate) b_used: Column<Advice>,
+    /// Advice column for match bits (1 if elements match, 0 otherwise)
+    pub(crate) match_bit: Column<Advice>,
+    /// Advice column for the inverse of (set_a - set_b), or zero when they are equal
+    pub(crate) inverse: Column<Advice>,
+    /// Advice column for pick bits (1 if the matching pairs the two slots)
+    pick: Column<Advice>,
+    /// Advice column holding a copy of the previous picks of the current A slot
+    pub(crate) a_picks_prev: Column<Advice>,
+    /// Advice column for running picks of the current A slot
+    pub(crate) a_picks: Column<Advice>,
+    /// Advice column holding a copy of the previous picks of the current B slot
+    b_picks_prev: Column<Advice>,
+    /// Advice column for running picks of the current B slot
+    pub(crate) b_picks: Column<Advice>,
+    /// Advice column holding a copy of the previous running sum
+    pub(crate) prev_sum: Column<Advice>,
+    /// Advice column for running sum of matches
+    pub(crate) sum: Column<Advice>,
+    /// Advice column for the private values of set B (zero for set A)
+    pub(crate) weight: Column<Advice>,
+    /// Advice column holding a copy of the previous weighted sum
+    prev_weighted: Column<Advice>,
+    /// Advice column for running sum of the values of matching B elements
+    pub(crate) weighted: Column<Advice>,
+    /// Selector for set A slot gates
+    q_slot_a: Selector,
+    /// Selector for set B slot gates
+    q_slot_b: Selector,
+    /// Selector for the "active slots form a prefix" gate on set A
+    q_prefix_a: Selector,
+    /// Selector for the "active slots form a prefix" gate on set B
+    q_prefix_b: Selector,
+    /// Selector for equality check gates
+    q_equality: Selector,
+    /// Selector for matching gates
+    q_matching: Selector,
+    /// Selector for sum gates
+    q_sum: Selector,
+    /// Selector for the duplicate rejection gate
+    q_distinct: Selector,
+    /// Selector for the gate computing count - threshold
+    q_threshold: Selector,
+    /// Selector for the binary decomposition gate of the range check
+    q_range: Selector,
+    /// Selector for the union size gate
+    q_union: Selector,
+    /// Selector for tally rows against a set that comes earlier
+    q_tally_earlier: Selector,
+    /// Selector for tally rows against a set that comes later
+    q_tally_later: Selector,
+    /// Selector for the tally threshold gate
+    q_tally_threshold: Selector,
+    /// Comparison lanes of a grid row; lane 0 is made of the B columns above
+    lanes: Vec<LaneColumns>,
+    /// Advice column used to witness commitment salts
+    pub(crate) salt: Column<Advice>,
+    /// Poseidon chip configuration for the set commitments
+    poseidon: Pow5Config<Fp, 3, 2>,
+    /// Instance column for public intersection size and set commitments
+    pub(crate) instance: Column<Instance>,
+}
+
+/// Columns of one comparison lane of the grid
+///
+/// A grid row compares one slot of A with one slot of B per lane, so the B
+/// side of a comparison lives in its lane while the A side and the running
+/// sums are shared by the row.
+#[derive(Debug, Clone)]
+struct LaneColumns {
+    set_b: Column<Advice>,
+    b_active: Column<Advice>,
+    b_used: Column<Advice>,
+    weight: Column<Advice>,
+    match_bit: Column<Advice>,
+    inverse: Column<Advice>,
+    pick: Column<Advice>,
+    b_picks_prev: Column<Advice>,
+    b_picks: Column<Advice>,
+}
+
+/// Assigned cells for one padded slot of an input set
+#[derive(Debug, Clone)]
+pub struct AssignedSlot {
+    pub value: AssignedCell<Fp, Fp>,
+    pub active: AssignedCell<Fp, Fp>,
+    pub used: AssignedCell<Fp, Fp>,
+    /// Private value attached to the slot
+    pub weight: AssignedCell<Fp, Fp>,
+}
+
+/// Assigned cells for a padded input set
+#[derive(Debug, Clone)]
+pub struct AssignedSet {
+    pub slots: Vec<AssignedSlot>,
+    /// Number of active slots
+    pub size: AssignedCell<Fp, Fp>,
+}
+
+/// Running accumulators of an assigned comparison row
+#[derive(Debug, Clone)]
+pub struct AssignedComparison {
+    /// Running count of picks over the whole grid
+    pub sum: AssignedCell<Fp, Fp>,
+    /// Picks of the row's A slot so far
+    pub a_picks: AssignedCell<Fp, Fp>,
+    /// Picks of each of the row's B slots so far
+    pub b_picks: Vec<AssignedCell<Fp, Fp>>,
+    /// Running sum of the values of matching B elements over the whole grid
+    pub weighted: AssignedCell<Fp, Fp>,
+}
+
+impl PsiConfig {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        Self::configure_with_lanes(meta, 1)
+    }
+
+    /// Configure a grid that packs `lanes` comparisons into every row
+    ///
+    /// Every lane beyond the first adds nine advice columns and divides the
+    /// number of grid rows by up to the number of lanes.
+    pub fn configure_with_lanes(meta: &mut ConstraintSystem<Fp>, lanes: usize) -> Self {
+        assert!(lanes >= 1, "The grid needs at least one lane");
+
+        let set_a = meta.advice_column();
+        let set_b = meta.advice_column();
+        let a_active = meta.advice_column();
+        let b_active = meta.advice_column();
+        let a_used = meta.advice_column();
+        let b_used = meta.advice_column();
+        let match_bit = meta.advice_column();
+        let inverse = meta.advice_column();
+        let pick = meta.advice_column();
+        let a_picks_prev = meta.advice_column();
+        let a_picks = meta.advice_column();
+        let b_picks_prev = meta.advice_column();
+        let b_picks = meta.advice_column();
+        let prev_sum = meta.advice_column();
+        let sum = meta.advice_column();
+        let weight = meta.advice_column();
+        let prev_weighted = meta.advice_column();
+        let weighted = meta.advice_column();
+        let instance = meta.instance_column();
+        let constant = meta.fixed_column();
+
+        meta.enable_constant(constant);
+
+        let poseidon_state = [
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+        ];
+        let partial_sbox = meta.advice_column();
+        let rc_a = [
+            meta.fixed_column(),
+            meta.fixed_column(),
+            meta.fixed_column(),
+        ];
+        let rc_b = [
+            meta.fixed_column(),
+            meta.fixed_column(),
+            meta.fixed_column(),
+        ];
+        let poseidon =
+            Pow5Chip::configure::<P128Pow5T3>(meta, poseidon_state, partial_sbox, rc_a, rc_b);
+        let salt = poseidon_state[0];
+
+        for column in [
+            set_a,
+            set_b,
+            a_active,
+            b_active,
+            a_used,
+            b_used,
+            match_bit,
+            a_picks_prev,
+            a_picks,
+            b_picks_prev,
+            b_picks,
+            prev_sum,
+            sum,
+            weight,
+            prev_weighted,
+            weighted,
+        ] {
+            meta.enable_equality(column);
+        }
+        meta.enable_equality(instance);
+
+        let lanes: Vec<LaneColumns> = std::iter::once(LaneColumns {
+            set_b,
+            b_active,
+            b_used,
+            weight,
+            match_bit,
+            inverse,
+            pick,
+            b_picks_prev,
+            b_picks,
+        })
+        .chain((1..lanes).map(|_| {
+            let lane = LaneColumns {
+                set_b: meta.advice_column(),
+                b_active: meta.advice_column(),
+                b_used: meta.advice_column(),
+                weight: meta.advice_column(),
+                match_bit: meta.advice_column(),
+                inverse: meta.advice_column(),
+                pick: meta.advice_column(),
+                b_picks_prev: meta.advice_column(),
+                b_picks: meta.advice_column(),
+            };
+            for column in [
+                lane.set_b,
+                lane.b_active,
+                lane.b_used,
+                lane.weight,
+                lane.b_picks_prev,
+                lane.b_picks,
+            ] {
+                meta.enable_equality(column);
+            }
+            lane
+        }))
+        .collect();
+
+        let q_slot_a = meta.selector();
+        let q_slot_b = meta.selector();
+        let q_prefix_a = meta.selector();
+        let q_prefix_b = meta.selector();
+        let q_equality = meta.selector();
+        let q_matching = meta.selector();
+        let q_sum = meta.selector();
+        let q_distinct = meta.selector();
+        let q_threshold = meta.selector();
+        let q_range = meta.selector();
+        let q_union = meta.selector();
+        let q_tally_earlier = meta.selector();
+        let q_tally_later = meta.selector();
+        let q_tally_threshold = meta.selector();
+
+        // Slot gates: every slot of a padded set carries a boolean "active" flag,
+        // padding slots hold zero, and active slots come before padding slots.
+        // Together these make the padded layout a canonical encoding of the set.
+        // The sum column counts the active slots, giving the set size, and the
+        // boolean "used" flag records whether the matching pairs the slot.
+        for (name, prefix_name, value, active, used, q_slot, q_prefix) in [
+            (
+                "set A slot",
+                "set A prefix",
+                set_a,
+                a_active,
+                a_used,
+                q_slot_a,
+                q_prefix_a,
+            ),
+            (
+                "set B slot",
+                "set B prefix",
+                set_b,
+                b_active,
+                b_used,
+                q_slot_b,
+                q_prefix_b,
+            ),
+        ] {
+            meta.create_gate(name, |meta| {
+                let q = meta.query_selector(q_slot);
+                let value = meta.query_advice(value, Rotation::cur());
+                let active = meta.query_advice(active, Rotation::cur());
+                let used = meta.query_advice(used, Rotation::cur());
+                let count_prev = meta.query_advice(prev_sum, Rotation::cur());
+                let count_cur = meta.query_advice(sum, Rotation::cur());
+                let one = Expression::Constant(Fp::one());
+
+                vec![
+                    // active is boolean
+                    q.clone() * active.clone() * (active.clone() - one.clone()),
+                    // padding slots hold zero
+                    q.clone() * (one.clone() - active.clone()) * value,
+                    // count[i] = count[i-1] + active[i]
+                    q.clone() * (count_cur - count_prev - active),
+                    // used is boolean
+                    q * used.clone() * (used - one),
+                ]
+            });
+
+            meta.create_gate(prefix_name, |meta| {
+                let q = meta.query_selector(q_prefix);
+                let active_prev = meta.query_advice(active, Rotation::prev());
+                let active_cur = meta.query_advice(active, Rotation::cur());
+
+                // a slot can only be active if the slot before it is
+                vec![q * active_cur * (Expression::Constant(Fp::one()) - active_prev)]
+            });
+        }
+
+        // Equality gate: Ensures match_bit is exactly [a == b] for active slots
+        // IsZero construction with diff = set_a - set_b and is_eq = 1 - diff * inverse:
+        // Constraint: diff * is_eq == 0 (if a != b, the inverse forces is_eq to 0)
+        // Constraint: match_bit == a_active * b_active * is_eq
+        // If a == b then diff == 0, so is_eq == 1 whatever the inverse holds, and a
+        // prover can neither claim a match on unequal elements nor hide a real one.
+        // Every lane compares the row's A slot with its own B slot.
+        meta.create_gate("equality check", |meta| {
+            let q = meta.query_selector(q_equality);
+            let a = meta.query_advice(set_a, Rotation::cur());
+            let a_active = meta.query_advice(a_active, Rotation::cur());
+
+            let mut constraints = vec![];
+            for lane in &lanes {
+                let b = meta.query_advice(lane.set_b, Rotation::cur());
+                let b_active = meta.query_advice(lane.b_active, Rotation::cur());
+                let match_bit = meta.query_advice(lane.match_bit, Rotation::cur());
+                let inverse = meta.query_advice(lane.inverse, Rotation::cur());
+
+                let diff = a.clone() - b;
+                let is_eq = Expression::Constant(Fp::one()) - diff.clone() * inverse;
+
+                // diff and is_eq cannot both be non-zero
+                constraints.push(q.clone() * diff * is_eq.clone());
+                // match only between active slots holding equal elements
+                constraints.push(q.clone() * (match_bit - a_active.clone() * b_active * is_eq));
+            }
+            constraints
+        });
+
+        // Matching gate: the pick bits form a maximal matching of the match bits
+        // Each slot is picked at most once (its pick count equals its boolean
+        // "used" flag, closed by copy constraints after the grid), and no match
+        // is left with both slots unused. Equal elements form complete bipartite
+        // components, so any maximal matching pairs min(mult_A, mult_B) of each.
+        // The picks of the A slot advance by the picks of all lanes of the row.
+        meta.create_gate("matching", |meta| {
+            let q = meta.query_selector(q_matching);
+            let a_used = meta.query_advice(a_used, Rotation::cur());
+            let a_picks_prev = meta.query_advice(a_picks_prev, Rotation::cur());
+            let a_picks = meta.query_advice(a_picks, Rotation::cur());
+            let one = Expression::Constant(Fp::one());
+
+            let mut constraints = vec![];
+            let mut row_picks = Expression::Constant(Fp::zero());
+            for lane in &lanes {
+                let match_bit = meta.query_advice(lane.match_bit, Rotation::cur());
+                let pick = meta.query_advice(lane.pick, Rotation::cur());
+                let b_used = meta.query_advice(lane.b_used, Rotation::cur());
+                let b_picks_prev = meta.query_advice(lane.b_picks_prev, Rotation::cur());
+                let b_picks = meta.query_advice(lane.b_picks, Rotation::cur());
+
+                // only matching pairs can be picked (this also makes pick boolean)
+                constraints.push(q.clone() * pick.clone() * (one.clone() - match_bit.clone()));
+                constraints.push(q.clone() * pick.clone() * (pick.clone() - one.clone()));
+                // per-slot pick counts of B
+                constraints.push(q.clone() * (b_picks - b_picks_prev - pick.clone()));
+                // maximality
+                constraints.push(
+                    q.clone() * match_bit * (one.clone() - a_used.clone()) * (one.clone() - b_used),
+                );
+                row_picks = row_picks + pick;
+            }
+            // per-slot pick count of A
+            constraints.push(q * (a_picks - a_picks_prev - row_picks));
+            constraints
+        });
+
+        // Sum gate: Accumulates the intersection size
+        // sum[i] = sum[i-1] + Σ pick[i], where sum[i-1] is copied into prev_sum
+        meta.create_gate("sum accumulator", |meta| {
+            let q = meta.query_selector(q_sum);
+            let sum_prev = meta.query_advice(prev_sum, Rotation::cur());
+            let sum_cur = meta.query_advice(sum, Rotation::cur());
+            let picks = lanes
+                .iter()
+                .fold(Expression::Constant(Fp::zero()), |acc, lane| {
+                    acc + meta.query_advice(lane.pick, Rotation::cur())
+                });
+
+            vec![q * (sum_cur - sum_prev - picks)]
+        });
+
+        // Weighted sum gate: Accumulates the values of matching B elements
+        // weighted[i] = weighted[i-1] + Σ weight_b[i] * match_bit[i]
+        meta.create_gate("weighted accumulator", |meta| {
+            let q = meta.query_selector(q_sum);
+            let weighted_prev = meta.query_advice(prev_weighted, Rotation::cur());
+            let weighted_cur = meta.query_advice(weighted, Rotation::cur());
+            let values = lanes
+                .iter()
+                .fold(Expression::Constant(Fp::zero()), |acc, lane| {
+                    let weight = meta.query_advice(lane.weight, Rotation::cur());
+                    let match_bit = meta.query_advice(lane.match_bit, Rotation::cur());
+                    acc + weight * match_bit
+                });
+
+            vec![q * (weighted_cur - weighted_prev - values)]
+        });
+
+        // Distinct gate: two active slots of the same set hold different elements,
+        // witnessed by the inverse of their difference
+        meta.create_gate("distinct elements", |meta| {
+            let q = meta.query_selector(q_distinct);
+            let x = meta.query_advice(set_a, Rotation::cur());
+            let y = meta.query_advice(set_b, Rotation::cur());
+            let x_active = meta.query_advice(a_active, Rotation::cur());
+            let y_active = meta.query_advice(b_active, Rotation::cur());
+            let inverse = meta.query_advice(inverse, Rotation::cur());
+
+            vec![q * x_active * y_active * (Expression::Constant(Fp::one()) - (x - y) * inverse)]
+        });
+
+        // Threshold gate: the running value z starts as count - threshold,
+        // with the count copied into prev_sum and the threshold into a_picks
+        meta.create_gate("threshold difference", |meta| {
+            let q = meta.query_selector(q_threshold);
+            let count = meta.query_advice(prev_sum, Rotation::cur());
+            let threshold = meta.query_advice(a_picks, Rotation::cur());
+            let z = meta.query_advice(sum, Rotation::cur());
+
+            vec![q * (z - (count - threshold))]
+        });
+
+        // Range gate: z[i] = bit[i] + 2 * z[i+1] with boolean bits, so a
+        // decomposition ending in z = 0 after `bits` rows shows that
+        // z[0] < 2^bits, which rules out a negative difference
+        meta.create_gate("range check", |meta| {
+            let q = meta.query_selector(q_range);
+            let bit = meta.query_advice(pick, Rotation::cur());
+            let z_cur = meta.query_advice(sum, Rotation::cur());
+            let z_next = meta.query_advice(sum, Rotation::next());
+
+            vec![
+                q.clone() * bit.clone() * (bit.clone() - Expression::Constant(Fp::one())),
+                q * (z_cur - z_next * Fp::from(2) - bit),
+            ]
+        });
+
+        // Union gate: |A ∪ B| = |A| + |B| - |A ∩ B|, with the sizes in the
+        // picks columns. This also holds for multisets, where the union takes
+        // the maximum multiplicity of each element.
+        meta.create_gate("union size", |meta| {
+            let q = meta.query_selector(q_union);
+            let size_a = meta.query_advice(a_picks_prev, Rotation::cur());
+            let size_b = meta.query_advice(a_picks, Rotation::cur());
+            let intersection = meta.query_advice(b_picks_prev, Rotation::cur());
+            let union = meta.query_advice(b_picks, Rotation::cur());
+
+            vec![q * (union - (size_a + size_b - intersection))]
+        });
+
+        // Tally gates for multi-party intersections: walking over the other
+        // sets, count[r] = count[r-1] + presence[r] counts the sets holding an
+        // element, and owner[r] = owner[r-1] * (1 - presence[r]) clears the
+        // owner flag if an earlier set holds it too, so that every element of
+        // the union is tallied once.
+        for (name, q_tally, earlier) in [
+            ("tally earlier set", q_tally_earlier, true),
+            ("tally later set", q_tally_later, false),
+        ] {
+            meta.create_gate(name, |meta| {
+                let q = meta.query_selector(q_tally);
+                let presence = meta.query_advice(match_bit, Rotation::cur());
+                let count_prev = meta.query_advice(sum, Rotation::prev());
+                let count_cur = meta.query_advice(sum, Rotation::cur());
+                let owner_prev = meta.query_advice(a_picks, Rotation::prev());
+                let owner_cur = meta.query_advice(a_picks, Rotation::cur());
+
+                let owner_next = if earlier {
+                    owner_prev * (Expression::Constant(Fp::one()) - presence.clone())
+                } else {
+                    owner_prev
+                };
+                vec![
+                    q.clone() * (count_cur - count_prev - presence),
+                    q * (owner_cur - owner_next),
+                ]
+            });
+        }
+
+        // Tally threshold gate: the boolean at_least is [count >= threshold],
+        // shown by range checking d = count - threshold when set and
+        // threshold - 1 - count otherwise. Owned elements held by enough sets
+        // are added to the running total.
+        meta.create_gate("tally threshold", |meta| {
+            let q = meta.query_selector(q_tally_threshold);
+            let count = meta.query_advice(sum, Rotation::cur());
+            let owner = meta.query_advice(a_picks, Rotation::cur());
+            let threshold = meta.query_advice(b_picks_prev, Rotation::cur());
+            let at_least = meta.query_advice(pick, Rotation::cur());
+            let d = meta.query_advice(b_picks, Rotation::cur());
+            let total_prev = meta.query_advice(prev_weighted, Rotation::cur());
+            let total = meta.query_advice(weighted, Rotation::cur());
+            let one = Expression::Constant(Fp::one());
+
+            vec![
+                q.clone() * at_least.clone() * (at_least.clone() - one.clone()),
+                q.clone()
+                    * (d - at_least.clone() * (count.clone() - threshold.clone())
+                        - (one.clone() - at_least.clone()) * (threshold - one - count)),
+                q * (total - total_prev - owner * at_least),
+            ]
+        });
+
+        Self {
+            set_a,
+            set_b,
+            a_active,
+            b_active,
+            a_used,
+            b_used,
+            match_bit,
+            inverse,
+            pick,
+            a_picks_prev,
+            a_picks,
+            b_picks_prev,
+            b_picks,
+            prev_sum,
+            sum,
+            weight,
+            prev_weighted,
+            weighted,
+            q_slot_a,
+            q_slot_b,
+            q_prefix_a,
+            q_prefix_b,
+            q_equality,
+            q_matching,
+            q_sum,
+            q_distinct,
+            q_threshold,
+            q_range,
+            q_union,
+            q_tally_earlier,
+            q_tally_later,
+            q_tally_threshold,
+            lanes,
+            salt,
+            poseidon,
+            instance,
+        }
+    }
+
+    /// Number of comparisons packed into every grid row
+    pub fn lanes(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Assign a padded set into its slot column.
+    ///
+    /// `slots` must already be padded to the circuit's fixed size, `used`
+    /// holds the matching's usage flag and `weights` the private value of each
+    /// slot; `is_a` selects between the set A and set B columns.
+    pub fn assign_set(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        slots: &[(Fp, bool)],
+        used: &[bool],
+        weights: &[Fp],
+        is_a: bool,
+    ) -> Result<AssignedSet, Error> {
+        let (value_col, active_col, used_col, q_slot, q_prefix, name) = if is_a {
+            (
+                self.set_a,
+                self.a_active,
+                self.a_used,
+                self.q_slot_a,
+                self.q_prefix_a,
+                "set A",
+            )
+        } else {
+            (
+                self.set_b,
+                self.b_active,
+                self.b_used,
+                self.q_slot_b,
+                self.q_prefix_b,
+                "set B",
+            )
+        };
+
+        layouter.assign_region(
+            || format!("load {}", name),
+            |mut region| {
+                let mut assigned = Vec::with_capacity(slots.len());
+                let mut count: Option<AssignedCell<Fp, Fp>> = None;
+
+                for (offset, (((value, active), used), weight)) in
+                    slots.iter().zip(used).zip(weights).enumerate()
+                {
+                    q_slot.enable(&mut region, offset)?;
+                    if offset > 0 {
+                        q_prefix.enable(&mut region, offset)?;
+                    }
+
+                    let value = region.assign_advice(
+                        || format!("{} value {}", name, offset),
+                        value_col,
+                        offset,
+                        || Value::known(*value),
+                    )?;
+                    let active = region.assign_advice(
+                        || format!("{} active {}", name, offset),
+                        active_col,
+                        offset,
+                        || Value::known(Fp::from(*active as u64)),
+                    )?;
+                    let used = region.assign_advice(
+                        || format!("{} used {}", name, offset),
+                        used_col,
+                        offset,
+                        || Value::known(Fp::from(*used as u64)),
+                    )?;
+                    let weight = region.assign_advice(
+                        || format!("{} weight {}", name, offset),
+                        self.weight,
+                        offset,
+                        || Value::known(*weight),
+                    )?;
+
+                    let prev_count = match count {
+                        Some(ref count) => count.copy_advice(
+                            || "prev count",
+                            &mut region,
+                            self.prev_sum,
+                            offset,
+                        )?,
+                        None => region.assign_advice_from_constant(
+                            || "prev count",
+                            self.prev_sum,
+                            offset,
+                            Fp::zero(),
+                        )?,
+                    };
+                    count = Some(region.assign_advice(
+                        || format!("{} count {}", name, offset),
+                        self.sum,
+                        offset,
+                        || prev_count.value().copied() + active.value(),
+                    )?);
+
+                    assigned.push(AssignedSlot {
+                        value,
+                        active,
+                        used,
+                        weight,
+                    });
+                }
+
+                Ok(AssignedSet {
+                    slots: assigned,
+                    size: count.expect("sets have at least one slot"),
+                })
+            },
+        )
+    }
+
+    /// Constrain the active elements of an assigned set to be pairwise distinct
+    pub fn assert_distinct(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        set: &AssignedSet,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "distinct elements",
+            |mut region| {
+                let mut offset = 0;
+                for (i, x) in set.slots.iter().enumerate() {
+                    for y in &set.slots[i + 1..] {
+                        self.q_distinct.enable(&mut region, offset)?;
+
+                        let x_val = x
+                            .value
+                            .copy_advice(|| "x", &mut region, self.set_a, offset)?;
+                        x.active
+                            .copy_advice(|| "x active", &mut region, self.a_active, offset)?;
+                        let y_val = y
+                            .value
+                            .copy_advice(|| "y", &mut region, self.set_b, offset)?;
+                        y.active
+                            .copy_advice(|| "y active", &mut region, self.b_active, offset)?;
+
+                        let inverse = (x_val.value().copied() - y_val.value())
+                            .map(|diff| diff.invert().unwrap_or(Fp::zero()));
+                        region.assign_advice(|| "inverse", self.inverse, offset, || inverse)?;
+
+                        offset += 1;
+                    }
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Constrain `count` to be at least the threshold held in the given
+    /// instance row, without revealing the count itself
+    ///
+    /// `count` must be known to stay below `2^bits`.
+    pub fn assert_at_least(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        count: &AssignedCell<Fp, Fp>,
+        threshold_row: usize,
+        bits: usize,
+    ) -> Result<(), Error> {
+        let diff = layouter.assign_region(
+            || "threshold",
+            |mut region| {
+                self.q_threshold.enable(&mut region, 0)?;
+
+                let count = count.copy_advice(|| "count", &mut region, self.prev_sum, 0)?;
+                let threshold = region.assign_advice_from_instance(
+                    || "threshold",
+                    self.instance,
+                    threshold_row,
+                    self.a_picks,
+                    0,
+                )?;
+                region.assign_advice(
+                    || "count - threshold",
+                    self.sum,
+                    0,
+                    || count.value().copied() - threshold.value(),
+                )
+            },
+        )?;
+
+        self.assert_range(layouter.namespace(|| "range"), &diff, bits)
+    }
+
+    /// Constrain `value` to lie in `[0, 2^bits)`
+    pub fn assert_range(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        value: &AssignedCell<Fp, Fp>,
+        bits: usize,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "range check",
+            |mut region| {
+                // Little-endian bits of the value; an honest prover only gets
+                // here with a value in range
+                let decomposition = value.value().map(|value| {
+                    let repr = value.to_repr();
+                    (0..bits)
+                        .map(|i| (repr.as_ref()[i / 8] >> (i % 8)) & 1 == 1)
+                        .collect::<Vec<_>>()
+                });
+
+                let mut z = value.copy_advice(|| "z 0", &mut region, self.sum, 0)?;
+                for i in 0..bits {
+                    self.q_range.enable(&mut region, i)?;
+
+                    let bit = decomposition.as_ref().map(|bits| Fp::from(bits[i] as u64));
+                    region.assign_advice(|| format!("bit {}", i), self.pick, i, || bit)?;
+
+                    // z[i+1] = (z[i] - bit[i]) / 2
+                    let z_next = (z.value().copied() - bit)
+                        .map(|value| value * Fp::from(2).invert().unwrap());
+                    z = region.assign_advice(
+                        || format!("z {}", i + 1),
+                        self.sum,
+                        i + 1,
+                        || z_next,
+                    )?;
+                }
+                region.constrain_constant(z.cell(), Fp::zero())
+            },
+        )
+    }
+
+    /// Constrain two assignments of a padded set to hold the same slots
+    pub fn constrain_same_set(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        set: &AssignedSet,
+        copy: &AssignedSet,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "same set",
+            |mut region| {
+                for (slot, copied) in set.slots.iter().zip(&copy.slots) {
+                    region.constrain_equal(slot.value.cell(), copied.value.cell())?;
+                    region.constrain_equal(slot.active.cell(), copied.active.cell())?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Tally one slot of a multi-party intersection.
+    ///
+    /// `presence` holds, for every other set in order, whether it contains the
+    /// slot's element and whether that set comes before the slot's own set.
+    /// The slot adds one to the running total if it is active, no earlier set
+    /// holds its element, and at least `threshold` sets (its own included) do.
+    /// `prev_total` continues the running total, or starts it from zero.
+    pub fn assign_tally(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        active: &AssignedCell<Fp, Fp>,
+        presence: &[(AssignedCell<Fp, Fp>, bool)],
+        threshold: u64,
+        prev_total: Option<&AssignedCell<Fp, Fp>>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let (total, d) = layouter.assign_region(
+            || "tally",
+            |mut region| {
+                let mut count = active.copy_advice(|| "count", &mut region, self.sum, 0)?;
+                let mut owner = active.copy_advice(|| "owner", &mut region, self.a_picks, 0)?;
+
+                for (offset, (present, earlier)) in
+                    presence.iter().enumerate().map(|(r, p)| (r + 1, p))
+                {
+                    if *earlier {
+                        self.q_tally_earlier.enable(&mut region, offset)?;
+                    } else {
+                        self.q_tally_later.enable(&mut region, offset)?;
+                    }
+
+                    let present =
+                        present.copy_advice(|| "presence", &mut region, self.match_bit, offset)?;
+                    count = region.assign_advice(
+                        || "count",
+                        self.sum,
+                        offset,
+                        || count.value().copied() + present.value(),
+                    )?;
+                    let owner_next = if *earlier {
+                        owner.value().copied() * (Value::known(Fp::one()) - present.value())
+                    } else {
+                        owner.value().copied()
+                    };
+                    owner =
+                        region.assign_advice(|| "owner", self.a_picks, offset, || owner_next)?;
+                }
+
+                let last = presence.len();
+                self.q_tally_threshold.enable(&mut region, last)?;
+
+                // Counts are at most MAX_PARTIES, so the low byte holds them
+                let at_least = count.value().map(|count| {
+                    Fp::from((u64::from(count.to_repr().as_ref()[0]) >= threshold) as u64)
+                });
+                let threshold = region.assign_advice_from_constant(
+                    || "threshold",
+                    self.b_picks_prev,
+                    last,
+                    Fp::from(threshold),
+                )?;
+                region.assign_advice(|| "at least", self.pick, last, || at_least)?;
+
+                let d = count.value().copied() * at_least - threshold.value().copied() * at_least
+                    + (Value::known(Fp::one()) - at_least)
+                        * (threshold.value().copied() - Value::known(Fp::one()) - count.value());
+                let d = region.assign_advice(|| "d", self.b_picks, last, || d)?;
+
+                let total_prev = match prev_total {
+                    Some(prev) => prev.copy_advice(
+                        || "previous total",
+                        &mut region,
+                        self.prev_weighted,
+                        last,
+                    )?,
+                    None => region.assign_advice_from_constant(
+                        || "previous total",
+                        self.prev_weighted,
+                        last,
+                        Fp::zero(),
+                    )?,
+                };
+                let total = region.assign_advice(
+                    || "total",
+                    self.weighted,
+                    last,
+                    || total_prev.value().copied() + owner.value().copied() * at_least,
+                )?;
+
+                Ok((total, d))
+            },
+        )?;
+
+        self.assert_range(layouter.namespace(|| "range"), &d, TALLY_BITS)?;
+        Ok(total)
+    }
+
+    /// Compute |A ∪ B| from the set sizes and the intersection size
+    pub fn assign_union(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        size_a: &AssignedCell<Fp, Fp>,
+        size_b: &AssignedCell<Fp, Fp>,
+        intersection: &AssignedCell<Fp, Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        layouter.assign_region(
+            || "union size",
+            |mut region| {
+                self.q_union.enable(&mut region, 0)?;
+
+                let size_a = size_a.copy_advice(|| "|A|", &mut region, self.a_picks_prev, 0)?;
+                let size_b = size_b.copy_advice(|| "|B|", &mut region, self.a_picks, 0)?;
+                let intersection =
+                    intersection.copy_advice(|| "|A ∩ B|", &mut region, self.b_picks_prev, 0)?;
+                region.assign_advice(
+                    || "|A ∪ B|",
+                    self.b_picks,
+                    0,
+                    || size_a.value().copied() + size_b.value() - intersection.value(),
+                )
+            },
+        )
+    }
+
+    /// Constrain every active slot of an assigned set to be paired in the
+    /// matching, i.e. to be picked exactly once in the comparison grid
+    pub fn assert_all_used(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        set: &AssignedSet,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "all used",
+            |mut region| {
+                for slot in &set.slots {
+                    region.constrain_equal(slot.used.cell(), slot.active.cell())?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Poseidon hash of two assigned cells
+    pub(crate) fn hash_pair(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        left: AssignedCell<Fp, Fp>,
+        right: AssignedCell<Fp, Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let chip = Pow5Chip::construct(self.poseidon.clone());
+        let hasher = PoseidonHash::<_, _, P128Pow5T3, ConstantLength<2>, 3, 2>::init(
+            chip,
+            layouter.namespace(|| "init"),
+        )?;
+        hasher.hash(layouter.namespace(|| "hash"), [left, right])
+    }
+
+    /// Compute the Poseidon commitment to an assigned set, matching [`commit_set`]
+    ///
+    /// [`commit_set`]: crate::commit_set
+    pub fn commit_set(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        set: &AssignedSet,
+        salt: Value<Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let salt = layouter.assign_region(
+            || "salt",
+            |mut region| region.assign_advice(|| "salt", self.salt, 0, || salt),
+        )?;
+
+        let mut digest = self.hash_pair(
+            layouter.namespace(|| "salt and size"),
+            salt,
+            set.size.clone(),
+        )?;
+        for (i, slot) in set.slots.iter().enumerate() {
+            digest = self.hash_pair(
+                layouter.namespace(|| format!("slot {}", i)),
+                digest,
+                slot.value.clone(),
+            )?;
+        }
+
+        Ok(digest)
+    }
+
+    /// Continue a set commitment over the slot weights, matching
+    /// [`commit_weighted_set`]
+    ///
+    /// [`commit_weighted_set`]: crate::commit_weighted_set
+    pub fn commit_weights(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        commitment: AssignedCell<Fp, Fp>,
+        set: &AssignedSet,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let mut digest = commitment;
+        for (i, slot) in set.slots.iter().enumerate() {
+            digest = self.hash_pair(
+                layouter.namespace(|| format!("weight {}", i)),
+                digest,
+                slot.weight.clone(),
+            )?;
+        }
+
+        Ok(digest)
+    }
+
+    /// Assign the full comparison grid of two sets.
+    ///
+    /// `picks[i][j]` is the matching witness for slot `i` of A and slot `j` of B.
+    /// Returns the accumulators of the last row, whose `sum` is the size of the
+    /// matching and `weighted` the sum of the B values over all matches.
+    pub fn assign_grid(
+        &self,
+        layouter: impl Layouter<Fp>,
+        set_a: &AssignedSet,
+        set_b: &AssignedSet,
+        picks: &[Vec<bool>],
+    ) -> Result<AssignedComparison, Error> {
+        self.assign_grid_with(layouter, set_a, set_b, picks, None, |_, witness| witness)
+    }
+
+    /// Assign the full comparison grid of two sets, continuing the running
+    /// sum from `start` instead of zero
+    pub fn assign_grid_from(
+        &self,
+        layouter: impl Layouter<Fp>,
+        set_a: &AssignedSet,
+        set_b: &AssignedSet,
+        picks: &[Vec<bool>],
+        start: &AssignedCell<Fp, Fp>,
+    ) -> Result<AssignedComparison, Error> {
+        self.assign_grid_with(layouter, set_a, set_b, picks, Some(start), |_, w| w)
+    }
+
+    /// Assign the comparison grid, letting `witness` rewrite the honest witness
+    /// of any comparison (see [`PsiConfig::assign_row_with`]). Comparisons are
+    /// indexed row-major, `i * |B| + j` for slot `i` of A and slot `j` of B.
+    /// The running sum starts from `start`, or from a fixed zero.
+    ///
+    /// The whole grid is a single region with one row per comparison row, so
+    /// the floor planner shapes one region instead of one per row.
+    pub(crate) fn assign_grid_with(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        set_a: &AssignedSet,
+        set_b: &AssignedSet,
+        picks: &[Vec<bool>],
+        start: Option<&AssignedCell<Fp, Fp>>,
+        witness: impl Fn(usize, ComparisonWitness) -> ComparisonWitness,
+    ) -> Result<AssignedComparison, Error> {
+        let width = self.lanes();
+
+        layouter.assign_region(
+            || "comparison grid",
+            |mut region| {
+                let mut last: Option<AssignedComparison> = None;
+                let mut above: Vec<Option<AssignedCell<Fp, Fp>>> = vec![None; set_b.slots.len()];
+                let mut row_ends = Vec::with_capacity(set_a.slots.len());
+                let mut row = 0;
+
+                // Compare every slot of set A with every slot of set B, `width`
+                // slots of B per row
+                for (i, a) in set_a.slots.iter().enumerate() {
+                    let mut left: Option<AssignedCell<Fp, Fp>> = None;
+                    for (chunk, b) in set_b.slots.chunks(width).enumerate() {
+                        let first = chunk * width;
+                        let range = first..first + b.len();
+                        let assigned = self.assign_row_with(
+                            &mut region,
+                            row,
+                            a,
+                            b,
+                            &picks[i][range.clone()],
+                            [
+                                last.as_ref().map(|last| &last.sum).or(start).cloned(),
+                                left.take(),
+                                last.as_ref().map(|last| last.weighted.clone()),
+                            ],
+                            &above[range.clone()],
+                            |lane, w| witness(i * set_b.slots.len() + first + lane, w),
+                        )?;
+                        left = Some(assigned.a_picks.clone());
+                        for (above, picks) in above[range].iter_mut().zip(&assigned.b_picks) {
+                            *above = Some(picks.clone());
+                        }
+                        last = Some(assigned);
+                        row += 1;
+                    }
+                    row_ends.extend(left);
+                }
+
+                // Each slot's pick count must equal its usage flag
+                for (slot, picks) in set_a.slots.iter().zip(&row_ends) {
+                    region.constrain_equal(slot.used.cell(), picks.cell())?;
+                }
+                for (slot, picks) in set_b.slots.iter().zip(above.iter().flatten()) {
+                    region.constrain_equal(slot.used.cell(), picks.cell())?;
+                }
+
+                Ok(last.expect("grid has at least one row"))
+            },
+        )
+    }
+
+    /// Assign one grid row at `offset` of `region`, comparing slot `a` with up
+    /// to [`PsiConfig::lanes`] slots of B and updating the running
+    /// accumulators.
+    ///
+    /// `links` holds the cells this row continues from: the running sum, the
+    /// previous picks of `a` and the running weighted sum, and `b_links` the
+    /// previous picks of each slot in `b`. `None` starts the accumulator from
+    /// a fixed zero. Lanes beyond `b` are padded with inactive slots.
+    #[allow(clippy::too_many_arguments)]
+    pub fn assign_row(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        a: &AssignedSlot,
+        b: &[AssignedSlot],
+        picks: &[bool],
+        links: [Option<AssignedCell<Fp, Fp>>; 3],
+        b_links: &[Option<AssignedCell<Fp, Fp>>],
+    ) -> Result<AssignedComparison, Error> {
+        self.assign_row_with(region, offset, a, b, picks, links, b_links, |_, w| w)
+    }
+
+    /// Assign a grid row, letting `witness` rewrite the honest witness values
+    /// of any lane before they are placed. The running values of a lane
+    /// continue from the (possibly rewritten) values of the lane before it, and
+    /// the copy constraints are laid out exactly as for an honest prover, which
+    /// lets tests model a malicious one.
+    #[allow(clippy::too_many_arguments)]
+    fn assign_row_with(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        a: &AssignedSlot,
+        b: &[AssignedSlot],
+        picks: &[bool],
+        links: [Option<AssignedCell<Fp, Fp>>; 3],
+        b_links: &[Option<AssignedCell<Fp, Fp>>],
+        witness: impl Fn(usize, ComparisonWitness) -> ComparisonWitness,
+    ) -> Result<AssignedComparison, Error> {
+        assert!(
+            !b.is_empty() && b.len() <= self.lanes(),
+            "Row does not fit the lanes"
+        );
+
+        self.q_equality.enable(region, offset)?;
+        self.q_matching.enable(region, offset)?;
+        self.q_sum.enable(region, offset)?;
+
+        let a_val = a
+            .value
+            .copy_advice(|| "set_a", region, self.set_a, offset)?;
+        let a_active = a
+            .active
+            .copy_advice(|| "a_active", region, self.a_active, offset)?;
+        a.used
+            .copy_advice(|| "a_used", region, self.a_used, offset)?;
+
+        let [sum_link, a_picks_link, weighted_link] = &links;
+        let start = [sum_link, a_picks_link, weighted_link].map(|link| match link {
+            Some(prev) => prev.value().copied(),
+            None => Value::known(Fp::zero()),
+        });
+        let [mut running_sum, mut running_a_picks, mut running_weighted] = start;
+        let mut first: Option<ComparisonWitness> = None;
+        let mut b_picks = Vec::with_capacity(b.len());
+
+        for (lane, columns) in self.lanes.iter().enumerate() {
+            let Some(slot) = b.get(lane) else {
+                // Padding lane: an inactive slot that can never match
+                region.assign_advice(
+                    || "set_b",
+                    columns.set_b,
+                    offset,
+                    || Value::known(Fp::zero()),
+                )?;
+                region.assign_advice_from_constant(
+                    || "b_active",
+                    columns.b_active,
+                    offset,
+                    Fp::zero(),
+                )?;
+                let inverse = a_val.value().map(|a| a.invert().unwrap_or(Fp::zero()));
+                region.assign_advice(|| "inverse", columns.inverse, offset, || inverse)?;
+                for column in [
+                    columns.b_used,
+                    columns.weight,
+                    columns.match_bit,
+                    columns.pick,
+                    columns.b_picks_prev,
+                    columns.b_picks,
+                ] {
+                    region.assign_advice(
+                        || "padding",
+                        column,
+                        offset,
+                        || Value::known(Fp::zero()),
+                    )?;
+                }
+                continue;
+            };
+
+            let b_val = slot
+                .value
+                .copy_advice(|| "set_b", region, columns.set_b, offset)?;
+            let b_active =
+                slot.active
+                    .copy_advice(|| "b_active", region, columns.b_active, offset)?;
+            slot.used
+                .copy_advice(|| "b_used", region, columns.b_used, offset)?;
+            let b_weight =
+                slot.weight
+                    .copy_advice(|| "b_weight", region, columns.weight, offset)?;
+
+            let b_link = &b_links[lane];
+            let w = witness(
+                lane,
+                ComparisonWitness::honest(
+                    [a_val.value().copied(), b_val.value().copied()],
+                    [a_active.value().copied(), b_active.value().copied()],
+                    b_weight.value().copied(),
+                    Value::known(Fp::from(picks[lane] as u64)),
+                    [
+                        running_sum,
+                        running_a_picks,
+                        match b_link {
+                            Some(prev) => prev.value().copied(),
+                            None => Value::known(Fp::zero()),
+                        },
+                        running_weighted,
+                    ],
+                ),
+            );
+
+            region.assign_advice(|| "match_bit", columns.match_bit, offset, || w.match_bit)?;
+            region.assign_advice(|| "inverse", columns.inverse, offset, || w.inverse)?;
+            region.assign_advice(|| "pick", columns.pick, offset, || w.pick)?;
+
+            let (prev, cur) = w.accumulators[2];
+            let prev_cell =
+                region.assign_advice(|| "previous", columns.b_picks_prev, offset, || prev)?;
+            match b_link {
+                Some(link) => region.constrain_equal(link.cell(), prev_cell.cell())?,
+                None => region.constrain_constant(prev_cell.cell(), Fp::zero())?,
+            }
+            b_picks.push(region.assign_advice(|| "running", columns.b_picks, offset, || cur)?);
+
+            running_sum = w.accumulators[0].1;
+            running_a_picks = w.accumulators[1].1;
+            running_weighted = w.weighted.1;
+            first.get_or_insert(w);
+        }
+
+        // The row-wide accumulators take one step over all lanes: each
+        // is copied from the row it continues, or starts from a fixed
+        // zero.
+        let first = first.expect("row has at least one comparison");
+        let mut outputs = Vec::with_capacity(3);
+        for ((link, (prev_col, cur_col)), (prev, cur)) in links
+            .iter()
+            .zip([
+                (self.prev_sum, self.sum),
+                (self.a_picks_prev, self.a_picks),
+                (self.prev_weighted, self.weighted),
+            ])
+            .zip([
+                (first.accumulators[0].0, running_sum),
+                (first.accumulators[1].0, running_a_picks),
+                (first.weighted.0, running_weighted),
+            ])
+        {
+            let prev_cell = region.assign_advice(|| "previous", prev_col, offset, || prev)?;
+            match link {
+                Some(link) => region.constrain_equal(link.cell(), prev_cell.cell())?,
+                None => region.constrain_constant(prev_cell.cell(), Fp::zero())?,
+            }
+            outputs.push(region.assign_advice(|| "running", cur_col, offset, || cur)?);
+        }
+
+        let [sum, a_picks, weighted]: [AssignedCell<Fp, Fp>; 3] =
+            outputs.try_into().expect("three accumulators");
+        Ok(AssignedComparison {
+            sum,
+            a_picks,
+            b_picks,
+            weighted,
+        })
+    }
+}
+
+/// Witness values of one comparison that are not copied from elsewhere
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ComparisonWitness {
+    pub(crate) match_bit: Value<Fp>,
+    pub(crate) inverse: Value<Fp>,
+    pub(crate) pick: Value<Fp>,
+    /// (previous, running) values of the sum, A picks and B picks accumulators
+    pub(crate) accumulators: [(Value<Fp>, Value<Fp>); 3],
+    /// (previous, running) values of the weighted sum
+    weighted: (Value<Fp>, Value<Fp>),
+}
+
+impl ComparisonWitness {
+    /// The witness an honest prover assigns for the given comparison inputs
+    fn honest(
+        [a, b]: [Value<Fp>; 2],
+        [a_active, b_active]: [Value<Fp>; 2],
+        b_weight: Value<Fp>,
+        pick: Value<Fp>,
+        previous: [Value<Fp>; 4],
+    ) -> Self {
+        let diff = a - b;
+        let inverse = diff.map(|diff| diff.invert().unwrap_or(Fp::zero()));
+        let is_eq = diff.map(|diff| {
+            if diff == Fp::zero() {
+                Fp::one()
+            } else {
+                Fp::zero()
+            }
+        });
+        let match_bit = a_active * b_active * is_eq;
+
+        let [prev_sum, prev_a_picks, prev_b_picks, prev_weighted] = previous;
+        Self {
+            match_bit,
+            inverse,
+            pick,
+            accumulators: [prev_sum, prev_a_picks, prev_b_picks].map(|prev| (prev, prev + pick)),
+            weighted: (prev_weighted, prev_weighted + b_weight * match_bit),
+        }
+    }
+}
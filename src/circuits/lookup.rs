@@ -0,0 +1,472 @@
+//! PSI circuit against a public catalogue through a lookup argument
+
+use crate::circuits::{check_element_bits, check_max_size, check_set_size};
+use crate::{
+    AssignedSet, ByteRangeConfig, COMMITMENT_A_ROW, CircuitError, ELEMENT_BITS,
+    INTERSECTION_SIZE_ROW, IntersectionSemantics, MAX_SET_SIZE, PsiCircuit, PsiConfig,
+    commit_set_with_capacity,
+};
+use ff::{Field, PrimeField};
+use halo2_proofs::circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{
+    Advice, Circuit, Column, ConstraintSystem, Error, Expression, Selector, TableColumn,
+};
+use halo2_proofs::poly::Rotation;
+use pasta_curves::Fp;
+use rand::rngs::OsRng;
+
+/// Configuration of [`LookupPsiCircuit`]: the PSI columns plus the catalogue
+/// tables and the membership columns
+///
+/// halo2 only looks up into fixed columns, so the catalogue is loaded into
+/// fixed tables when the keys are generated. Every slot of A looks up a pair
+/// of neighbouring catalogue elements `(lo, hi)`: it is a member if it equals
+/// `hi`, and provably not a member if it lies strictly between them. The
+/// catalogue is padded with the sentinels `-1` and `2^ELEMENT_BITS`.
+#[derive(Debug, Clone)]
+pub struct LookupPsiConfig {
+    psi: PsiConfig,
+    /// Lower neighbour in the catalogue
+    gap_lo: Column<Advice>,
+    /// Upper neighbour in the catalogue (the element itself for members)
+    gap_hi: Column<Advice>,
+    /// `a - lo - 1` for non-members, range checked to show `lo < a`
+    below: Column<Advice>,
+    /// `hi - a - 1` for non-members and `2^ELEMENT_BITS - 1 - a` for members,
+    /// range checked to show `a < hi` and `a < 2^ELEMENT_BITS` respectively
+    above: Column<Advice>,
+    /// Selector for the membership gate and the catalogue lookup
+    q_member: Selector,
+    /// 1 on catalogue rows, 0 on the all-zero row matched by disabled lookups
+    table_tag: TableColumn,
+    table_lo: TableColumn,
+    table_hi: TableColumn,
+    /// Byte table for the range checks of below and above
+    bytes: ByteRangeConfig,
+}
+
+impl LookupPsiConfig {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let psi = PsiConfig::configure(meta);
+        let gap_lo = meta.advice_column();
+        let gap_hi = meta.advice_column();
+        let below = meta.advice_column();
+        let above = meta.advice_column();
+        let q_member = meta.complex_selector();
+        let table_tag = meta.lookup_table_column();
+        let table_lo = meta.lookup_table_column();
+        let table_hi = meta.lookup_table_column();
+        let bytes = ByteRangeConfig::configure(meta, psi.sum);
+
+        meta.enable_equality(below);
+        meta.enable_equality(above);
+
+        let bound = Expression::Constant(element_bound());
+
+        // Membership gate: member is boolean and the count grows by it. A
+        // member equals hi; otherwise lo < a < hi, shown by range checking
+        // below and above
+        meta.create_gate("catalogue membership", |meta| {
+            let q = meta.query_selector(q_member);
+            let a = meta.query_advice(psi.set_a, Rotation::cur());
+            let active = meta.query_advice(psi.a_active, Rotation::cur());
+            let member = meta.query_advice(psi.match_bit, Rotation::cur());
+            let lo = meta.query_advice(gap_lo, Rotation::cur());
+            let hi = meta.query_advice(gap_hi, Rotation::cur());
+            let below = meta.query_advice(below, Rotation::cur());
+            let above = meta.query_advice(above, Rotation::cur());
+            let prev_sum = meta.query_advice(psi.prev_sum, Rotation::cur());
+            let sum = meta.query_advice(psi.sum, Rotation::cur());
+            let one = Expression::Constant(Fp::one());
+            let outside = active.clone() * (one.clone() - member.clone());
+
+            vec![
+                q.clone() * member.clone() * (member.clone() - one.clone()),
+                q.clone() * active.clone() * member.clone() * (a.clone() - hi.clone()),
+                q.clone() * (below - outside.clone() * (a.clone() - lo - one.clone())),
+                q.clone()
+                    * (above
+                        - outside * (hi - a.clone() - one.clone())
+                        - active.clone() * member.clone() * (bound - one - a)),
+                q * (sum - prev_sum - active * member),
+            ]
+        });
+
+        // Every active slot's neighbours are a pair of the catalogue; inactive
+        // slots look up the all-zero row
+        meta.lookup(|meta| {
+            let q = meta.query_selector(q_member);
+            let active = meta.query_advice(psi.a_active, Rotation::cur());
+            let lo = meta.query_advice(gap_lo, Rotation::cur());
+            let hi = meta.query_advice(gap_hi, Rotation::cur());
+            let enabled = q * active;
+
+            vec![
+                (enabled.clone(), table_tag),
+                (enabled.clone() * lo, table_lo),
+                (enabled * hi, table_hi),
+            ]
+        });
+
+        Self {
+            psi,
+            gap_lo,
+            gap_hi,
+            below,
+            above,
+            q_member,
+            table_tag,
+            table_lo,
+            table_hi,
+            bytes,
+        }
+    }
+
+    /// Load the byte table and the neighbouring pairs of the sorted catalogue
+    pub fn load_tables(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        catalogue: &[Fp],
+    ) -> Result<(), Error> {
+        self.bytes.load(layouter.namespace(|| "bytes"))?;
+
+        layouter.assign_table(
+            || "catalogue",
+            |mut table| {
+                let rows = std::iter::once((Fp::zero(), Fp::zero(), Fp::zero()))
+                    .chain(catalogue_gaps(catalogue).map(|(lo, hi)| (Fp::one(), lo, hi)));
+                for (offset, (tag, lo, hi)) in rows.enumerate() {
+                    table.assign_cell(|| "tag", self.table_tag, offset, || Value::known(tag))?;
+                    table.assign_cell(|| "lo", self.table_lo, offset, || Value::known(lo))?;
+                    table.assign_cell(|| "hi", self.table_hi, offset, || Value::known(hi))?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Derive the catalogue membership bit of every slot of A and return the
+    /// number of members
+    pub fn assign_membership(
+        &self,
+        layouter: impl Layouter<Fp>,
+        set: &AssignedSet,
+        catalogue: &[Fp],
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        self.assign_membership_with(layouter, set, catalogue, |_, witness| witness)
+    }
+
+    /// Assign the membership rows, letting `witness` rewrite the honest
+    /// `(member, lo, hi)` witness of any active slot
+    fn assign_membership_with(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        set: &AssignedSet,
+        catalogue: &[Fp],
+        witness: impl Fn(usize, (bool, Fp, Fp)) -> (bool, Fp, Fp),
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let sorted = sorted_catalogue(catalogue);
+        let mut count: Option<AssignedCell<Fp, Fp>> = None;
+
+        for (i, slot) in set.slots.iter().enumerate() {
+            let (sum, below, above) = layouter.assign_region(
+                || format!("membership {}", i),
+                |mut region| {
+                    self.q_member.enable(&mut region, 0)?;
+
+                    let a = slot
+                        .value
+                        .copy_advice(|| "a", &mut region, self.psi.set_a, 0)?;
+                    let active =
+                        slot.active
+                            .copy_advice(|| "active", &mut region, self.psi.a_active, 0)?;
+                    let prev_sum = match &count {
+                        Some(prev) => {
+                            prev.copy_advice(|| "prev sum", &mut region, self.psi.prev_sum, 0)?
+                        }
+                        None => region.assign_advice_from_constant(
+                            || "prev sum",
+                            self.psi.prev_sum,
+                            0,
+                            Fp::zero(),
+                        )?,
+                    };
+
+                    let witness = a.value().zip(active.value()).map(|(a, active)| {
+                        if *active == Fp::zero() {
+                            (false, Fp::zero(), Fp::zero())
+                        } else {
+                            witness(i, catalogue_neighbours(&sorted, a))
+                        }
+                    });
+                    let member = witness.map(|(member, _, _)| Fp::from(member as u64));
+                    let lo = witness.map(|(_, lo, _)| lo);
+                    let hi = witness.map(|(_, _, hi)| hi);
+
+                    region.assign_advice(|| "member", self.psi.match_bit, 0, || member)?;
+                    region.assign_advice(|| "lo", self.gap_lo, 0, || lo)?;
+                    region.assign_advice(|| "hi", self.gap_hi, 0, || hi)?;
+
+                    let one = Value::known(Fp::one());
+                    let outside = active.value().copied() * (one - member);
+                    let below = region.assign_advice(
+                        || "below",
+                        self.below,
+                        0,
+                        || outside * (a.value().copied() - lo - one),
+                    )?;
+                    let above = region.assign_advice(
+                        || "above",
+                        self.above,
+                        0,
+                        || {
+                            outside * (hi - a.value() - one)
+                                + active.value().copied()
+                                    * member
+                                    * (Value::known(element_bound()) - one - a.value())
+                        },
+                    )?;
+                    let sum = region.assign_advice(
+                        || "sum",
+                        self.psi.sum,
+                        0,
+                        || prev_sum.value().copied() + active.value().copied() * member,
+                    )?;
+
+                    Ok((sum, below, above))
+                },
+            )?;
+
+            self.bytes.assert_bytes(
+                layouter.namespace(|| format!("below {}", i)),
+                &below,
+                ELEMENT_BITS / 8,
+            )?;
+            self.bytes.assert_bytes(
+                layouter.namespace(|| format!("above {}", i)),
+                &above,
+                ELEMENT_BITS / 8,
+            )?;
+            count = Some(sum);
+        }
+
+        Ok(count.expect("sets have at least one slot"))
+    }
+}
+
+/// `2^ELEMENT_BITS`, the upper sentinel of the catalogue
+pub(crate) fn element_bound() -> Fp {
+    Fp::from(2).pow_vartime([ELEMENT_BITS as u64])
+}
+
+/// Whether all values are below `2^ELEMENT_BITS`
+pub(crate) fn within_element_bits<'a>(mut values: impl Iterator<Item = &'a Fp>) -> bool {
+    let bound = element_key(&element_bound());
+    values.all(|x| element_key(x) < bound)
+}
+
+/// Big-endian bytes of a field element, which order like the integers
+pub(crate) fn element_key(value: &Fp) -> Vec<u8> {
+    value.to_repr().as_ref().iter().rev().copied().collect()
+}
+
+/// The distinct catalogue elements in ascending order
+pub(crate) fn sorted_catalogue(catalogue: &[Fp]) -> Vec<Fp> {
+    let mut sorted = catalogue.to_vec();
+    sorted.sort_by_key(element_key);
+    sorted.dedup();
+    sorted
+}
+
+/// Pairs of neighbouring elements of the sorted catalogue, from
+/// `(-1, first)` to `(last, 2^ELEMENT_BITS)`
+fn catalogue_gaps(catalogue: &[Fp]) -> impl Iterator<Item = (Fp, Fp)> {
+    let sorted = sorted_catalogue(catalogue);
+    let lower = std::iter::once(-Fp::one()).chain(sorted.clone());
+    let upper = sorted.into_iter().chain(std::iter::once(element_bound()));
+    lower.zip(upper)
+}
+
+/// Membership of `value` in the sorted catalogue, with the neighbouring pair
+/// proving it
+pub(crate) fn catalogue_neighbours(sorted: &[Fp], value: &Fp) -> (bool, Fp, Fp) {
+    let position = sorted.partition_point(|x| element_key(x) < element_key(value));
+    let member = sorted.get(position) == Some(value);
+    let lo = position
+        .checked_sub(1)
+        .map_or(-Fp::one(), |previous| sorted[previous]);
+    let hi = sorted.get(position).copied().unwrap_or_else(element_bound);
+    (member, lo, hi)
+}
+
+/// PSI circuit for a small private set A against a large catalogue B, using a
+/// lookup argument instead of the comparison grid
+///
+/// Rows scale with `|A| * ELEMENT_BITS / 8 + |B|` instead of `|A| * |B|`, so a
+/// small set can be checked against thousands of catalogue entries. The
+/// maximum size of A and the catalogue are part of the circuit shape: the
+/// catalogue is loaded into fixed tables at key generation, so the keys from
+/// [`setup_lookup`] are specific to it and it is visible to anyone holding
+/// them. Use [`PsiCircuit`] when B must stay private.
+///
+/// Sets must not contain duplicates, and elements must be below
+/// `2^ELEMENT_BITS`. The public inputs are the intersection size and the
+/// commitment to A, at [`INTERSECTION_SIZE_ROW`] and [`COMMITMENT_A_ROW`]; the
+/// proof is created and checked with [`generate_proof`] and [`verify_proof`].
+///
+/// [`setup_lookup`]: crate::setup_lookup
+/// [`generate_proof`]: crate::generate_proof
+/// [`verify_proof`]: crate::verify_proof
+#[derive(Debug, Clone)]
+pub struct LookupPsiCircuit {
+    /// Maximum size of set A
+    pub max_size: usize,
+    /// Private set of hashed elements
+    pub set_a: Vec<Fp>,
+    /// Catalogue of hashed elements, loaded into the lookup table
+    pub catalogue: Vec<Fp>,
+    /// Expected intersection size (public input)
+    pub intersection_size: u64,
+    /// Blinding salt for the commitment to set A
+    pub salt_a: Fp,
+}
+
+impl Default for LookupPsiCircuit {
+    fn default() -> Self {
+        Self::empty(MAX_SET_SIZE, vec![])
+    }
+}
+
+impl LookupPsiCircuit {
+    /// Create a new lookup circuit with a fresh random commitment salt
+    pub fn new(
+        set_a: Vec<Fp>,
+        catalogue: Vec<Fp>,
+        intersection_size: u64,
+    ) -> Result<Self, CircuitError> {
+        Self::new_with_salt(set_a, catalogue, intersection_size, Fp::random(OsRng))
+    }
+
+    /// Create a new lookup circuit with the salt of a previously published
+    /// commitment to A
+    pub fn new_with_salt(
+        set_a: Vec<Fp>,
+        catalogue: Vec<Fp>,
+        intersection_size: u64,
+        salt_a: Fp,
+    ) -> Result<Self, CircuitError> {
+        Self::new_with_capacity(MAX_SET_SIZE, set_a, catalogue, intersection_size, salt_a)
+    }
+
+    /// Create a new lookup circuit laid out for a set A of up to `max_size`
+    /// elements, with the salt of a previously published commitment to A
+    ///
+    /// Fails if A exceeds `max_size` or an element is not below
+    /// `2^ELEMENT_BITS`.
+    pub fn new_with_capacity(
+        max_size: usize,
+        set_a: Vec<Fp>,
+        catalogue: Vec<Fp>,
+        intersection_size: u64,
+        salt_a: Fp,
+    ) -> Result<Self, CircuitError> {
+        check_max_size(max_size)?;
+        check_set_size("A", &set_a, max_size)?;
+        check_element_bits("A", &set_a)?;
+        check_element_bits("catalogue", &catalogue)?;
+
+        Ok(Self {
+            set_a,
+            intersection_size,
+            salt_a,
+            ..Self::empty(max_size, catalogue)
+        })
+    }
+
+    /// Shape of a circuit over a set A of up to `max_size` elements against
+    /// `catalogue`, e.g. for key generation
+    pub fn empty(max_size: usize, catalogue: Vec<Fp>) -> Self {
+        Self {
+            max_size,
+            set_a: vec![],
+            catalogue,
+            intersection_size: 0,
+            salt_a: Fp::zero(),
+        }
+    }
+
+    /// Compute the intersection size natively (for witness generation)
+    pub fn compute_intersection_size(&self) -> u64 {
+        IntersectionSemantics::Set.intersection_size(&self.set_a, &self.catalogue)
+    }
+
+    /// Commitment to set A, matching the public input
+    pub fn commitment_a(&self) -> Fp {
+        commit_set_with_capacity(&self.set_a, self.salt_a, self.max_size)
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        vec![Fp::from(self.intersection_size), self.commitment_a()]
+    }
+}
+
+impl Circuit<Fp> for LookupPsiCircuit {
+    type Config = LookupPsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.max_size, self.catalogue.clone())
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        LookupPsiConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.synthesize_with(&config, layouter, |_, witness| witness)
+    }
+}
+
+impl LookupPsiCircuit {
+    /// Lay out the circuit, letting `witness` rewrite the membership witness
+    /// (see [`LookupPsiConfig::assign_membership_with`])
+    pub(crate) fn synthesize_with(
+        &self,
+        config: &LookupPsiConfig,
+        mut layouter: impl Layouter<Fp>,
+        witness: impl Fn(usize, (bool, Fp, Fp)) -> (bool, Fp, Fp),
+    ) -> Result<(), Error> {
+        // The fields are public, so the constructors cannot rule this out alone
+        if check_max_size(self.max_size).is_err() {
+            return Err(Error::Synthesis);
+        }
+        config.load_tables(layouter.namespace(|| "tables"), &self.catalogue)?;
+
+        let psi = &config.psi;
+        let slots = PsiCircuit::padded_slots(&self.set_a, self.max_size);
+        let set_a = psi.assign_set(
+            layouter.namespace(|| "set A"),
+            &slots,
+            &vec![false; self.max_size],
+            &vec![Fp::zero(); self.max_size],
+            true,
+        )?;
+        psi.assert_distinct(layouter.namespace(|| "distinct A"), &set_a)?;
+        let commitment_a = psi.commit_set(
+            layouter.namespace(|| "commit set A"),
+            &set_a,
+            Value::known(self.salt_a),
+        )?;
+
+        let count = config.assign_membership_with(
+            layouter.namespace(|| "membership"),
+            &set_a,
+            &self.catalogue,
+            witness,
+        )?;
+
+        layouter.constrain_instance(count.cell(), psi.instance, INTERSECTION_SIZE_ROW)?;
+        layouter.constrain_instance(commitment_a.cell(), psi.instance, COMMITMENT_A_ROW)
+    }
+}
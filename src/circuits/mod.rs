@@ -0,0 +1,208 @@
+//! The PSI circuits, their set capacities and witness validation
+
+pub(crate) mod chunk;
+pub(crate) mod disjoint;
+pub(crate) mod grid;
+pub(crate) mod lookup;
+pub(crate) mod multi;
+pub(crate) mod psi;
+pub(crate) mod range;
+pub(crate) mod sort;
+pub(crate) mod subset;
+
+pub use chunk::{PsiChunkCircuit, PsiChunkConfig};
+pub use disjoint::DisjointnessCircuit;
+pub use grid::{AssignedComparison, AssignedSet, AssignedSlot, PsiConfig};
+pub use lookup::{LookupPsiCircuit, LookupPsiConfig};
+pub use multi::MultiPsiCircuit;
+pub use psi::{PackedPsiCircuit, PsiCircuit, ThresholdPsiCircuit};
+pub use range::ByteRangeConfig;
+pub use sort::{
+    AssignedChallenges, AssignedEntry, AssignedMerged, MAX_MERGED_SIZE, SortPsiCircuit,
+    SortPsiConfig,
+};
+pub use subset::SubsetCircuit;
+
+use crate::circuits::lookup::within_element_bits;
+use crate::{ELEMENT_BITS, MAX_PARTIES, MAX_SET_CAPACITY, MAX_SET_SIZE, range_bits};
+use pasta_curves::Fp;
+use serde::{Deserialize, Serialize};
+
+/// Maximum sizes of the two sets of a [`PsiCircuit`]
+///
+/// The capacity is part of the circuit shape: it fixes the number of slots of
+/// each set, hence the size of the comparison grid and the commitments (see
+/// [`commit_set_with_capacity`]). Keys made for one capacity do not verify
+/// proofs made for another, and [`min_k`] of [`PsiCircuit::empty`] gives the
+/// smallest `k` that holds it.
+///
+/// [`commit_set_with_capacity`]: crate::commit_set_with_capacity
+/// [`min_k`]: crate::min_k
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetCapacity {
+    /// Maximum size of set A
+    pub max_a: usize,
+    /// Maximum size of set B
+    pub max_b: usize,
+}
+
+impl SetCapacity {
+    /// Capacity for sets A and B of up to `max_a` and `max_b` elements
+    ///
+    /// Fails with [`CircuitError::InvalidCapacity`] unless both sizes lie in
+    /// `1..=MAX_SET_CAPACITY`.
+    pub fn new(max_a: usize, max_b: usize) -> Result<Self, CircuitError> {
+        check_max_size(max_a)?;
+        check_max_size(max_b)?;
+        Ok(Self { max_a, max_b })
+    }
+
+    /// Check that `set_a` and `set_b` fit this capacity
+    fn check_sets(&self, set_a: &[Fp], set_b: &[Fp]) -> Result<(), CircuitError> {
+        check_set_size("A", set_a, self.max_a)?;
+        check_set_size("B", set_b, self.max_b)
+    }
+
+    /// Bits needed to range check a difference of intersection sizes, which
+    /// are bounded by the smaller maximum size
+    fn count_bits(&self) -> usize {
+        range_bits(self.max_a.min(self.max_b))
+    }
+}
+
+impl Default for SetCapacity {
+    fn default() -> Self {
+        Self {
+            max_a: MAX_SET_SIZE,
+            max_b: MAX_SET_SIZE,
+        }
+    }
+}
+
+/// Reasons a circuit cannot be built over the given sets and options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CircuitError {
+    /// A maximum set size is zero or above the limit of the circuit
+    InvalidCapacity {
+        /// The rejected maximum size
+        max: usize,
+        /// Largest maximum size the circuit supports
+        limit: usize,
+    },
+    /// A set holds more elements than its maximum size
+    SetTooLarge {
+        /// Name of the set, e.g. `"A"` or `"blocklist"`
+        set: String,
+        /// Number of elements in the set
+        size: usize,
+        /// Maximum size of the set
+        max: usize,
+    },
+    /// An element of the named set is not below `2^ELEMENT_BITS`
+    ElementOutOfRange(String),
+    /// The number of sets of a [`MultiPsiCircuit`] is not in
+    /// `2..=MAX_PARTIES`
+    InvalidPartyCount(usize),
+    /// The threshold of a [`MultiPsiCircuit`] is not in `1..=parties`
+    InvalidThreshold {
+        /// The rejected threshold
+        threshold: usize,
+        /// Number of sets
+        parties: usize,
+    },
+    /// There are not as many salts or values as sets or elements
+    LengthMismatch {
+        /// What is counted, e.g. `"salts"`
+        items: &'static str,
+        /// Expected number of items
+        expected: usize,
+        /// Number of items given
+        found: usize,
+    },
+    /// Weighted sums are requested under multiset semantics
+    WeightedMultiset,
+}
+
+impl std::fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCapacity { max, limit } => {
+                write!(f, "Maximum set size {} is not between 1 and {}", max, limit)
+            }
+            Self::SetTooLarge { set, size, max } => write!(
+                f,
+                "Set {} has {} elements, more than its maximum size {}",
+                set, size, max
+            ),
+            Self::ElementOutOfRange(set) => {
+                write!(f, "Set {} has an element not below 2^{}", set, ELEMENT_BITS)
+            }
+            Self::InvalidPartyCount(parties) => write!(
+                f,
+                "{} sets given, between 2 and {} are supported",
+                parties, MAX_PARTIES
+            ),
+            Self::InvalidThreshold { threshold, parties } => write!(
+                f,
+                "Threshold {} is not between 1 and the {} sets",
+                threshold, parties
+            ),
+            Self::LengthMismatch {
+                items,
+                expected,
+                found,
+            } => write!(f, "Expected {} {}, got {}", expected, items, found),
+            Self::WeightedMultiset => write!(f, "Weighted sums need set semantics"),
+        }
+    }
+}
+
+impl std::error::Error for CircuitError {}
+
+/// Check that a maximum set size lies in `1..=MAX_SET_CAPACITY`
+pub(crate) fn check_max_size(max: usize) -> Result<(), CircuitError> {
+    if (1..=MAX_SET_CAPACITY).contains(&max) {
+        Ok(())
+    } else {
+        Err(CircuitError::InvalidCapacity {
+            max,
+            limit: MAX_SET_CAPACITY,
+        })
+    }
+}
+
+/// Check that both maximum sizes of a [`SortPsiCircuit`] are positive and
+/// together at most [`MAX_MERGED_SIZE`]
+pub(crate) fn check_merged_size(capacity: SetCapacity) -> Result<(), CircuitError> {
+    let merged = capacity.max_a + capacity.max_b;
+    if capacity.max_a == 0 || capacity.max_b == 0 || merged > MAX_MERGED_SIZE {
+        return Err(CircuitError::InvalidCapacity {
+            max: merged,
+            limit: MAX_MERGED_SIZE,
+        });
+    }
+    Ok(())
+}
+
+/// Check that the set named `name` holds at most `max` elements
+pub(crate) fn check_set_size(name: &str, set: &[Fp], max: usize) -> Result<(), CircuitError> {
+    if set.len() <= max {
+        Ok(())
+    } else {
+        Err(CircuitError::SetTooLarge {
+            set: name.to_string(),
+            size: set.len(),
+            max,
+        })
+    }
+}
+
+/// Check that the elements of the set named `name` are below
+/// `2^ELEMENT_BITS`
+pub(crate) fn check_element_bits(name: &str, set: &[Fp]) -> Result<(), CircuitError> {
+    if within_element_bits(set.iter()) {
+        Ok(())
+    } else {
+        Err(CircuitError::ElementOutOfRange(name.to_string()))
+    }
+}
@@ -0,0 +1,261 @@
+//! Circuit over the sets of more than two parties
+
+use crate::circuits::{check_max_size, check_set_size};
+use crate::{
+    CircuitError, MAX_PARTIES, MAX_SET_SIZE, MULTI_COMMITMENTS_ROW, MULTI_COUNT_ROW, PsiCircuit,
+    PsiConfig, commit_set_with_capacity, multi_intersection_size,
+};
+use ff::Field;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use pasta_curves::Fp;
+use rand::rngs::OsRng;
+
+/// Circuit counting the elements shared by several private sets
+///
+/// Every pair of sets is compared on its own grid, whose maximal matching
+/// tells for every slot whether the other set holds its element. A tally over
+/// those flags then counts, for every distinct element of the union, the sets
+/// holding it, and adds the element to the count if it is held by at least
+/// `threshold` sets. An element is only tallied in the first set holding it.
+///
+/// Sets must not contain duplicates. The public inputs are the count at
+/// [`MULTI_COUNT_ROW`] and the commitments to the sets, in order, from
+/// [`MULTI_COMMITMENTS_ROW`]. The maximum set size, the number of sets and the
+/// threshold are part of the circuit shape, so the keys must come from
+/// [`setup_multi`] with the same values.
+///
+/// [`setup_multi`]: crate::setup_multi
+#[derive(Debug, Clone)]
+pub struct MultiPsiCircuit {
+    /// Maximum size of every set
+    pub max_size: usize,
+    /// Sets of hashed elements, one per party
+    pub sets: Vec<Vec<Fp>>,
+    /// Blinding salts for the commitments to the sets
+    pub salts: Vec<Fp>,
+    /// Minimum number of sets an element must be in to be counted
+    pub threshold: usize,
+    /// Expected count (public input)
+    pub intersection_size: u64,
+}
+
+impl MultiPsiCircuit {
+    /// Create a circuit counting the elements held by all sets, with fresh
+    /// random commitment salts
+    pub fn new(sets: Vec<Vec<Fp>>, intersection_size: u64) -> Result<Self, CircuitError> {
+        let salts = sets.iter().map(|_| Fp::random(OsRng)).collect();
+        Self::new_with_salts(sets, salts, intersection_size)
+    }
+
+    /// Create a circuit counting the elements held by all sets, with the
+    /// salts of previously published commitments
+    pub fn new_with_salts(
+        sets: Vec<Vec<Fp>>,
+        salts: Vec<Fp>,
+        intersection_size: u64,
+    ) -> Result<Self, CircuitError> {
+        Self::new_with_capacity(MAX_SET_SIZE, sets, salts, intersection_size)
+    }
+
+    /// Create a circuit laid out for sets of up to `max_size` elements, with
+    /// the salts of previously published commitments
+    ///
+    /// Fails unless there are between 2 and [`MAX_PARTIES`] sets, each with a
+    /// salt and at most `max_size` elements.
+    pub fn new_with_capacity(
+        max_size: usize,
+        sets: Vec<Vec<Fp>>,
+        salts: Vec<Fp>,
+        intersection_size: u64,
+    ) -> Result<Self, CircuitError> {
+        check_max_size(max_size)?;
+        if !(2..=MAX_PARTIES).contains(&sets.len()) {
+            return Err(CircuitError::InvalidPartyCount(sets.len()));
+        }
+        if salts.len() != sets.len() {
+            return Err(CircuitError::LengthMismatch {
+                items: "salts",
+                expected: sets.len(),
+                found: salts.len(),
+            });
+        }
+        for (i, set) in sets.iter().enumerate() {
+            check_set_size(&i.to_string(), set, max_size)?;
+        }
+
+        Ok(Self {
+            max_size,
+            threshold: sets.len(),
+            sets,
+            salts,
+            intersection_size,
+        })
+    }
+
+    /// Count the elements held by at least `threshold` of the sets instead
+    pub fn with_threshold(mut self, threshold: usize) -> Result<Self, CircuitError> {
+        if !(1..=self.sets.len()).contains(&threshold) {
+            return Err(CircuitError::InvalidThreshold {
+                threshold,
+                parties: self.sets.len(),
+            });
+        }
+        self.threshold = threshold;
+        Ok(self)
+    }
+
+    /// Shape of a circuit over `parties` sets of up to `max_size` elements
+    /// counting the elements held by at least `threshold` of them, e.g. for
+    /// key generation
+    ///
+    /// An unsupported shape fails at synthesis.
+    pub fn empty(max_size: usize, parties: usize, threshold: usize) -> Self {
+        Self {
+            max_size,
+            sets: vec![vec![]; parties],
+            salts: vec![Fp::zero(); parties],
+            threshold,
+            intersection_size: 0,
+        }
+    }
+
+    /// Compute the count natively (for witness generation)
+    pub fn compute_intersection_size(&self) -> u64 {
+        multi_intersection_size(&self.sets, self.threshold)
+    }
+
+    /// Commitments to the sets, matching the public inputs
+    pub fn commitments(&self) -> Vec<Fp> {
+        self.sets
+            .iter()
+            .zip(&self.salts)
+            .map(|(set, salt)| commit_set_with_capacity(set, *salt, self.max_size))
+            .collect()
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        std::iter::once(Fp::from(self.intersection_size))
+            .chain(self.commitments())
+            .collect()
+    }
+}
+
+impl Circuit<Fp> for MultiPsiCircuit {
+    type Config = PsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.max_size, self.sets.len(), self.threshold)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        // The fields are public, so the constructors cannot rule this out alone
+        let parties = self.sets.len();
+        if check_max_size(self.max_size).is_err()
+            || !(2..=MAX_PARTIES).contains(&parties)
+            || !(1..=parties).contains(&self.threshold)
+            || self.salts.len() != parties
+        {
+            return Err(Error::Synthesis);
+        }
+
+        let padded: Vec<_> = self
+            .sets
+            .iter()
+            .map(|set| PsiCircuit::padded_slots(set, self.max_size))
+            .collect();
+        let no_weights = vec![Fp::zero(); self.max_size];
+
+        // Commit to every set once
+        let mut sets = vec![];
+        for (k, (slots, salt)) in padded.iter().zip(&self.salts).enumerate() {
+            let set = config.assign_set(
+                layouter.namespace(|| format!("set {}", k)),
+                slots,
+                &vec![false; self.max_size],
+                &no_weights,
+                true,
+            )?;
+            config.assert_distinct(layouter.namespace(|| format!("distinct {}", k)), &set)?;
+            let commitment = config.commit_set(
+                layouter.namespace(|| format!("commit set {}", k)),
+                &set,
+                Value::known(*salt),
+            )?;
+            layouter.constrain_instance(
+                commitment.cell(),
+                config.instance,
+                MULTI_COMMITMENTS_ROW + k,
+            )?;
+            sets.push(set);
+        }
+
+        // Compare every pair; presence[k][k'][i] tells whether set k' holds
+        // the element in slot i of set k
+        let mut presence = vec![vec![vec![]; parties]; parties];
+        for k in 0..parties {
+            for other in k + 1..parties {
+                let (picks, used_a, used_b) = PsiCircuit::matching(&padded[k], &padded[other]);
+                let copy_a = config.assign_set(
+                    layouter.namespace(|| format!("pair {} {}: set {}", k, other, k)),
+                    &padded[k],
+                    &used_a,
+                    &no_weights,
+                    true,
+                )?;
+                let copy_b = config.assign_set(
+                    layouter.namespace(|| format!("pair {} {}: set {}", k, other, other)),
+                    &padded[other],
+                    &used_b,
+                    &no_weights,
+                    false,
+                )?;
+                config.constrain_same_set(layouter.namespace(|| "same set"), &sets[k], &copy_a)?;
+                config.constrain_same_set(
+                    layouter.namespace(|| "same set"),
+                    &sets[other],
+                    &copy_b,
+                )?;
+                config.assign_grid(
+                    layouter.namespace(|| format!("grid {} {}", k, other)),
+                    &copy_a,
+                    &copy_b,
+                    &picks,
+                )?;
+
+                presence[k][other] = copy_a.slots.iter().map(|slot| slot.used.clone()).collect();
+                presence[other][k] = copy_b.slots.iter().map(|slot| slot.used.clone()).collect();
+            }
+        }
+
+        let mut total = None;
+        for (k, set) in sets.iter().enumerate() {
+            for (i, slot) in set.slots.iter().enumerate() {
+                let others: Vec<_> = (0..parties)
+                    .filter(|other| *other != k)
+                    .map(|other| (presence[k][other][i].clone(), other < k))
+                    .collect();
+                total = Some(config.assign_tally(
+                    layouter.namespace(|| format!("tally {} {}", k, i)),
+                    &slot.active,
+                    &others,
+                    self.threshold as u64,
+                    total.as_ref(),
+                )?);
+            }
+        }
+
+        let total = total.expect("at least two sets");
+        layouter.constrain_instance(total.cell(), config.instance, MULTI_COUNT_ROW)
+    }
+}
@@ -0,0 +1,587 @@
+//! The two-set PSI circuit and its threshold and packed variants
+
+use crate::circuits::check_max_size;
+use crate::{
+    AssignedSet, CARDINALITIES_ROW, COMMITMENT_A_ROW, COMMITMENT_B_ROW, Cardinalities,
+    CircuitError, INTERSECTION_SIZE_ROW, IntersectionSemantics, PsiConfig, SetCapacity,
+    THRESHOLD_ROW, VALUE_BITS, WEIGHTED_SUM_ROW, commit_set_with_capacity,
+    commit_weighted_set_with_capacity,
+};
+use ff::Field;
+use halo2_proofs::circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use pasta_curves::Fp;
+use rand::rngs::OsRng;
+
+/// PSI Circuit structure
+///
+/// The circuit always lays out `max_a` slots for set A, `max_b` slots for set
+/// B and `max_a x max_b` comparison rows (see [`SetCapacity`]), regardless of
+/// how many elements the sets actually hold. Unused slots are padded and
+/// flagged as inactive, so the keys generated from [`PsiCircuit::empty`]
+/// constrain every proof up to the maximum set sizes.
+///
+/// The grid counts a maximal matching between equal elements, i.e. the
+/// multiset intersection size. Under [`IntersectionSemantics::Set`] the circuit
+/// additionally rejects duplicated elements, so the count is the size of the
+/// set intersection. The semantics is part of the circuit shape: keys made for
+/// one semantics do not verify proofs made under the other.
+///
+/// Besides the intersection size, the circuit exposes a salted Poseidon
+/// commitment to each set (see [`commit_set`]), which ties a proof to sets the
+/// prover published beforehand.
+///
+/// With private values attached to set B (see [`PsiCircuit::with_values_b`])
+/// the circuit is weighted: it exposes `Σ value_j · match_bit_ij` over the
+/// whole grid instead of the count, range checks every value to
+/// [`VALUE_BITS`] bits so the sum cannot overflow, and commits to the values
+/// along with set B (see [`commit_weighted_set`]). Whether values are present
+/// is part of the circuit shape. Weighted circuits use set semantics: with
+/// repeated elements, which copies the matching pairs would decide the sum.
+///
+/// [`commit_set`]: crate::commit_set
+/// [`commit_weighted_set`]: crate::commit_weighted_set
+#[derive(Debug, Clone, Default)]
+pub struct PsiCircuit {
+    /// First set of hashed elements
+    pub set_a: Vec<Fp>,
+    /// Second set of hashed elements
+    pub set_b: Vec<Fp>,
+    /// Expected intersection size (public input)
+    pub intersection_size: u64,
+    /// Blinding salt for the commitment to set A
+    pub salt_a: Fp,
+    /// Blinding salt for the commitment to set B
+    pub salt_b: Fp,
+    /// How repeated elements are counted
+    pub semantics: IntersectionSemantics,
+    /// Private values of the elements of set B, for a weighted sum
+    pub values_b: Option<Vec<u32>>,
+    /// Whether to also expose |A|, |B|, |A ∩ B| and |A ∪ B|
+    pub expose_cardinalities: bool,
+    /// Maximum set sizes the circuit is laid out for
+    pub capacity: SetCapacity,
+}
+
+impl PsiCircuit {
+    /// Create a new PSI circuit with two sets and fresh random commitment salts
+    pub fn new(
+        set_a: Vec<Fp>,
+        set_b: Vec<Fp>,
+        intersection_size: u64,
+    ) -> Result<Self, CircuitError> {
+        Self::new_with_salts(
+            set_a,
+            set_b,
+            intersection_size,
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+        )
+    }
+
+    /// Create a new PSI circuit with two sets and the salts of previously
+    /// published commitments
+    pub fn new_with_salts(
+        set_a: Vec<Fp>,
+        set_b: Vec<Fp>,
+        intersection_size: u64,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self, CircuitError> {
+        Self::new_with_capacity(
+            SetCapacity::default(),
+            set_a,
+            set_b,
+            intersection_size,
+            salt_a,
+            salt_b,
+        )
+    }
+
+    /// Create a new PSI circuit laid out for sets of up to the sizes of
+    /// `capacity`, with the salts of previously published commitments
+    ///
+    /// Fails with [`CircuitError::SetTooLarge`] if a set exceeds its maximum
+    /// size.
+    pub fn new_with_capacity(
+        capacity: SetCapacity,
+        set_a: Vec<Fp>,
+        set_b: Vec<Fp>,
+        intersection_size: u64,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self, CircuitError> {
+        check_max_size(capacity.max_a)?;
+        check_max_size(capacity.max_b)?;
+        capacity.check_sets(&set_a, &set_b)?;
+
+        Ok(Self {
+            set_a,
+            set_b,
+            intersection_size,
+            salt_a,
+            salt_b,
+            ..Self::empty(capacity)
+        })
+    }
+
+    /// Shape of a circuit over sets of up to the sizes of `capacity`, e.g. for
+    /// key generation
+    pub fn empty(capacity: SetCapacity) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    /// Use the given intersection semantics
+    ///
+    /// Fails with [`CircuitError::WeightedMultiset`] for multiset semantics
+    /// on a weighted circuit.
+    pub fn with_semantics(
+        mut self,
+        semantics: IntersectionSemantics,
+    ) -> Result<Self, CircuitError> {
+        if self.values_b.is_some() && semantics != IntersectionSemantics::Set {
+            return Err(CircuitError::WeightedMultiset);
+        }
+        self.semantics = semantics;
+        Ok(self)
+    }
+
+    /// Commitment to set A under `salt_a`
+    pub fn commitment_a(&self) -> Fp {
+        commit_set_with_capacity(&self.set_a, self.salt_a, self.capacity.max_a)
+    }
+
+    /// Attach a private value to every element of set B, making the circuit
+    /// prove the weighted sum of the matching elements
+    ///
+    /// Only circuits with set semantics can be weighted, and every element of
+    /// B needs a value.
+    pub fn with_values_b(mut self, values: Vec<u32>) -> Result<Self, CircuitError> {
+        if values.len() != self.set_b.len() {
+            return Err(CircuitError::LengthMismatch {
+                items: "values",
+                expected: self.set_b.len(),
+                found: values.len(),
+            });
+        }
+        if self.semantics != IntersectionSemantics::Set {
+            return Err(CircuitError::WeightedMultiset);
+        }
+        self.values_b = Some(values);
+        Ok(self)
+    }
+
+    /// Also expose |A|, |B|, |A ∩ B| and |A ∪ B| as public inputs, starting at
+    /// [`CARDINALITIES_ROW`]
+    pub fn with_cardinalities(mut self) -> Self {
+        self.expose_cardinalities = true;
+        self
+    }
+
+    /// Compute the set sizes this circuit exposes (for witness generation)
+    pub fn cardinalities(&self) -> Cardinalities {
+        let size_a = self.set_a.len() as u64;
+        let size_b = self.set_b.len() as u64;
+        let intersection = self.compute_intersection_size();
+        Cardinalities {
+            size_a,
+            size_b,
+            intersection,
+            union: size_a + size_b - intersection,
+        }
+    }
+
+    /// Commitment to set B under `salt_b`, including its values if weighted
+    pub fn commitment_b(&self) -> Fp {
+        let capacity = self.capacity.max_b;
+        match &self.values_b {
+            Some(values) => {
+                commit_weighted_set_with_capacity(&self.set_b, values, self.salt_b, capacity)
+            }
+            None => commit_set_with_capacity(&self.set_b, self.salt_b, capacity),
+        }
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    ///
+    /// A weighted circuit exposes [`PsiCircuit::compute_weighted_sum`] in place
+    /// of `intersection_size`, and the cardinalities are appended if exposed.
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        let output = match self.values_b {
+            Some(_) => self.compute_weighted_sum(),
+            None => self.intersection_size,
+        };
+        let mut public_inputs = vec![Fp::from(output), self.commitment_a(), self.commitment_b()];
+        if self.expose_cardinalities {
+            public_inputs.extend(self.cardinalities().to_public_inputs());
+        }
+        public_inputs
+    }
+
+    /// Compute the actual intersection size (for witness generation)
+    pub fn compute_intersection_size(&self) -> u64 {
+        self.semantics.intersection_size(&self.set_a, &self.set_b)
+    }
+
+    /// Compute `Σ value_j · [a_i == b_j]` over all pairs of elements, or zero
+    /// without values (for witness generation)
+    ///
+    /// Under set semantics every element matches at most once, so this is the
+    /// sum of the values of the elements of B that are also in A.
+    pub fn compute_weighted_sum(&self) -> u64 {
+        let Some(values) = &self.values_b else {
+            return 0;
+        };
+
+        self.set_a
+            .iter()
+            .flat_map(|a| {
+                self.set_b
+                    .iter()
+                    .zip(values)
+                    .filter(move |(b, _)| *b == a)
+                    .map(|(_, value)| *value as u64)
+            })
+            .sum()
+    }
+
+    /// Values of the set B slots, padded with zeros
+    fn padded_values_b(&self) -> Vec<Fp> {
+        (0..self.capacity.max_b)
+            .map(|j| {
+                self.values_b
+                    .as_ref()
+                    .and_then(|values| values.get(j))
+                    .map_or(Fp::zero(), |v| Fp::from(*v as u64))
+            })
+            .collect()
+    }
+
+    /// Pad a set to `capacity` slots of (value, active) pairs
+    pub(crate) fn padded_slots(set: &[Fp], capacity: usize) -> Vec<(Fp, bool)> {
+        set.iter()
+            .map(|v| (*v, true))
+            .chain(std::iter::repeat((Fp::zero(), false)))
+            .take(capacity)
+            .collect()
+    }
+
+    /// Greedy maximal matching between equal active slots.
+    ///
+    /// Returns the pick matrix and the usage flags of the A and B slots.
+    pub(crate) fn matching(
+        slots_a: &[(Fp, bool)],
+        slots_b: &[(Fp, bool)],
+    ) -> (Vec<Vec<bool>>, Vec<bool>, Vec<bool>) {
+        let mut picks = vec![vec![false; slots_b.len()]; slots_a.len()];
+        let mut used_a = vec![false; slots_a.len()];
+        let mut used_b = vec![false; slots_b.len()];
+
+        for (i, (a, a_active)) in slots_a.iter().enumerate() {
+            for (j, (b, b_active)) in slots_b.iter().enumerate() {
+                if *a_active && *b_active && a == b && !used_a[i] && !used_b[j] {
+                    picks[i][j] = true;
+                    used_a[i] = true;
+                    used_b[j] = true;
+                }
+            }
+        }
+
+        (picks, used_a, used_b)
+    }
+}
+
+impl Circuit<Fp> for PsiCircuit {
+    type Config = PsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            semantics: self.semantics,
+            values_b: self.values_b.as_ref().map(|_| vec![]),
+            expose_cardinalities: self.expose_cardinalities,
+            capacity: self.capacity,
+            ..Self::default()
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let cells = self.synthesize_intersection(&config, layouter.namespace(|| "psi"))?;
+
+        // Expose the final sum (or weighted sum) and the set commitments as
+        // public inputs
+        match self.values_b {
+            Some(_) => layouter.constrain_instance(
+                cells.weighted.cell(),
+                config.instance,
+                WEIGHTED_SUM_ROW,
+            )?,
+            None => layouter.constrain_instance(
+                cells.sum.cell(),
+                config.instance,
+                INTERSECTION_SIZE_ROW,
+            )?,
+        }
+
+        if self.expose_cardinalities {
+            let union = config.assign_union(
+                layouter.namespace(|| "union"),
+                &cells.set_a.size,
+                &cells.set_b.size,
+                &cells.sum,
+            )?;
+            for (offset, cell) in [&cells.set_a.size, &cells.set_b.size, &cells.sum, &union]
+                .into_iter()
+                .enumerate()
+            {
+                layouter.constrain_instance(
+                    cell.cell(),
+                    config.instance,
+                    CARDINALITIES_ROW + offset,
+                )?;
+            }
+        }
+
+        cells.expose_commitments(&config, layouter)
+    }
+}
+
+/// Cells of a synthesized intersection that variants of the circuit expose
+pub(crate) struct IntersectionCells {
+    pub(crate) set_a: AssignedSet,
+    pub(crate) set_b: AssignedSet,
+    pub(crate) sum: AssignedCell<Fp, Fp>,
+    weighted: AssignedCell<Fp, Fp>,
+    pub(crate) commitment_a: AssignedCell<Fp, Fp>,
+    pub(crate) commitment_b: AssignedCell<Fp, Fp>,
+}
+
+impl IntersectionCells {
+    fn expose_commitments(
+        &self,
+        config: &PsiConfig,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        layouter.constrain_instance(self.commitment_a.cell(), config.instance, COMMITMENT_A_ROW)?;
+        layouter.constrain_instance(self.commitment_b.cell(), config.instance, COMMITMENT_B_ROW)
+    }
+}
+
+impl PsiCircuit {
+    /// Lay out both sets, their commitments and the comparison grid
+    pub(crate) fn synthesize_intersection(
+        &self,
+        config: &PsiConfig,
+        layouter: impl Layouter<Fp>,
+    ) -> Result<IntersectionCells, Error> {
+        self.synthesize_intersection_from(config, layouter, None)
+    }
+
+    /// Lay out the circuit as [`PsiCircuit::synthesize_intersection`], with
+    /// the match sum continuing from `start` instead of zero
+    pub(crate) fn synthesize_intersection_from(
+        &self,
+        config: &PsiConfig,
+        mut layouter: impl Layouter<Fp>,
+        start: Option<&AssignedCell<Fp, Fp>>,
+    ) -> Result<IntersectionCells, Error> {
+        // The fields are public, so the builders cannot rule this out alone
+        if self.values_b.is_some() && self.semantics != IntersectionSemantics::Set {
+            return Err(Error::Synthesis);
+        }
+
+        let padded_a = Self::padded_slots(&self.set_a, self.capacity.max_a);
+        let padded_b = Self::padded_slots(&self.set_b, self.capacity.max_b);
+        let (picks, used_a, used_b) = Self::matching(&padded_a, &padded_b);
+
+        let slots_a = config.assign_set(
+            layouter.namespace(|| "set A"),
+            &padded_a,
+            &used_a,
+            &vec![Fp::zero(); self.capacity.max_a],
+            true,
+        )?;
+        let slots_b = config.assign_set(
+            layouter.namespace(|| "set B"),
+            &padded_b,
+            &used_b,
+            &self.padded_values_b(),
+            false,
+        )?;
+
+        if self.semantics == IntersectionSemantics::Set {
+            config.assert_distinct(layouter.namespace(|| "distinct A"), &slots_a)?;
+            config.assert_distinct(layouter.namespace(|| "distinct B"), &slots_b)?;
+        }
+
+        let commitment_a = config.commit_set(
+            layouter.namespace(|| "commit set A"),
+            &slots_a,
+            Value::known(self.salt_a),
+        )?;
+        let mut commitment_b = config.commit_set(
+            layouter.namespace(|| "commit set B"),
+            &slots_b,
+            Value::known(self.salt_b),
+        )?;
+
+        if self.values_b.is_some() {
+            for (j, slot) in slots_b.slots.iter().enumerate() {
+                config.assert_range(
+                    layouter.namespace(|| format!("value {}", j)),
+                    &slot.weight,
+                    VALUE_BITS,
+                )?;
+            }
+            commitment_b = config.commit_weights(
+                layouter.namespace(|| "commit values B"),
+                commitment_b,
+                &slots_b,
+            )?;
+        }
+
+        let last = config.assign_grid_with(
+            layouter.namespace(|| "grid"),
+            &slots_a,
+            &slots_b,
+            &picks,
+            start,
+            |_, witness| witness,
+        )?;
+
+        Ok(IntersectionCells {
+            set_a: slots_a,
+            set_b: slots_b,
+            sum: last.sum,
+            weighted: last.weighted,
+            commitment_a,
+            commitment_b,
+        })
+    }
+}
+
+/// PSI circuit proving that the intersection size is at least a public
+/// threshold, without revealing the size itself
+///
+/// The public inputs are the threshold and the two set commitments, in the
+/// same rows as for [`PsiCircuit`]. The circuit range checks
+/// `intersection size - threshold` to the bits of the smaller maximum set
+/// size, which is only possible when the difference is non-negative.
+#[derive(Debug, Clone, Default)]
+pub struct ThresholdPsiCircuit {
+    /// The sets, salts and semantics; `psi.intersection_size` is not used
+    pub psi: PsiCircuit,
+    /// Minimum intersection size (public input)
+    pub threshold: u64,
+}
+
+impl ThresholdPsiCircuit {
+    /// Create a threshold circuit over the sets of `psi`
+    pub fn new(psi: PsiCircuit, threshold: u64) -> Self {
+        Self { psi, threshold }
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        vec![
+            Fp::from(self.threshold),
+            self.psi.commitment_a(),
+            self.psi.commitment_b(),
+        ]
+    }
+}
+
+impl Circuit<Fp> for ThresholdPsiCircuit {
+    type Config = PsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            psi: self.psi.without_witnesses(),
+            threshold: 0,
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let cells = self
+            .psi
+            .synthesize_intersection(&config, layouter.namespace(|| "psi"))?;
+
+        config.assert_at_least(
+            layouter.namespace(|| "threshold"),
+            &cells.sum,
+            THRESHOLD_ROW,
+            self.psi.capacity.count_bits(),
+        )?;
+        cells.expose_commitments(&config, layouter)
+    }
+}
+
+/// PSI circuit packing `LANES` comparisons into every row of the grid
+///
+/// The proof statement and public inputs are those of the wrapped
+/// [`PsiCircuit`], but the grid takes `|A| * ceil(|B| / LANES)` rows instead
+/// of `|A| * |B|`, at the cost of nine advice columns per extra lane (see
+/// [`PsiConfig::configure_with_lanes`]). Fewer rows and regions mean a
+/// smaller `k` and faster keygen and proving, while every extra column adds
+/// an opening to the proof. Rows only shrink once the grid outgrows the
+/// commitment hashing, from about 64 x 64 sets. The lane count is part of the
+/// circuit shape, so the keys must come from [`setup_for`] on a circuit with
+/// the same `LANES`.
+///
+/// [`setup_for`]: crate::setup_for
+#[derive(Debug, Clone, Default)]
+pub struct PackedPsiCircuit<const LANES: usize> {
+    /// The sets, salts and options of the proof
+    pub psi: PsiCircuit,
+}
+
+impl<const LANES: usize> PackedPsiCircuit<LANES> {
+    /// Create a packed circuit over the sets of `psi`
+    pub fn new(psi: PsiCircuit) -> Self {
+        Self { psi }
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        self.psi.public_inputs()
+    }
+}
+
+impl<const LANES: usize> Circuit<Fp> for PackedPsiCircuit<LANES> {
+    type Config = PsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            psi: self.psi.without_witnesses(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiConfig::configure_with_lanes(meta, LANES)
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.psi.synthesize(config, layouter)
+    }
+}
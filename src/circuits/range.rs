@@ -0,0 +1,95 @@
+//! Byte-decomposition range checks
+
+use ff::PrimeField;
+use halo2_proofs::circuit::{AssignedCell, Layouter, Value};
+use halo2_proofs::plonk::{
+    Advice, Column, ConstraintSystem, Error, Expression, Selector, TableColumn,
+};
+use halo2_proofs::poly::Rotation;
+use pasta_curves::Fp;
+
+/// Byte lookup table and the gate decomposing a value into base-256 digits
+#[derive(Debug, Clone)]
+pub struct ByteRangeConfig {
+    /// Advice column holding the running value of the decomposition
+    z: Column<Advice>,
+    /// Selector for the byte lookups of the range check
+    q_bytes: Selector,
+    /// All byte values
+    table: TableColumn,
+}
+
+impl ByteRangeConfig {
+    /// Configure the byte lookup over the running values in `z`
+    pub fn configure(meta: &mut ConstraintSystem<Fp>, z: Column<Advice>) -> Self {
+        let q_bytes = meta.complex_selector();
+        let table = meta.lookup_table_column();
+
+        // Byte decomposition: z[i] - 256 * z[i+1] is a byte
+        meta.lookup(|meta| {
+            let q = meta.query_selector(q_bytes);
+            let z_cur = meta.query_advice(z, Rotation::cur());
+            let z_next = meta.query_advice(z, Rotation::next());
+
+            vec![(
+                q * (z_cur - z_next * Expression::Constant(Fp::from(256))),
+                table,
+            )]
+        });
+
+        Self { z, q_bytes, table }
+    }
+
+    /// Load the byte table
+    pub fn load(&self, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        layouter.assign_table(
+            || "bytes",
+            |mut table| {
+                for byte in 0..256 {
+                    table.assign_cell(
+                        || "byte",
+                        self.table,
+                        byte,
+                        || Value::known(Fp::from(byte as u64)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Constrain a value to `bytes` bytes by looking up its base-256 digits
+    pub fn assert_bytes(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        value: &AssignedCell<Fp, Fp>,
+        bytes: usize,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "byte range check",
+            |mut region| {
+                let mut z = value.copy_advice(|| "z 0", &mut region, self.z, 0)?;
+                for i in 0..bytes {
+                    self.q_bytes.enable(&mut region, i)?;
+
+                    // z[i+1] = z[i] >> 8; an honest prover only gets here with
+                    // a value in range
+                    let z_next = z.value().map(|z| {
+                        let mut repr = z.to_repr();
+                        let repr = repr.as_mut();
+                        repr.copy_within(1.., 0);
+                        repr[31] = 0;
+                        Fp::from_repr(repr.try_into().unwrap()).unwrap()
+                    });
+                    z = region.assign_advice(
+                        || format!("z {}", i + 1),
+                        self.z,
+                        i + 1,
+                        || z_next,
+                    )?;
+                }
+                region.constrain_constant(z.cell(), Fp::zero())
+            },
+        )
+    }
+}
@@ -0,0 +1,696 @@
+//! PSI circuit over the sorted merge of the two sets
+
+use crate::circuits::lookup::element_key;
+use crate::circuits::{check_element_bits, check_merged_size};
+use crate::{
+    AssignedSet, ByteRangeConfig, COMMITMENT_A_ROW, COMMITMENT_B_ROW, CircuitError, ELEMENT_BITS,
+    INTERSECTION_SIZE_ROW, IntersectionSemantics, PsiCircuit, PsiConfig, SetCapacity,
+    commit_set_with_capacity,
+};
+use ff::Field;
+use halo2_proofs::circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error, Expression, Selector, VirtualCells};
+use halo2_proofs::poly::Rotation;
+use pasta_curves::Fp;
+use rand::rngs::OsRng;
+
+/// Largest number of entries in the merged list of a [`SortPsiCircuit`],
+/// i.e. the largest `max_a + max_b` of its [`SetCapacity`]
+///
+/// Merged values are bounded by the first active value plus the range checked
+/// steps after it, all below `2^ELEMENT_BITS`. With at most `2^6` entries the
+/// bound stays below the field modulus (> 2^254), so the order cannot wrap
+/// around, and the two flag bits of every entry fit in one field element.
+pub const MAX_MERGED_SIZE: usize = 1 << (254 - ELEMENT_BITS);
+
+/// Configuration of [`SortPsiCircuit`]: the PSI columns plus the gates of the
+/// sorted merged list and its grand product
+///
+/// The prover lays out the entries of both padded sets, tagged with the set
+/// they come from, sorted with the padding first and the active elements in
+/// ascending order. A grand product at a challenge derived in-circuit from the
+/// set commitments and the merged list shows that the list is a permutation
+/// of the two sets. Adjacent active entries holding the same element are then
+/// exactly the intersection, provided an entry of A always comes right before
+/// its equal entry of B, which also rules out duplicates within a set.
+#[derive(Debug, Clone)]
+pub struct SortPsiConfig {
+    psi: PsiConfig,
+    /// Selector for the gates of every merged entry
+    q_entry: Selector,
+    /// Selector for the first merged entry
+    q_first: Selector,
+    /// Selector for the gates linking a merged entry to the one before it
+    q_step: Selector,
+    /// Selector for the grand product rows
+    q_product: Selector,
+    /// Byte table for the range checks of the ordering steps
+    bytes: ByteRangeConfig,
+}
+
+/// Assigned cells of one entry of the merged list
+#[derive(Debug, Clone)]
+pub struct AssignedEntry {
+    pub value: AssignedCell<Fp, Fp>,
+    pub active: AssignedCell<Fp, Fp>,
+    /// 0 for an entry of set A, 1 for an entry of set B
+    pub tag: AssignedCell<Fp, Fp>,
+}
+
+/// Challenges of the grand product of a [`SortPsiConfig`]
+#[derive(Debug, Clone)]
+pub struct AssignedChallenges {
+    /// Point at which the products of `r - fingerprint` are evaluated
+    pub r: AssignedCell<Fp, Fp>,
+    /// Weight of the active and tag bits in the fingerprints
+    pub alpha: AssignedCell<Fp, Fp>,
+}
+
+/// Assigned cells of the sorted merged list
+#[derive(Debug, Clone)]
+pub struct AssignedMerged {
+    pub entries: Vec<AssignedEntry>,
+    /// Number of adjacent pairs of equal active entries
+    pub count: AssignedCell<Fp, Fp>,
+    /// The active and tag bits of all entries, packed two bits per entry
+    pub flags: AssignedCell<Fp, Fp>,
+}
+
+impl SortPsiConfig {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let psi = PsiConfig::configure(meta);
+        let q_entry = meta.selector();
+        let q_first = meta.selector();
+        let q_step = meta.selector();
+        let q_product = meta.selector();
+        let bytes = ByteRangeConfig::configure(meta, psi.sum);
+
+        // Merged list columns: value, active and tag in the set A slot
+        // columns, the equality bit and its inverse as in the grid, the
+        // ordering step in b_picks, the packed flags in weighted and the
+        // running count in sum
+        let entry = |meta: &mut VirtualCells<Fp>, rotation| {
+            (
+                meta.query_advice(psi.set_a, rotation),
+                meta.query_advice(psi.a_active, rotation),
+                meta.query_advice(psi.b_active, rotation),
+            )
+        };
+
+        // Entry gate: active and tag are boolean and padding entries hold zero
+        meta.create_gate("merged entry", |meta| {
+            let q = meta.query_selector(q_entry);
+            let (value, active, tag) = entry(meta, Rotation::cur());
+            let one = Expression::Constant(Fp::one());
+
+            vec![
+                q.clone() * active.clone() * (active.clone() - one.clone()),
+                q.clone() * tag.clone() * (tag - one.clone()),
+                q * (one - active) * value,
+            ]
+        });
+
+        // First entry gate: the ordering step is the value itself if active,
+        // so that the first active value is range checked too
+        meta.create_gate("first merged entry", |meta| {
+            let q = meta.query_selector(q_first);
+            let (value, active, tag) = entry(meta, Rotation::cur());
+            let step = meta.query_advice(psi.b_picks, Rotation::cur());
+            let flags = meta.query_advice(psi.weighted, Rotation::cur());
+
+            vec![
+                q.clone() * (step - active.clone() * value),
+                q * (flags - active - tag * Fp::from(2)),
+            ]
+        });
+
+        // Step gate: padding comes first, and the step active * (value -
+        // active_prev * value_prev) is range checked, so active values never
+        // decrease. Equal neighbours are detected with the IsZero construction
+        // of the equality gate; an equal pair must be an entry of A followed
+        // by an entry of B, and is counted.
+        meta.create_gate("merged step", |meta| {
+            let q = meta.query_selector(q_step);
+            let (value_prev, active_prev, tag_prev) = entry(meta, Rotation::prev());
+            let (value, active, tag) = entry(meta, Rotation::cur());
+            let step = meta.query_advice(psi.b_picks, Rotation::cur());
+            let equal = meta.query_advice(psi.match_bit, Rotation::cur());
+            let inverse = meta.query_advice(psi.inverse, Rotation::cur());
+            let count_prev = meta.query_advice(psi.sum, Rotation::prev());
+            let count = meta.query_advice(psi.sum, Rotation::cur());
+            let flags_prev = meta.query_advice(psi.weighted, Rotation::prev());
+            let flags = meta.query_advice(psi.weighted, Rotation::cur());
+            let one = Expression::Constant(Fp::one());
+
+            let diff = value.clone() - value_prev.clone();
+            let is_eq = one.clone() - diff.clone() * inverse;
+
+            vec![
+                q.clone() * active_prev.clone() * (one.clone() - active.clone()),
+                q.clone() * (step - active.clone() * (value - active_prev.clone() * value_prev)),
+                q.clone() * diff * is_eq.clone(),
+                q.clone() * (equal.clone() - active_prev * is_eq),
+                q.clone() * equal.clone() * tag_prev,
+                q.clone() * equal.clone() * (one - tag.clone()),
+                q.clone() * (count - count_prev - equal),
+                q * (flags - flags_prev * Fp::from(4) - active - tag * Fp::from(2)),
+            ]
+        });
+
+        // Grand product gate: with the fingerprint v + alpha * active +
+        // alpha^2 * tag, the products of r - fingerprint over the input slots
+        // (set A columns) and over the merged entries (set B columns) grow
+        // row by row, with r and alpha copied into every row
+        meta.create_gate("grand product", |meta| {
+            let q = meta.query_selector(q_product);
+            let r = meta.query_advice(psi.prev_sum, Rotation::cur());
+            let alpha = meta.query_advice(psi.a_picks_prev, Rotation::cur());
+            let fingerprint = |value, active, tag| {
+                value + alpha.clone() * active + alpha.clone() * alpha.clone() * tag
+            };
+
+            let input = fingerprint(
+                meta.query_advice(psi.set_a, Rotation::cur()),
+                meta.query_advice(psi.a_active, Rotation::cur()),
+                meta.query_advice(psi.a_used, Rotation::cur()),
+            );
+            let merged = fingerprint(
+                meta.query_advice(psi.set_b, Rotation::cur()),
+                meta.query_advice(psi.b_active, Rotation::cur()),
+                meta.query_advice(psi.b_used, Rotation::cur()),
+            );
+            let input_prev = meta.query_advice(psi.a_picks, Rotation::prev());
+            let input_cur = meta.query_advice(psi.a_picks, Rotation::cur());
+            let merged_prev = meta.query_advice(psi.b_picks, Rotation::prev());
+            let merged_cur = meta.query_advice(psi.b_picks, Rotation::cur());
+
+            vec![
+                q.clone() * (input_cur - input_prev * (r.clone() - input)),
+                q * (merged_cur - merged_prev * (r - merged)),
+            ]
+        });
+
+        Self {
+            psi,
+            q_entry,
+            q_first,
+            q_step,
+            q_product,
+            bytes,
+        }
+    }
+
+    /// Assign the sorted merged list of `(value, active, tag)` entries and
+    /// range check its ordering steps
+    pub fn assign_merged(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        entries: &[(Fp, bool, bool)],
+    ) -> Result<AssignedMerged, Error> {
+        let psi = &self.psi;
+        let (merged, steps) = layouter.assign_region(
+            || "merged list",
+            |mut region| {
+                let mut assigned: Vec<AssignedEntry> = Vec::with_capacity(entries.len());
+                let mut steps = Vec::with_capacity(entries.len());
+                let mut count: Option<AssignedCell<Fp, Fp>> = None;
+                let mut flags: Option<AssignedCell<Fp, Fp>> = None;
+
+                for (offset, (value, active, tag)) in entries.iter().enumerate() {
+                    self.q_entry.enable(&mut region, offset)?;
+
+                    let value = region.assign_advice(
+                        || format!("value {}", offset),
+                        psi.set_a,
+                        offset,
+                        || Value::known(*value),
+                    )?;
+                    let active = region.assign_advice(
+                        || format!("active {}", offset),
+                        psi.a_active,
+                        offset,
+                        || Value::known(Fp::from(*active as u64)),
+                    )?;
+                    let tag = region.assign_advice(
+                        || format!("tag {}", offset),
+                        psi.b_active,
+                        offset,
+                        || Value::known(Fp::from(*tag as u64)),
+                    )?;
+                    let entry_flags =
+                        active.value().copied() + tag.value().copied() * Value::known(Fp::from(2));
+
+                    let (step, packed) = match (assigned.last(), &count, &flags) {
+                        (Some(prev), Some(count_prev), Some(flags_prev)) => {
+                            self.q_step.enable(&mut region, offset)?;
+
+                            let diff = value.value().copied() - prev.value.value();
+                            let inverse = diff.map(|diff| diff.invert().unwrap_or(Fp::zero()));
+                            let is_eq = diff.map(|diff| Fp::from((diff == Fp::zero()) as u64));
+                            let equal = prev.active.value().copied() * is_eq;
+                            region.assign_advice(|| "inverse", psi.inverse, offset, || inverse)?;
+                            region.assign_advice(|| "equal", psi.match_bit, offset, || equal)?;
+                            count = Some(region.assign_advice(
+                                || format!("count {}", offset),
+                                psi.sum,
+                                offset,
+                                || count_prev.value().copied() + equal,
+                            )?);
+
+                            (
+                                active.value().copied()
+                                    * (value.value().copied()
+                                        - prev.active.value().copied() * prev.value.value()),
+                                flags_prev.value().copied() * Value::known(Fp::from(4))
+                                    + entry_flags,
+                            )
+                        }
+                        _ => {
+                            self.q_first.enable(&mut region, offset)?;
+
+                            count = Some(region.assign_advice_from_constant(
+                                || "count 0",
+                                psi.sum,
+                                offset,
+                                Fp::zero(),
+                            )?);
+                            (active.value().copied() * value.value(), entry_flags)
+                        }
+                    };
+
+                    steps.push(region.assign_advice(
+                        || format!("step {}", offset),
+                        psi.b_picks,
+                        offset,
+                        || step,
+                    )?);
+                    flags = Some(region.assign_advice(
+                        || format!("flags {}", offset),
+                        psi.weighted,
+                        offset,
+                        || packed,
+                    )?);
+                    assigned.push(AssignedEntry { value, active, tag });
+                }
+
+                Ok((
+                    AssignedMerged {
+                        entries: assigned,
+                        count: count.expect("merged list has at least one entry"),
+                        flags: flags.expect("merged list has at least one entry"),
+                    },
+                    steps,
+                ))
+            },
+        )?;
+
+        for (i, step) in steps.iter().enumerate() {
+            self.bytes.assert_bytes(
+                layouter.namespace(|| format!("step {}", i)),
+                step,
+                ELEMENT_BITS / 8,
+            )?;
+        }
+
+        Ok(merged)
+    }
+
+    /// Derive the grand product challenges by hashing the set
+    /// commitments, the merged values and the packed flags, so they are only
+    /// known once both sets and the merged list are fixed
+    pub fn challenges(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        commitment_a: &AssignedCell<Fp, Fp>,
+        commitment_b: &AssignedCell<Fp, Fp>,
+        merged: &AssignedMerged,
+    ) -> Result<AssignedChallenges, Error> {
+        let mut digest = self.psi.hash_pair(
+            layouter.namespace(|| "commitments"),
+            commitment_a.clone(),
+            commitment_b.clone(),
+        )?;
+        for (i, entry) in merged.entries.iter().enumerate() {
+            digest = self.psi.hash_pair(
+                layouter.namespace(|| format!("entry {}", i)),
+                digest,
+                entry.value.clone(),
+            )?;
+        }
+
+        let r = self
+            .psi
+            .hash_pair(layouter.namespace(|| "r"), digest, merged.flags.clone())?;
+        let alpha = self
+            .psi
+            .hash_pair(layouter.namespace(|| "alpha"), r.clone(), r.clone())?;
+        Ok(AssignedChallenges { r, alpha })
+    }
+
+    /// Constrain the merged list to be a permutation of the slots of both
+    /// sets, tagged 0 for A and 1 for B, by comparing the grand products of
+    /// their fingerprints at the challenges
+    pub fn assert_permutation(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        set_a: &AssignedSet,
+        set_b: &AssignedSet,
+        merged: &AssignedMerged,
+        challenges: &AssignedChallenges,
+    ) -> Result<(), Error> {
+        let psi = &self.psi;
+        let inputs: Vec<_> = set_a
+            .slots
+            .iter()
+            .map(|slot| (slot, Fp::zero()))
+            .chain(set_b.slots.iter().map(|slot| (slot, Fp::one())))
+            .collect();
+
+        layouter.assign_region(
+            || "grand product",
+            |mut region| {
+                let mut input_product =
+                    region.assign_advice_from_constant(|| "input 0", psi.a_picks, 0, Fp::one())?;
+                let mut merged_product =
+                    region.assign_advice_from_constant(|| "merged 0", psi.b_picks, 0, Fp::one())?;
+
+                for (offset, ((slot, tag), entry)) in inputs.iter().zip(&merged.entries).enumerate()
+                {
+                    let offset = offset + 1;
+                    self.q_product.enable(&mut region, offset)?;
+
+                    let r = challenges
+                        .r
+                        .copy_advice(|| "r", &mut region, psi.prev_sum, offset)?;
+                    let alpha = challenges.alpha.copy_advice(
+                        || "alpha",
+                        &mut region,
+                        psi.a_picks_prev,
+                        offset,
+                    )?;
+                    let fingerprint = |value: Value<Fp>, active: Value<Fp>, tag: Value<Fp>| {
+                        let alpha = alpha.value().copied();
+                        value + alpha * active + alpha * alpha * tag
+                    };
+
+                    let value =
+                        slot.value
+                            .copy_advice(|| "input", &mut region, psi.set_a, offset)?;
+                    let active = slot.active.copy_advice(
+                        || "input active",
+                        &mut region,
+                        psi.a_active,
+                        offset,
+                    )?;
+                    let tag = region.assign_advice_from_constant(
+                        || "input tag",
+                        psi.a_used,
+                        offset,
+                        *tag,
+                    )?;
+                    let input = fingerprint(
+                        value.value().copied(),
+                        active.value().copied(),
+                        tag.value().copied(),
+                    );
+
+                    let value =
+                        entry
+                            .value
+                            .copy_advice(|| "merged", &mut region, psi.set_b, offset)?;
+                    let active = entry.active.copy_advice(
+                        || "merged active",
+                        &mut region,
+                        psi.b_active,
+                        offset,
+                    )?;
+                    let tag =
+                        entry
+                            .tag
+                            .copy_advice(|| "merged tag", &mut region, psi.b_used, offset)?;
+                    let merged = fingerprint(
+                        value.value().copied(),
+                        active.value().copied(),
+                        tag.value().copied(),
+                    );
+
+                    input_product = region.assign_advice(
+                        || format!("input {}", offset),
+                        psi.a_picks,
+                        offset,
+                        || input_product.value().copied() * (r.value().copied() - input),
+                    )?;
+                    merged_product = region.assign_advice(
+                        || format!("merged {}", offset),
+                        psi.b_picks,
+                        offset,
+                        || merged_product.value().copied() * (r.value().copied() - merged),
+                    )?;
+                }
+
+                region.constrain_equal(input_product.cell(), merged_product.cell())
+            },
+        )
+    }
+
+    /// Load the byte table of the range checks
+    pub fn load_tables(&self, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.bytes.load(layouter)
+    }
+}
+
+/// PSI circuit counting the intersection on the sorted merge of both sets,
+/// instead of the comparison grid
+///
+/// The prover supplies the slots of both padded sets merged into one list,
+/// sorted by element, and a grand product argument ties the list to the
+/// committed sets (see [`SortPsiConfig`]). Counting adjacent equal entries
+/// then takes `|A| + |B|` rows plus a 248-bit range check per entry, instead
+/// of the `|A| * |B|` rows of [`PsiCircuit`]. The two maximum sizes together
+/// are at most [`MAX_MERGED_SIZE`], and the keys come from [`setup_sort`] for
+/// the same capacity.
+///
+/// Sets must not contain duplicates, which the circuit rejects, and elements
+/// must be below `2^ELEMENT_BITS`. The public inputs are laid out as for
+/// [`PsiCircuit`]: the intersection size and the two set commitments, so
+/// proofs are created and checked with [`generate_proof`] and
+/// [`verify_proof`].
+///
+/// [`setup_sort`]: crate::setup_sort
+/// [`generate_proof`]: crate::generate_proof
+/// [`verify_proof`]: crate::verify_proof
+#[derive(Debug, Clone, Default)]
+pub struct SortPsiCircuit {
+    /// First set of hashed elements
+    pub set_a: Vec<Fp>,
+    /// Second set of hashed elements
+    pub set_b: Vec<Fp>,
+    /// Expected intersection size (public input)
+    pub intersection_size: u64,
+    /// Blinding salt for the commitment to set A
+    pub salt_a: Fp,
+    /// Blinding salt for the commitment to set B
+    pub salt_b: Fp,
+    /// Maximum sizes of the two sets
+    pub capacity: SetCapacity,
+}
+
+impl SortPsiCircuit {
+    /// Create a new sort-and-merge circuit with fresh random commitment salts
+    pub fn new(
+        set_a: Vec<Fp>,
+        set_b: Vec<Fp>,
+        intersection_size: u64,
+    ) -> Result<Self, CircuitError> {
+        Self::new_with_salts(
+            set_a,
+            set_b,
+            intersection_size,
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+        )
+    }
+
+    /// Create a new sort-and-merge circuit with the salts of previously
+    /// published commitments
+    pub fn new_with_salts(
+        set_a: Vec<Fp>,
+        set_b: Vec<Fp>,
+        intersection_size: u64,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self, CircuitError> {
+        Self::new_with_capacity(
+            SetCapacity::default(),
+            set_a,
+            set_b,
+            intersection_size,
+            salt_a,
+            salt_b,
+        )
+    }
+
+    /// Create a new sort-and-merge circuit laid out for sets of up to the
+    /// sizes of `capacity`, with the salts of previously published
+    /// commitments
+    ///
+    /// Fails if the capacity holds more than [`MAX_MERGED_SIZE`] elements in
+    /// total, a set exceeds its maximum size or an element is not below
+    /// `2^ELEMENT_BITS`.
+    pub fn new_with_capacity(
+        capacity: SetCapacity,
+        set_a: Vec<Fp>,
+        set_b: Vec<Fp>,
+        intersection_size: u64,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self, CircuitError> {
+        check_merged_size(capacity)?;
+        capacity.check_sets(&set_a, &set_b)?;
+        check_element_bits("A", &set_a)?;
+        check_element_bits("B", &set_b)?;
+
+        Ok(Self {
+            set_a,
+            set_b,
+            intersection_size,
+            salt_a,
+            salt_b,
+            capacity,
+        })
+    }
+
+    /// Shape of a circuit over sets of up to the sizes of `capacity`, e.g. for
+    /// key generation
+    pub fn empty(capacity: SetCapacity) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    /// Compute the intersection size natively (for witness generation)
+    pub fn compute_intersection_size(&self) -> u64 {
+        IntersectionSemantics::Set.intersection_size(&self.set_a, &self.set_b)
+    }
+
+    /// Commitment to set A under `salt_a`
+    pub fn commitment_a(&self) -> Fp {
+        commit_set_with_capacity(&self.set_a, self.salt_a, self.capacity.max_a)
+    }
+
+    /// Commitment to set B under `salt_b`
+    pub fn commitment_b(&self) -> Fp {
+        commit_set_with_capacity(&self.set_b, self.salt_b, self.capacity.max_b)
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        vec![
+            Fp::from(self.intersection_size),
+            self.commitment_a(),
+            self.commitment_b(),
+        ]
+    }
+
+    /// The `(value, active, tag)` entries of both padded sets, with the
+    /// padding first and the active elements in ascending order, each entry
+    /// of A before an equal entry of B
+    fn merged_entries(slots_a: &[(Fp, bool)], slots_b: &[(Fp, bool)]) -> Vec<(Fp, bool, bool)> {
+        let mut entries: Vec<_> = slots_a
+            .iter()
+            .map(|(value, active)| (*value, *active, false))
+            .chain(
+                slots_b
+                    .iter()
+                    .map(|(value, active)| (*value, *active, true)),
+            )
+            .collect();
+        entries.sort_by_key(|(value, active, tag)| (*active, element_key(value), *tag));
+        entries
+    }
+}
+
+impl Circuit<Fp> for SortPsiCircuit {
+    type Config = SortPsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.capacity)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        SortPsiConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.synthesize_with(&config, layouter, |entries| entries)
+    }
+}
+
+impl SortPsiCircuit {
+    /// Lay out the circuit, letting `merged` rewrite the honest merged list
+    pub(crate) fn synthesize_with(
+        &self,
+        config: &SortPsiConfig,
+        mut layouter: impl Layouter<Fp>,
+        merged: impl Fn(Vec<(Fp, bool, bool)>) -> Vec<(Fp, bool, bool)>,
+    ) -> Result<(), Error> {
+        // The fields are public, so the constructors cannot rule this out alone
+        if check_merged_size(self.capacity).is_err() {
+            return Err(Error::Synthesis);
+        }
+        config.load_tables(layouter.namespace(|| "tables"))?;
+
+        let psi = &config.psi;
+        let SetCapacity { max_a, max_b } = self.capacity;
+        let padded_a = PsiCircuit::padded_slots(&self.set_a, max_a);
+        let padded_b = PsiCircuit::padded_slots(&self.set_b, max_b);
+
+        let set_a = psi.assign_set(
+            layouter.namespace(|| "set A"),
+            &padded_a,
+            &vec![false; max_a],
+            &vec![Fp::zero(); max_a],
+            true,
+        )?;
+        let set_b = psi.assign_set(
+            layouter.namespace(|| "set B"),
+            &padded_b,
+            &vec![false; max_b],
+            &vec![Fp::zero(); max_b],
+            false,
+        )?;
+        let commitment_a = psi.commit_set(
+            layouter.namespace(|| "commit set A"),
+            &set_a,
+            Value::known(self.salt_a),
+        )?;
+        let commitment_b = psi.commit_set(
+            layouter.namespace(|| "commit set B"),
+            &set_b,
+            Value::known(self.salt_b),
+        )?;
+
+        let entries = merged(Self::merged_entries(&padded_a, &padded_b));
+        assert_eq!(entries.len(), max_a + max_b, "Merged list covers both sets");
+        let merged = config.assign_merged(layouter.namespace(|| "merge"), &entries)?;
+        let challenges = config.challenges(
+            layouter.namespace(|| "challenges"),
+            &commitment_a,
+            &commitment_b,
+            &merged,
+        )?;
+        config.assert_permutation(
+            layouter.namespace(|| "permutation"),
+            &set_a,
+            &set_b,
+            &merged,
+            &challenges,
+        )?;
+
+        layouter.constrain_instance(merged.count.cell(), psi.instance, INTERSECTION_SIZE_ROW)?;
+        layouter.constrain_instance(commitment_a.cell(), psi.instance, COMMITMENT_A_ROW)?;
+        layouter.constrain_instance(commitment_b.cell(), psi.instance, COMMITMENT_B_ROW)
+    }
+}
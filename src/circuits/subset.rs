@@ -0,0 +1,77 @@
+//! Circuit proving one private set is a subset of another
+
+use crate::{PsiCircuit, PsiConfig, SUBSET_COMMITMENT_ROW, SUPERSET_COMMITMENT_ROW};
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use pasta_curves::Fp;
+
+/// Circuit proving that every element of a private set A is contained in a
+/// private set B
+///
+/// Every active slot of A must be picked exactly once by the matching of the
+/// comparison grid; since the matching is maximal, this holds exactly when A
+/// is a subset of B (a sub-multiset under [`IntersectionSemantics::Multiset`]).
+/// The public inputs are the two set commitments, at [`SUBSET_COMMITMENT_ROW`]
+/// and [`SUPERSET_COMMITMENT_ROW`]; no count is revealed.
+///
+/// [`IntersectionSemantics::Multiset`]: crate::IntersectionSemantics::Multiset
+#[derive(Debug, Clone, Default)]
+pub struct SubsetCircuit {
+    /// A as `psi.set_a` and B as `psi.set_b`; `psi.intersection_size` is not used
+    pub psi: PsiCircuit,
+}
+
+impl SubsetCircuit {
+    /// Create a subset circuit over the sets of `psi`
+    pub fn new(psi: PsiCircuit) -> Self {
+        Self { psi }
+    }
+
+    /// Whether A is contained in B (for witness generation)
+    pub fn is_subset(&self) -> bool {
+        self.psi.compute_intersection_size() == self.psi.set_a.len() as u64
+    }
+
+    /// Public inputs for this circuit, in instance column order
+    pub fn public_inputs(&self) -> Vec<Fp> {
+        vec![self.psi.commitment_a(), self.psi.commitment_b()]
+    }
+}
+
+impl Circuit<Fp> for SubsetCircuit {
+    type Config = PsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            psi: self.psi.without_witnesses(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let cells = self
+            .psi
+            .synthesize_intersection(&config, layouter.namespace(|| "psi"))?;
+
+        config.assert_all_used(layouter.namespace(|| "subset"), &cells.set_a)?;
+
+        layouter.constrain_instance(
+            cells.commitment_a.cell(),
+            config.instance,
+            SUBSET_COMMITMENT_ROW,
+        )?;
+        layouter.constrain_instance(
+            cells.commitment_b.cell(),
+            config.instance,
+            SUPERSET_COMMITMENT_ROW,
+        )
+    }
+}
@@ -0,0 +1,128 @@
+//! Poseidon hashing and the set commitments exposed by the circuits
+
+use crate::{MAX_SET_SIZE, PsiCircuit};
+use ff::PrimeField;
+use halo2_gadgets::poseidon::primitives::{self as poseidon};
+use halo2_gadgets::poseidon::primitives::{ConstantLength, P128Pow5T3};
+use pasta_curves::Fp;
+
+pub fn hash_to_field(value: u64) -> Fp {
+    let bytes = value.to_le_bytes();
+    let hash = blake3::hash(&bytes);
+    let hash_bytes = hash.as_bytes();
+
+    let mut repr = [0u8; 32];
+    repr[..31].copy_from_slice(&hash_bytes[..31]);
+
+    Fp::from_repr(repr).unwrap()
+}
+
+/// Hash a string to a field element
+pub fn hash_string_to_field(s: &str) -> Fp {
+    let hash = blake3::hash(s.as_bytes());
+    let hash_bytes = hash.as_bytes();
+
+    let mut repr = [0u8; 32];
+    repr[..31].copy_from_slice(&hash_bytes[..31]);
+
+    Fp::from_repr(repr).unwrap()
+}
+
+/// Poseidon hash of two field elements, the building block of set commitments
+pub fn poseidon_hash_pair(left: Fp, right: Fp) -> Fp {
+    poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash([left, right])
+}
+
+/// Compute the Poseidon commitment to a set, as exposed by a [`PsiCircuit`]
+/// with the default [`SetCapacity`].
+///
+/// The set is padded to `MAX_SET_SIZE` slots and folded into a hash chain
+/// seeded with the blinding `salt` and the set size:
+/// `H(..H(H(salt, |set|), s_0).., s_{MAX_SET_SIZE - 1})`.
+///
+/// [`SetCapacity`]: crate::SetCapacity
+pub fn commit_set(set: &[Fp], salt: Fp) -> Fp {
+    commit_set_with_capacity(set, salt, MAX_SET_SIZE)
+}
+
+/// Compute the commitment of [`commit_set`] over `capacity` slots, as exposed
+/// by a [`PsiCircuit`] whose [`SetCapacity`] allows `capacity` elements in the
+/// set.
+///
+/// [`SetCapacity`]: crate::SetCapacity
+pub fn commit_set_with_capacity(set: &[Fp], salt: Fp, capacity: usize) -> Fp {
+    assert!(set.len() <= capacity, "Set exceeds maximum size");
+
+    PsiCircuit::padded_slots(set, capacity).iter().fold(
+        poseidon_hash_pair(salt, Fp::from(set.len() as u64)),
+        |digest, (value, _)| poseidon_hash_pair(digest, *value),
+    )
+}
+
+/// Compute the Poseidon commitment to a set with private per-element values,
+/// as exposed by a weighted [`PsiCircuit`].
+///
+/// The hash chain of [`commit_set`] continues over the values, padded with
+/// zeros to `MAX_SET_SIZE`, so the commitment binds both keys and values.
+pub fn commit_weighted_set(set: &[Fp], values: &[u32], salt: Fp) -> Fp {
+    commit_weighted_set_with_capacity(set, values, salt, MAX_SET_SIZE)
+}
+
+/// Compute the commitment of [`commit_weighted_set`] over `capacity` slots
+pub fn commit_weighted_set_with_capacity(
+    set: &[Fp],
+    values: &[u32],
+    salt: Fp,
+    capacity: usize,
+) -> Fp {
+    assert_eq!(set.len(), values.len(), "Every element needs a value");
+
+    (0..capacity)
+        .map(|i| values.get(i).map_or(Fp::zero(), |v| Fp::from(*v as u64)))
+        .fold(
+            commit_set_with_capacity(set, salt, capacity),
+            poseidon_hash_pair,
+        )
+}
+
+/// Compute the commitment to a public blocklist, as exposed by
+/// [`DisjointnessCircuit`].
+///
+/// The list is public, so it is committed like a set with a zero salt and
+/// anyone holding the list can recompute the commitment.
+///
+/// [`DisjointnessCircuit`]: crate::DisjointnessCircuit
+pub fn commit_blocklist(blocklist: &[Fp]) -> Fp {
+    commit_blocklist_with_capacity(blocklist, MAX_SET_SIZE)
+}
+
+/// Compute the commitment of [`commit_blocklist`] over `capacity` slots, as
+/// exposed by a [`DisjointnessCircuit`] whose blocklist allows `capacity`
+/// elements
+///
+/// [`DisjointnessCircuit`]: crate::DisjointnessCircuit
+pub fn commit_blocklist_with_capacity(blocklist: &[Fp], capacity: usize) -> Fp {
+    commit_set_with_capacity(blocklist, Fp::zero(), capacity)
+}
+
+/// Native count of the distinct elements held by at least `threshold` of the
+/// sets, as exposed by [`MultiPsiCircuit`].
+///
+/// With `threshold == sets.len()` this is the size of the intersection of all
+/// sets. Repeated elements within one set count once.
+///
+/// [`MultiPsiCircuit`]: crate::MultiPsiCircuit
+pub fn multi_intersection_size(sets: &[Vec<Fp>], threshold: usize) -> u64 {
+    let mut seen: Vec<Fp> = vec![];
+    let mut count = 0u64;
+    for x in sets.iter().flatten() {
+        if seen.contains(x) {
+            continue;
+        }
+        seen.push(*x);
+        if sets.iter().filter(|set| set.contains(x)).count() >= threshold {
+            count += 1;
+        }
+    }
+    count
+}
@@ -0,0 +1,259 @@
+//! Self-describing JSON envelope around a serialized proof
+
+use crate::{invalid_data, params_digest, params_k, vk_digest};
+use ff::PrimeField;
+use halo2_proofs::plonk::VerifyingKey;
+use pasta_curves::{EqAffine, Fp};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Magic bytes starting every encoded [`ProofEnvelope`]
+pub const PROOF_ENVELOPE_MAGIC: [u8; 4] = *b"ZPSI";
+
+/// Version of the [`ProofEnvelope`] format written by this crate
+pub const PROOF_ENVELOPE_VERSION: u16 = 1;
+
+/// Curve and commitment scheme of the proofs of this crate
+pub const PROOF_CURVE: &str = "pasta-vesta-ipa";
+
+/// Self-describing proof file
+///
+/// Besides the proof, an envelope records what is needed to verify it: the
+/// circuit it is for, the digests of the params and verifying key it was
+/// produced against, and the public inputs as 32-byte field element
+/// encodings. [`ProofEnvelope::write`] encodes it with bincode after the magic
+/// bytes and format version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofEnvelope {
+    /// Always [`PROOF_ENVELOPE_MAGIC`]
+    pub magic: [u8; 4],
+    /// Format version, [`PROOF_ENVELOPE_VERSION`] for envelopes of this crate
+    pub version: u16,
+    /// Curve and commitment scheme, [`PROOF_CURVE`]
+    pub curve: String,
+    /// Identifier of the circuit, e.g. `"psi"` or `"subset"`
+    pub circuit: String,
+    /// Circuit size parameter
+    pub k: u32,
+    /// [`params_digest`] of the params the proof was produced with
+    pub params_digest: [u8; 32],
+    /// [`vk_digest`] of the key the proof was produced with
+    pub vk_digest: [u8; 32],
+    /// Public inputs in instance column order
+    pub public_inputs: Vec<[u8; 32]>,
+    /// Proof transcript
+    pub proof: Vec<u8>,
+}
+
+impl ProofEnvelope {
+    /// Envelope for a proof of `circuit` produced with `params` and the
+    /// proving key of `vk`
+    pub fn new(
+        circuit: &str,
+        params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+        vk: &VerifyingKey<EqAffine>,
+        public_inputs: &[Fp],
+        proof: Vec<u8>,
+    ) -> Self {
+        Self {
+            magic: PROOF_ENVELOPE_MAGIC,
+            version: PROOF_ENVELOPE_VERSION,
+            curve: PROOF_CURVE.to_string(),
+            circuit: circuit.to_string(),
+            k: params_k(params),
+            params_digest: params_digest(params),
+            vk_digest: vk_digest(vk),
+            public_inputs: public_inputs.iter().map(|input| input.to_repr()).collect(),
+            proof,
+        }
+    }
+
+    /// Decoded public inputs
+    pub fn public_inputs(&self) -> io::Result<Vec<Fp>> {
+        self.public_inputs
+            .iter()
+            .map(|repr| {
+                Option::from(Fp::from_repr(*repr)).ok_or_else(|| {
+                    invalid_data("public input is not a canonical field element".to_string())
+                })
+            })
+            .collect()
+    }
+
+    /// Write the envelope
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        bincode::serialize_into(writer, self).map_err(io::Error::other)
+    }
+
+    /// Read an envelope written by [`ProofEnvelope::write`]
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] on other files and on
+    /// envelopes of another format version or curve.
+    pub fn read<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header)?;
+        if header[..4] != PROOF_ENVELOPE_MAGIC {
+            return Err(invalid_data("not a proof envelope".to_string()));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != PROOF_ENVELOPE_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported proof envelope version {}, expected {}",
+                version, PROOF_ENVELOPE_VERSION
+            )));
+        }
+
+        let envelope: Self = bincode::deserialize_from(io::Read::chain(&header[..], reader))
+            .map_err(|e| invalid_data(format!("malformed proof envelope: {}", e)))?;
+        if envelope.curve != PROOF_CURVE {
+            return Err(invalid_data(format!(
+                "proof is over {}, not {}",
+                envelope.curve, PROOF_CURVE
+            )));
+        }
+        Ok(envelope)
+    }
+
+    /// Fail unless the proof was produced against `params` and `vk`
+    pub fn check(
+        &self,
+        params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+        vk: &VerifyingKey<EqAffine>,
+    ) -> io::Result<()> {
+        if params_digest(params) != self.params_digest {
+            return Err(invalid_data(
+                "the proof was produced with other params".to_string(),
+            ));
+        }
+        if vk_digest(vk) != self.vk_digest {
+            return Err(invalid_data(
+                "the proof was produced with another key".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// JSON export of a [`ProofEnvelope`]
+///
+/// Holds the fields of the envelope with every byte string as a `0x`-prefixed
+/// hex string, for tools that do not read bincode. Each public input is the
+/// 32-byte little-endian encoding of a field element. The verifier still needs
+/// the params and verifying key, which [`ProofJson::to_envelope`] followed by
+/// [`ProofEnvelope::check`] matches against the digests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofJson {
+    /// Format version of the envelope, [`PROOF_ENVELOPE_VERSION`]
+    pub version: u16,
+    /// Curve and commitment scheme, [`PROOF_CURVE`]
+    pub curve: String,
+    /// Identifier of the circuit, as in [`ProofEnvelope::circuit`]
+    pub circuit: String,
+    /// Circuit size parameter
+    pub k: u32,
+    /// Hex encoding of [`ProofEnvelope::params_digest`]
+    pub params_digest: String,
+    /// Hex encoding of [`ProofEnvelope::vk_digest`]
+    pub vk_digest: String,
+    /// Hex encodings of the public inputs in instance column order
+    pub public_inputs: Vec<String>,
+    /// Hex encoding of the proof transcript
+    pub proof: String,
+}
+
+impl From<&ProofEnvelope> for ProofJson {
+    fn from(envelope: &ProofEnvelope) -> Self {
+        Self {
+            version: envelope.version,
+            curve: envelope.curve.clone(),
+            circuit: envelope.circuit.clone(),
+            k: envelope.k,
+            params_digest: to_hex(&envelope.params_digest),
+            vk_digest: to_hex(&envelope.vk_digest),
+            public_inputs: envelope
+                .public_inputs
+                .iter()
+                .map(|repr| to_hex(repr))
+                .collect(),
+            proof: to_hex(&envelope.proof),
+        }
+    }
+}
+
+impl ProofJson {
+    /// JSON encoding of the export
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("proof exports serialize to JSON")
+    }
+
+    /// Read an export encoded by [`ProofJson::to_json`]
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] on malformed JSON and on
+    /// exports of another format version or curve.
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let export: Self = serde_json::from_str(json)
+            .map_err(|e| invalid_data(format!("malformed proof JSON: {}", e)))?;
+        if export.version != PROOF_ENVELOPE_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported proof envelope version {}, expected {}",
+                export.version, PROOF_ENVELOPE_VERSION
+            )));
+        }
+        if export.curve != PROOF_CURVE {
+            return Err(invalid_data(format!(
+                "proof is over {}, not {}",
+                export.curve, PROOF_CURVE
+            )));
+        }
+        Ok(export)
+    }
+
+    /// Decode the hex strings back into an envelope
+    pub fn to_envelope(&self) -> io::Result<ProofEnvelope> {
+        Ok(ProofEnvelope {
+            magic: PROOF_ENVELOPE_MAGIC,
+            version: self.version,
+            curve: self.curve.clone(),
+            circuit: self.circuit.clone(),
+            k: self.k,
+            params_digest: from_hex_array(&self.params_digest)?,
+            vk_digest: from_hex_array(&self.vk_digest)?,
+            public_inputs: self
+                .public_inputs
+                .iter()
+                .map(|input| from_hex_array(input))
+                .collect::<io::Result<_>>()?,
+            proof: from_hex(&self.proof)?,
+        })
+    }
+}
+
+/// `0x`-prefixed hex encoding of bytes
+fn to_hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("0x{}", digits)
+}
+
+/// Decode bytes encoded by [`to_hex`]
+fn from_hex(input: &str) -> io::Result<Vec<u8>> {
+    let digits = input
+        .strip_prefix("0x")
+        .ok_or_else(|| invalid_data(format!("hex string {:?} lacks the 0x prefix", input)))?;
+    if digits.len() % 2 != 0 || !digits.is_ascii() {
+        return Err(invalid_data(format!("invalid hex string {:?}", input)));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| invalid_data(format!("invalid hex string {:?}", input)))
+        })
+        .collect()
+}
+
+/// Decode 32 bytes encoded by [`to_hex`]
+fn from_hex_array(input: &str) -> io::Result<[u8; 32]> {
+    from_hex(input)?
+        .try_into()
+        .map_err(|_| invalid_data(format!("hex string {:?} is not 32 bytes", input)))
+}
@@ -0,0 +1,477 @@
+//! Parameter and key setup, key configuration and their serialization
+
+use crate::proof::keygen;
+use crate::{
+    DisjointnessCircuit, Halo2Setup, IntersectionSemantics, LookupPsiCircuit, MAX_K,
+    MAX_SET_CAPACITY, MultiPsiCircuit, PsiChunkCircuit, PsiCircuit, SetCapacity, SortPsiCircuit,
+    SubsetCircuit, ThresholdPsiCircuit, invalid_data, keygen_with_params, min_k,
+};
+use ff::PrimeField;
+use halo2_proofs::plonk::{Circuit, Error, ProvingKey, VerifyingKey, keygen_vk};
+use halo2_proofs::transcript::{Challenge255, Transcript};
+use pasta_curves::group::GroupEncoding;
+use pasta_curves::{EqAffine, Fp};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Simplified setup function for EqAffine curve, using set semantics
+pub fn setup_eq(k: u32) -> Result<Halo2Setup<EqAffine>, Error> {
+    setup_eq_with_semantics(k, IntersectionSemantics::Set)
+}
+
+/// Setup function for EqAffine curve with the given intersection semantics
+pub fn setup_eq_with_semantics(
+    k: u32,
+    semantics: IntersectionSemantics,
+) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(
+        k,
+        &PsiCircuit {
+            semantics,
+            ..PsiCircuit::default()
+        },
+    )
+}
+
+/// Setup function for EqAffine curve for sets of up to the sizes of
+/// `capacity`, using set semantics
+///
+/// `k` must be at least [`min_k`] of [`PsiCircuit::empty`] for `capacity`.
+pub fn setup_eq_with_capacity(
+    k: u32,
+    capacity: SetCapacity,
+) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(k, &PsiCircuit::empty(capacity))
+}
+
+/// Setup function for EqAffine curve for weighted sum proofs
+pub fn setup_weighted(k: u32) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(
+        k,
+        &PsiCircuit {
+            values_b: Some(vec![]),
+            ..PsiCircuit::default()
+        },
+    )
+}
+
+/// Setup function for EqAffine curve for threshold proofs
+pub fn setup_threshold(
+    k: u32,
+    semantics: IntersectionSemantics,
+) -> Result<Halo2Setup<EqAffine>, Error> {
+    let psi = PsiCircuit {
+        semantics,
+        ..PsiCircuit::default()
+    };
+    keygen(k, &ThresholdPsiCircuit::new(psi, 0))
+}
+
+/// Setup function for EqAffine curve for disjointness proofs over a set of
+/// up to `capacity.max_a` elements and a blocklist of up to `capacity.max_b`
+/// elements
+pub fn setup_disjointness(k: u32, capacity: SetCapacity) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(k, &DisjointnessCircuit::empty(capacity))
+}
+
+/// Setup function for EqAffine curve for subset proofs
+pub fn setup_subset(
+    k: u32,
+    semantics: IntersectionSemantics,
+) -> Result<Halo2Setup<EqAffine>, Error> {
+    let psi = PsiCircuit {
+        semantics,
+        ..PsiCircuit::default()
+    };
+    keygen(k, &SubsetCircuit::new(psi))
+}
+
+/// Setup function for EqAffine curve for multi-party proofs over `parties`
+/// sets of up to `max_size` elements, counting the elements held by at least
+/// `threshold` of them
+pub fn setup_multi(
+    k: u32,
+    max_size: usize,
+    parties: usize,
+    threshold: usize,
+) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(k, &MultiPsiCircuit::empty(max_size, parties, threshold))
+}
+
+/// Setup function for EqAffine curve for lookup proofs of sets of up to
+/// `max_size` elements against `catalogue`
+///
+/// The catalogue is baked into the keys, which need
+/// `2^k > |catalogue| + 2` rows for its table.
+pub fn setup_lookup(
+    k: u32,
+    max_size: usize,
+    catalogue: &[Fp],
+) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(k, &LookupPsiCircuit::empty(max_size, catalogue.to_vec()))
+}
+
+/// Setup function for EqAffine curve for sort-and-merge proofs of sets of up
+/// to the sizes of `capacity`
+pub fn setup_sort(k: u32, capacity: SetCapacity) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(k, &SortPsiCircuit::empty(capacity))
+}
+
+/// Setup function for EqAffine curve for the chunks of a [`ChunkedPsiProof`]
+/// of up to the sizes of `capacity`
+///
+/// [`ChunkedPsiProof`]: crate::ChunkedPsiProof
+pub fn setup_chunk(k: u32, capacity: SetCapacity) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(k, &PsiChunkCircuit::empty(capacity))
+}
+
+/// Setup function for EqAffine curve for the shape of `circuit`
+///
+/// The witness is ignored, so this covers any combination of circuit options,
+/// e.g. a [`PsiCircuit`] built with [`PsiCircuit::with_cardinalities`].
+pub fn setup_for<C: Circuit<Fp>>(k: u32, circuit: &C) -> Result<Halo2Setup<EqAffine>, Error> {
+    keygen(k, &circuit.without_witnesses())
+}
+
+/// Circuit a stored proving or verifying key belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyCircuit {
+    /// A [`PsiCircuit`], or a [`ThresholdPsiCircuit`] if
+    /// [`KeyConfig::threshold`] is set
+    Psi,
+    /// A [`DisjointnessCircuit`] over a set of up to `max_a` elements and a
+    /// blocklist of up to `max_b` elements
+    Disjoint,
+    /// A [`SubsetCircuit`]
+    Subset,
+    /// A [`MultiPsiCircuit`] over `parties` sets of up to `max_a` elements
+    Multi {
+        /// Number of sets
+        parties: usize,
+        /// Minimum number of sets an element is counted in
+        threshold: usize,
+    },
+}
+
+/// Shape of the circuit a stored proving or verifying key belongs to
+///
+/// halo2 cannot serialize keys, so a key file records this configuration with
+/// the digests of the params and of the verifying key. Reading it regenerates
+/// the keys and checks them against the digests, see [`read_proving_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyConfig {
+    /// Circuit size parameter
+    pub k: u32,
+    /// Circuit the key is for
+    pub circuit: KeyCircuit,
+    /// Maximum sizes of the two sets
+    pub capacity: SetCapacity,
+    /// How repeated elements are counted, for PSI and subset keys
+    pub semantics: IntersectionSemantics,
+    /// Whether a PSI key is for a [`ThresholdPsiCircuit`]
+    pub threshold: bool,
+}
+
+impl KeyConfig {
+    /// Configuration of a [`PsiCircuit`] with set semantics
+    pub fn new(k: u32, capacity: SetCapacity) -> Self {
+        Self {
+            k,
+            circuit: KeyCircuit::Psi,
+            capacity,
+            semantics: IntersectionSemantics::Set,
+            threshold: false,
+        }
+    }
+
+    /// Configure the given circuit instead of a [`PsiCircuit`]
+    pub fn with_circuit(mut self, circuit: KeyCircuit) -> Self {
+        self.circuit = circuit;
+        self
+    }
+
+    /// Use the given intersection semantics
+    pub fn with_semantics(mut self, semantics: IntersectionSemantics) -> Self {
+        self.semantics = semantics;
+        self
+    }
+
+    /// Configure a [`ThresholdPsiCircuit`] instead of a [`PsiCircuit`]
+    pub fn with_threshold(mut self, threshold: bool) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Generate params and keys for this configuration
+    pub fn setup(&self) -> Result<Halo2Setup<EqAffine>, Error> {
+        let params = halo2_proofs::poly::commitment::Params::new(self.k);
+        let (pk, vk) = self.keygen(&params)?;
+        Ok((params, pk, vk))
+    }
+
+    /// Generate the keys of this configuration for `params`
+    ///
+    /// Fails with [`Error::Synthesis`] for a threshold key of a circuit other
+    /// than [`KeyCircuit::Psi`] or an unsupported multi-party shape.
+    pub fn keygen(
+        &self,
+        params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    ) -> Result<(ProvingKey<EqAffine>, VerifyingKey<EqAffine>), Error> {
+        match (self.circuit, self.threshold) {
+            (KeyCircuit::Psi, false) => keygen_with_params(params, &self.empty_psi()),
+            (KeyCircuit::Psi, true) => {
+                keygen_with_params(params, &ThresholdPsiCircuit::new(self.empty_psi(), 0))
+            }
+            (KeyCircuit::Disjoint, false) => {
+                keygen_with_params(params, &DisjointnessCircuit::empty(self.capacity))
+            }
+            (KeyCircuit::Subset, false) => {
+                keygen_with_params(params, &SubsetCircuit::new(self.empty_psi()))
+            }
+            (KeyCircuit::Multi { parties, threshold }, false) => keygen_with_params(
+                params,
+                &MultiPsiCircuit::empty(self.capacity.max_a, parties, threshold),
+            ),
+            (_, true) => Err(Error::Synthesis),
+        }
+    }
+
+    /// Generate only the verifying key of this configuration for `params`
+    pub fn keygen_vk(
+        &self,
+        params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    ) -> Result<VerifyingKey<EqAffine>, Error> {
+        match (self.circuit, self.threshold) {
+            (KeyCircuit::Psi, false) => keygen_vk(params, &self.empty_psi()),
+            (KeyCircuit::Psi, true) => {
+                keygen_vk(params, &ThresholdPsiCircuit::new(self.empty_psi(), 0))
+            }
+            (KeyCircuit::Disjoint, false) => {
+                keygen_vk(params, &DisjointnessCircuit::empty(self.capacity))
+            }
+            (KeyCircuit::Subset, false) => keygen_vk(params, &SubsetCircuit::new(self.empty_psi())),
+            (KeyCircuit::Multi { parties, threshold }, false) => keygen_vk(
+                params,
+                &MultiPsiCircuit::empty(self.capacity.max_a, parties, threshold),
+            ),
+            (_, true) => Err(Error::Synthesis),
+        }
+    }
+
+    /// Smallest `k` holding the circuit of this configuration, see [`min_k`]
+    pub fn min_k(&self) -> Result<u32, Error> {
+        match (self.circuit, self.threshold) {
+            (KeyCircuit::Psi, false) => min_k(&self.empty_psi()),
+            (KeyCircuit::Psi, true) => min_k(&ThresholdPsiCircuit::new(self.empty_psi(), 0)),
+            (KeyCircuit::Disjoint, false) => min_k(&DisjointnessCircuit::empty(self.capacity)),
+            (KeyCircuit::Subset, false) => min_k(&SubsetCircuit::new(self.empty_psi())),
+            (KeyCircuit::Multi { parties, threshold }, false) => min_k(&MultiPsiCircuit::empty(
+                self.capacity.max_a,
+                parties,
+                threshold,
+            )),
+            (_, true) => Err(Error::Synthesis),
+        }
+    }
+
+    fn empty_psi(&self) -> PsiCircuit {
+        PsiCircuit {
+            semantics: self.semantics,
+            ..PsiCircuit::empty(self.capacity)
+        }
+    }
+}
+
+/// Transcript writing the common inputs absorbed into it instead of hashing
+/// them
+struct CommonInputWriter<W: io::Write>(W);
+
+impl<W: io::Write> Transcript<EqAffine, Challenge255<EqAffine>> for CommonInputWriter<W> {
+    fn squeeze_challenge(&mut self) -> Challenge255<EqAffine> {
+        unreachable!("keys are only absorbed as common inputs")
+    }
+
+    fn common_point(&mut self, point: EqAffine) -> io::Result<()> {
+        self.0.write_all(&point.to_bytes())
+    }
+
+    fn common_scalar(&mut self, scalar: Fp) -> io::Result<()> {
+        self.0.write_all(&scalar.to_repr())
+    }
+}
+
+/// Digest identifying a verifying key
+///
+/// halo2 keeps the pinned fields of a key private and has no key encoding, so
+/// this hashes the canonical encoding of the scalar that halo2 derives from
+/// them and absorbs into every transcript. Keys with equal digests therefore
+/// verify the same proofs.
+pub fn vk_digest(vk: &VerifyingKey<EqAffine>) -> [u8; 32] {
+    let mut writer = CommonInputWriter(blake3::Hasher::new());
+    vk.hash_into(&mut writer).expect("hashing does not fail");
+    *writer.0.finalize().as_bytes()
+}
+
+/// Write params, so that provers and verifiers load the same ones instead of
+/// regenerating them
+pub fn write_params<W: io::Write>(
+    writer: &mut W,
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+) -> io::Result<()> {
+    params.write(writer)
+}
+
+/// Read params written by [`write_params`]
+///
+/// Fails with [`io::ErrorKind::InvalidData`] for params of a size above
+/// [`MAX_K`], before allocating them.
+pub fn read_params<R: io::Read>(
+    reader: &mut R,
+) -> io::Result<halo2_proofs::poly::commitment::Params<EqAffine>> {
+    let mut k = [0u8; 4];
+    reader.read_exact(&mut k)?;
+    // halo2 allocates 2^k points up front, so reject oversized params first
+    if u32::from_le_bytes(k) > MAX_K {
+        return Err(invalid_data(format!(
+            "params size k={} is above the maximum of {}",
+            u32::from_le_bytes(k),
+            MAX_K
+        )));
+    }
+    halo2_proofs::poly::commitment::Params::read(&mut io::Read::chain(&k[..], reader))
+}
+
+/// Digest identifying params, computed over their [`write_params`] encoding
+pub fn params_digest(params: &halo2_proofs::poly::commitment::Params<EqAffine>) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    params.write(&mut hasher).expect("hashing does not fail");
+    *hasher.finalize().as_bytes()
+}
+
+/// Circuit size parameter `k` of params
+pub fn params_k(params: &halo2_proofs::poly::commitment::Params<EqAffine>) -> u32 {
+    params.get_g().len().trailing_zeros()
+}
+
+/// Contents of a key file
+#[derive(Serialize, Deserialize)]
+struct StoredKey {
+    proving: bool,
+    config: KeyConfig,
+    params_digest: [u8; 32],
+    vk_digest: [u8; 32],
+}
+
+/// Write a proving key generated for `config` with `params`
+pub fn write_proving_key<W: io::Write>(
+    writer: &mut W,
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    config: &KeyConfig,
+    pk: &ProvingKey<EqAffine>,
+) -> io::Result<()> {
+    write_key(writer, true, params, config, pk.get_vk())
+}
+
+/// Write a verifying key generated for `config` with `params`
+pub fn write_verifying_key<W: io::Write>(
+    writer: &mut W,
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    config: &KeyConfig,
+    vk: &VerifyingKey<EqAffine>,
+) -> io::Result<()> {
+    write_key(writer, false, params, config, vk)
+}
+
+fn write_key<W: io::Write>(
+    writer: &mut W,
+    proving: bool,
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    config: &KeyConfig,
+    vk: &VerifyingKey<EqAffine>,
+) -> io::Result<()> {
+    let stored = StoredKey {
+        proving,
+        config: *config,
+        params_digest: params_digest(params),
+        vk_digest: vk_digest(vk),
+    };
+    bincode::serialize_into(writer, &stored).map_err(io::Error::other)
+}
+
+/// Read a proving key written by [`write_proving_key`], regenerating it for
+/// `params`
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the file is not a proving key,
+/// `params` are not the ones the key was generated with, or the regenerated
+/// key differs from the stored one.
+pub fn read_proving_key<R: io::Read>(
+    reader: &mut R,
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+) -> io::Result<(KeyConfig, ProvingKey<EqAffine>)> {
+    let stored = read_key(reader, true, params)?;
+    let (pk, vk) = stored
+        .config
+        .keygen(params)
+        .map_err(|e| invalid_data(format!("cannot regenerate the key: {:?}", e)))?;
+    check_vk_digest(&stored, &vk)?;
+    Ok((stored.config, pk))
+}
+
+/// Read a verifying key written by [`write_verifying_key`], regenerating it
+/// for `params`
+///
+/// Fails like [`read_proving_key`].
+pub fn read_verifying_key<R: io::Read>(
+    reader: &mut R,
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+) -> io::Result<(KeyConfig, VerifyingKey<EqAffine>)> {
+    let stored = read_key(reader, false, params)?;
+    let vk = stored
+        .config
+        .keygen_vk(params)
+        .map_err(|e| invalid_data(format!("cannot regenerate the key: {:?}", e)))?;
+    check_vk_digest(&stored, &vk)?;
+    Ok((stored.config, vk))
+}
+
+fn read_key<R: io::Read>(
+    reader: &mut R,
+    proving: bool,
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+) -> io::Result<StoredKey> {
+    let stored: StoredKey = bincode::deserialize_from(reader)
+        .map_err(|e| invalid_data(format!("malformed key file: {}", e)))?;
+    if stored.proving != proving {
+        let kind = |proving| if proving { "proving" } else { "verifying" };
+        return Err(invalid_data(format!(
+            "expected a {} key, found a {} key",
+            kind(proving),
+            kind(stored.proving)
+        )));
+    }
+    let capacity = stored.config.capacity;
+    if ![capacity.max_a, capacity.max_b]
+        .iter()
+        .all(|max| (1..=MAX_SET_CAPACITY).contains(max))
+    {
+        return Err(invalid_data(format!(
+            "maximum set sizes {} and {} are out of range",
+            capacity.max_a, capacity.max_b
+        )));
+    }
+    if params_digest(params) != stored.params_digest {
+        return Err(invalid_data(
+            "the key was generated with other params".to_string(),
+        ));
+    }
+    Ok(stored)
+}
+
+fn check_vk_digest(stored: &StoredKey, vk: &VerifyingKey<EqAffine>) -> io::Result<()> {
+    if vk_digest(vk) != stored.vk_digest {
+        return Err(invalid_data(
+            "the regenerated key does not match the stored one".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -1,19 +1,146 @@
-use ff::PrimeField;
-use halo2_proofs::{
-    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
-    plonk::{
-        Advice, Circuit, Column, ConstraintSystem, Error, Expression, Instance, ProvingKey,
-        Selector, VerifyingKey, create_proof, keygen_pk, keygen_vk,
-        verify_proof as halo2_verify_proof,
-    },
-    poly::Rotation,
-    transcript::{Blake2bRead, Blake2bWrite, Challenge255},
+use halo2_proofs::plonk::{ProvingKey, VerifyingKey};
+use std::io;
+
+mod chunked;
+mod circuits;
+mod commitment;
+mod envelope;
+mod keys;
+mod proof;
+mod semantics;
+#[cfg(feature = "submit")]
+pub mod submit;
+#[cfg(test)]
+mod tests;
+
+pub use chunked::{
+    ChunkedPsiError, ChunkedPsiProof, PsiChunkProof, chunk_bound, chunk_chain, chunk_chain_step,
+    commit_chunks,
+};
+pub use circuits::{
+    AssignedChallenges, AssignedComparison, AssignedEntry, AssignedMerged, AssignedSet,
+    AssignedSlot, ByteRangeConfig, CircuitError, DisjointnessCircuit, LookupPsiCircuit,
+    LookupPsiConfig, MAX_MERGED_SIZE, MultiPsiCircuit, PackedPsiCircuit, PsiChunkCircuit,
+    PsiChunkConfig, PsiCircuit, PsiConfig, SetCapacity, SortPsiCircuit, SortPsiConfig,
+    SubsetCircuit, ThresholdPsiCircuit,
+};
+pub use commitment::{
+    commit_blocklist, commit_blocklist_with_capacity, commit_set, commit_set_with_capacity,
+    commit_weighted_set, commit_weighted_set_with_capacity, hash_string_to_field, hash_to_field,
+    multi_intersection_size, poseidon_hash_pair,
+};
+pub use envelope::{
+    PROOF_CURVE, PROOF_ENVELOPE_MAGIC, PROOF_ENVELOPE_VERSION, ProofEnvelope, ProofJson,
 };
-use pasta_curves::{EqAffine, Fp};
-use rand::rngs::OsRng;
+pub use keys::{
+    KeyCircuit, KeyConfig, params_digest, params_k, read_params, read_proving_key,
+    read_verifying_key, setup_chunk, setup_disjointness, setup_eq, setup_eq_with_capacity,
+    setup_eq_with_semantics, setup_for, setup_lookup, setup_multi, setup_sort, setup_subset,
+    setup_threshold, setup_weighted, vk_digest, write_params, write_proving_key,
+    write_verifying_key,
+};
+pub use proof::{
+    BatchVerifyError, LayoutSize, generate_multi_proof, generate_proof, keygen_with_params,
+    layout_size, min_k, prove_disjointness, prove_multi, prove_subset, prove_threshold,
+    verify_disjointness, verify_multi, verify_multi_proof, verify_proof, verify_proofs_batch,
+    verify_subset, verify_threshold,
+};
+pub use semantics::{Cardinalities, IntersectionSemantics};
 
+/// Default maximum size of each set, see [`SetCapacity`]
 pub const MAX_SET_SIZE: usize = 32;
 
+/// Largest maximum set size a [`SetCapacity`] accepts
+///
+/// This bounds the comparison grid to `2^20` rows, which keeps weighted sums
+/// below `2^52`.
+pub const MAX_SET_CAPACITY: usize = 1 << 10;
+
+/// Largest circuit size parameter `k` that [`read_params`] accepts
+///
+/// This is the `k` the two-set circuits need at [`MAX_SET_CAPACITY`].
+pub const MAX_K: u32 = 22;
+
+/// Instance column row holding the intersection size
+pub const INTERSECTION_SIZE_ROW: usize = 0;
+/// Instance column row holding the weighted sum of a weighted [`PsiCircuit`]
+pub const WEIGHTED_SUM_ROW: usize = 0;
+/// Instance column row holding |A| when a [`PsiCircuit`] exposes cardinalities;
+/// |B|, |A ∩ B| and |A ∪ B| follow in the next rows
+pub const CARDINALITIES_ROW: usize = 3;
+/// Instance column row holding the threshold of a [`ThresholdPsiCircuit`]
+pub const THRESHOLD_ROW: usize = 0;
+/// Instance column row holding the Poseidon commitment to set A
+pub const COMMITMENT_A_ROW: usize = 1;
+/// Instance column row holding the Poseidon commitment to set B
+pub const COMMITMENT_B_ROW: usize = 2;
+
+/// Instance column row holding the commitment to the private set of a
+/// [`DisjointnessCircuit`]
+pub const DISJOINT_SET_COMMITMENT_ROW: usize = 0;
+/// Instance column row holding the blocklist commitment of a [`DisjointnessCircuit`]
+pub const BLOCKLIST_COMMITMENT_ROW: usize = 1;
+
+/// Instance column row holding the commitment to the subset of a [`SubsetCircuit`]
+pub const SUBSET_COMMITMENT_ROW: usize = 0;
+/// Instance column row holding the commitment to the superset of a [`SubsetCircuit`]
+pub const SUPERSET_COMMITMENT_ROW: usize = 1;
+
+/// Instance column row holding the count of a [`MultiPsiCircuit`]
+pub const MULTI_COUNT_ROW: usize = 0;
+/// Instance column row holding the commitment to the first set of a
+/// [`MultiPsiCircuit`]; the commitments to the other sets follow in order
+pub const MULTI_COMMITMENTS_ROW: usize = 1;
+/// Instance column row holding the running count a [`PsiChunkCircuit`]
+/// continues from
+pub const CHUNK_COUNT_IN_ROW: usize = 0;
+/// Instance column row holding the running count after a [`PsiChunkCircuit`]
+pub const CHUNK_COUNT_OUT_ROW: usize = 1;
+/// Instance column row holding the hash chain a [`PsiChunkCircuit`]
+/// continues from
+pub const CHUNK_CHAIN_IN_ROW: usize = 2;
+/// Instance column row holding the hash chain after a [`PsiChunkCircuit`]
+pub const CHUNK_CHAIN_OUT_ROW: usize = 3;
+/// Instance column row holding the commitment to the lower bound of the
+/// chunk of A of a [`PsiChunkCircuit`] (see [`chunk_bound`])
+pub const CHUNK_BOUND_A_IN_ROW: usize = 4;
+/// Instance column row holding the commitment to the bound after the chunk of
+/// A of a [`PsiChunkCircuit`]
+pub const CHUNK_BOUND_A_OUT_ROW: usize = 5;
+/// Instance column row holding the commitment to the lower bound of the
+/// chunk of B of a [`PsiChunkCircuit`]
+pub const CHUNK_BOUND_B_IN_ROW: usize = 6;
+/// Instance column row holding the commitment to the bound after the chunk of
+/// B of a [`PsiChunkCircuit`]
+pub const CHUNK_BOUND_B_OUT_ROW: usize = 7;
+/// Maximum number of sets in a [`MultiPsiCircuit`]
+pub const MAX_PARTIES: usize = 32;
+
+/// Bits of the elements of a [`LookupPsiCircuit`] or a [`SortPsiCircuit`]
+///
+/// Elements are ordered as integers below `2^ELEMENT_BITS`, which holds for
+/// the 31-byte outputs of [`hash_to_field`] and [`hash_string_to_field`].
+pub const ELEMENT_BITS: usize = 248;
+
+/// Bits of the private per-element values of a weighted [`PsiCircuit`]
+///
+/// Values are range checked to this many bits, so the weighted sum over the
+/// full grid stays below `MAX_SET_CAPACITY^2 * 2^VALUE_BITS`, far from both
+/// the field modulus and `u64::MAX`.
+pub const VALUE_BITS: usize = u32::BITS as usize;
+
+/// Bits needed to range check a difference of party counts, which are bounded
+/// by `MAX_PARTIES`
+const TALLY_BITS: usize = range_bits(MAX_PARTIES);
+
+// The tally reads party counts from the low byte of their encoding
+const _: () = assert!(MAX_PARTIES < 1 << 8);
+
+/// Bits needed to range check a difference of counts bounded by `bound`
+const fn range_bits(bound: usize) -> usize {
+    (usize::BITS - bound.leading_zeros()) as usize
+}
+
 type Halo2Setup<E> = (
     halo2_proofs::poly::commitment::Params<E>,
     ProvingKey<E>,
@@ -35,315 +162,6 @@ pub fn draw_circuit(k: u32, circuit: &PsiCircuit) {
         .unwrap();
 }
 
-pub fn hash_to_field(value: u64) -> Fp {
-    let bytes = value.to_le_bytes();
-    let hash = blake3::hash(&bytes);
-    let hash_bytes = hash.as_bytes();
-
-    let mut repr = [0u8; 32];
-    repr[..31].copy_from_slice(&hash_bytes[..31]);
-
-    Fp::from_repr(repr).unwrap()
-}
-
-/// Hash a string to a field element
-pub fn hash_string_to_field(s: &str) -> Fp {
-    let hash = blake3::hash(s.as_bytes());
-    let hash_bytes = hash.as_bytes();
-
-    let mut repr = [0u8; 32];
-    repr[..31].copy_from_slice(&hash_bytes[..31]);
-
-    Fp::from_repr(repr).unwrap()
-}
-
-#[derive(Debug, Clone)]
-pub struct PsiConfig {
-    /// Advice columns for set A elements
-    set_a: Column<Advice>,
-    /// Advice columns for set B elements
-    set_b: Column<Advice>,
-    /// Advice column for match bits (1 if elements match, 0 otherwise)
-    match_bit: Column<Advice>,
-    /// Advice column for running sum of matches
-    sum: Column<Advice>,
-    /// Selector for equality check gates
-    q_equality: Selector,
-    /// Selector for sum gates
-    q_sum: Selector,
-    /// Instance column for public intersection size
-    instance: Column<Instance>,
-}
-
-impl PsiConfig {
-    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
-        let set_a = meta.advice_column();
-        let set_b = meta.advice_column();
-        let match_bit = meta.advice_column();
-        let sum = meta.advice_column();
-        let instance = meta.instance_column();
-
-        meta.enable_equality(set_a);
-        meta.enable_equality(set_b);
-        meta.enable_equality(match_bit);
-        meta.enable_equality(sum);
-        meta.enable_equality(instance);
-
-        let q_equality = meta.selector();
-        let q_sum = meta.selector();
-
-        // Equality gate: Ensures match_bit is correct
-        // If set_a[i] == set_b[j], then match_bit must be 1, else 0
-        // Constraint: match_bit * (match_bit - 1) == 0 (boolean constraint)
-        // Constraint: (set_a - set_b) * (1 - match_bit) == 0 (if equal, match_bit must be 1)
-        meta.create_gate("equality check", |meta| {
-            let q = meta.query_selector(q_equality);
-            let a = meta.query_advice(set_a, Rotation::cur());
-            let b = meta.query_advice(set_b, Rotation::cur());
-            let match_bit = meta.query_advice(match_bit, Rotation::cur());
-
-            vec![
-                // match_bit is boolean
-                q.clone()
-                    * (match_bit.clone() * (match_bit.clone() - Expression::Constant(Fp::one()))),
-                // if a == b, then match_bit must be 1
-                q * (a - b) * (Expression::Constant(Fp::one()) - match_bit),
-            ]
-        });
-
-        // Sum gate: Accumulates the match count
-        // sum[i] = sum[i-1] + match_bit[i]
-        meta.create_gate("sum accumulator", |meta| {
-            let q = meta.query_selector(q_sum);
-            let sum_prev = meta.query_advice(sum, Rotation::prev());
-            let sum_cur = meta.query_advice(sum, Rotation::cur());
-            let match_bit = meta.query_advice(match_bit, Rotation::cur());
-
-            vec![q * (sum_cur - sum_prev - match_bit)]
-        });
-
-        Self {
-            set_a,
-            set_b,
-            match_bit,
-            sum,
-            q_equality,
-            q_sum,
-            instance,
-        }
-    }
-
-    /// Assign a single comparison and update running sum
-    pub fn assign_comparison(
-        &self,
-        mut layouter: impl Layouter<Fp>,
-        a_val: Fp,
-        b_val: Fp,
-        prev_sum: Option<AssignedCell<Fp, Fp>>,
-        offset: usize,
-    ) -> Result<AssignedCell<Fp, Fp>, Error> {
-        layouter.assign_region(
-            || format!("comparison row {}", offset),
-            |mut region| {
-                self.q_equality.enable(&mut region, 0)?;
-                if offset > 0 {
-                    self.q_sum.enable(&mut region, 0)?;
-                }
-
-                region.assign_advice(|| "set_a", self.set_a, 0, || Value::known(a_val))?;
-
-                region.assign_advice(|| "set_b", self.set_b, 0, || Value::known(b_val))?;
-
-                let is_equal = a_val == b_val;
-                let match_bit_val = if is_equal { Fp::one() } else { Fp::zero() };
-
-                region.assign_advice(
-                    || "match_bit",
-                    self.match_bit,
-                    0,
-                    || Value::known(match_bit_val),
-                )?;
-
-                let new_sum = if let Some(ref prev) = prev_sum {
-                    prev.value().copied() + Value::known(match_bit_val)
-                } else {
-                    Value::known(match_bit_val)
-                };
-
-                let sum_cell = region.assign_advice(|| "sum", self.sum, 0, || new_sum)?;
-
-                Ok(sum_cell)
-            },
-        )
-    }
-}
-
-/// PSI Circuit structure
-#[derive(Debug, Clone, Default)]
-pub struct PsiCircuit {
-    /// First set of hashed elements
-    pub set_a: Vec<Fp>,
-    /// Second set of hashed elements
-    pub set_b: Vec<Fp>,
-    /// Expected intersection size (public input)
-    pub intersection_size: u64,
-}
-
-impl PsiCircuit {
-    /// Create a new PSI circuit with two sets
-    pub fn new(set_a: Vec<Fp>, set_b: Vec<Fp>, intersection_size: u64) -> Self {
-        assert!(set_a.len() <= MAX_SET_SIZE, "Set A exceeds maximum size");
-        assert!(set_b.len() <= MAX_SET_SIZE, "Set B exceeds maximum size");
-
-        Self {
-            set_a,
-            set_b,
-            intersection_size,
-        }
-    }
-
-    /// Compute the actual intersection size (for witness generation)
-    pub fn compute_intersection_size(&self) -> u64 {
-        let mut count = 0u64;
-        for a in &self.set_a {
-            for b in &self.set_b {
-                if a == b {
-                    count += 1;
-                    break; // Count each element in A only once
-                }
-            }
-        }
-        count
-    }
-}
-
-impl Circuit<Fp> for PsiCircuit {
-    type Config = PsiConfig;
-    type FloorPlanner = SimpleFloorPlanner;
-
-    fn without_witnesses(&self) -> Self {
-        Self::default()
-    }
-
-    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
-        PsiConfig::configure(meta)
-    }
-
-    fn synthesize(
-        &self,
-        config: Self::Config,
-        mut layouter: impl Layouter<Fp>,
-    ) -> Result<(), Error> {
-        let mut sum_cell: Option<AssignedCell<Fp, Fp>> = None;
-        let mut row = 0;
-
-        // Compare each element in set_a with each element in set_b
-        for a in &self.set_a {
-            for b in &self.set_b {
-                sum_cell = Some(config.assign_comparison(
-                    layouter.namespace(|| format!("comparison {}", row)),
-                    *a,
-                    *b,
-                    sum_cell.clone(),
-                    row,
-                )?);
-                row += 1;
-            }
-        }
-
-        // Expose the final sum as a public input
-        if let Some(final_sum) = sum_cell {
-            layouter.constrain_instance(final_sum.cell(), config.instance, 0)?;
-        }
-
-        Ok(())
-    }
-}
-
-/// Simplified setup function for EqAffine curve
-pub fn setup_eq(k: u32) -> Result<Halo2Setup<EqAffine>, Error> {
-    let params = halo2_proofs::poly::commitment::Params::<EqAffine>::new(k);
-    let empty_circuit = PsiCircuit::default();
-
-    let vk = keygen_vk(&params, &empty_circuit)?;
-    let pk = keygen_pk(&params, vk.clone(), &empty_circuit)?;
-
-    Ok((params, pk, vk))
-}
-
-/// Generate a proof for the PSI circuit
-pub fn generate_proof(
-    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
-    pk: &ProvingKey<EqAffine>,
-    circuit: PsiCircuit,
-    public_inputs: &[Fp],
-) -> Result<Vec<u8>, Error> {
-    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
-
-    create_proof(
-        params,
-        pk,
-        &[circuit],
-        &[&[public_inputs]],
-        OsRng,
-        &mut transcript,
-    )?;
-
-    Ok(transcript.finalize())
-}
-
-/// Verify a proof for the PSI circuit
-pub fn verify_proof(
-    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
-    vk: &VerifyingKey<EqAffine>,
-    proof: &[u8],
-    public_inputs: &[Fp],
-) -> Result<(), Error> {
-    let strategy = halo2_proofs::plonk::SingleVerifier::new(params);
-    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
-
-    halo2_verify_proof(params, vk, strategy, &[&[public_inputs]], &mut transcript)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_hash_to_field() {
-        let h1 = hash_to_field(42);
-        let h2 = hash_to_field(42);
-        let h3 = hash_to_field(43);
-
-        assert_eq!(h1, h2);
-        assert_ne!(h1, h3);
-    }
-
-    #[test]
-    fn test_intersection_computation() {
-        let set_a = vec![hash_to_field(1), hash_to_field(2), hash_to_field(3)];
-        let set_b = vec![hash_to_field(2), hash_to_field(3), hash_to_field(4)];
-
-        let circuit = PsiCircuit::new(set_a, set_b, 2);
-        assert_eq!(circuit.compute_intersection_size(), 2);
-    }
-
-    #[test]
-    fn test_full_proof_verification_flow() {
-        let set_a = vec![hash_to_field(1), hash_to_field(2)];
-        let set_b = vec![hash_to_field(2), hash_to_field(3)];
-
-        let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
-        let intersection_size = circuit.compute_intersection_size();
-        assert_eq!(intersection_size, 1);
-
-        let k = 10;
-        let (params, pk, vk) = setup_eq(k).unwrap();
-
-        let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
-        let public_inputs = vec![Fp::from(intersection_size)];
-
-        let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
-        verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
-    }
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
 }
@@ -0,0 +1,422 @@
+//! Proof generation and verification for the circuits
+
+use crate::{DisjointnessCircuit, Halo2Setup, MultiPsiCircuit, SubsetCircuit, ThresholdPsiCircuit};
+use halo2_proofs::circuit::Value;
+use halo2_proofs::plonk::{
+    Advice, Any, Assigned, Assignment, BatchVerifier, Circuit, Column, ConstraintSystem, Error,
+    Fixed, FloorPlanner, Instance, ProvingKey, Selector, VerifyingKey, create_proof, keygen_pk,
+    keygen_vk, verify_proof as halo2_verify_proof,
+};
+use halo2_proofs::transcript::{Blake2bRead, Blake2bWrite, Challenge255};
+use pasta_curves::{EqAffine, Fp};
+use rand::rngs::OsRng;
+use std::collections::HashSet;
+
+/// Smallest `k` whose `2^k` rows hold the layout of `circuit`
+///
+/// The circuit is laid out without computing any witness, so this is cheap
+/// even for shapes that need a large `k`.
+pub fn min_k<C: Circuit<Fp>>(circuit: &C) -> Result<u32, Error> {
+    let mut meta = ConstraintSystem::default();
+    C::configure(&mut meta);
+
+    let rows = (layout_size(circuit)?.rows + meta.blinding_factors() + 1).max(meta.minimum_rows());
+    Ok(rows.next_power_of_two().trailing_zeros())
+}
+
+/// Rows and columns taken by the layout of a circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutSize {
+    /// Rows assigned by the layout, without the blinding rows the proof
+    /// system adds
+    pub rows: usize,
+    /// Advice columns holding assigned cells
+    pub advice_columns: usize,
+}
+
+/// Size of the layout of `circuit`, laid out without computing any witness
+pub fn layout_size<C: Circuit<Fp>>(circuit: &C) -> Result<LayoutSize, Error> {
+    let mut meta = ConstraintSystem::default();
+    let config = C::configure(&mut meta);
+    // The circuits keep their constants in a dedicated fixed column, which a
+    // fresh column stands in for
+    let constants = vec![meta.fixed_column()];
+
+    let mut counter = RowCounter::default();
+    C::FloorPlanner::synthesize(&mut counter, circuit, config, constants)?;
+
+    Ok(LayoutSize {
+        rows: counter.rows,
+        advice_columns: counter.advice.len(),
+    })
+}
+
+/// Assignment that only records the rows and advice columns a layout uses
+#[derive(Default)]
+struct RowCounter {
+    rows: usize,
+    advice: HashSet<Column<Advice>>,
+}
+
+impl RowCounter {
+    fn touch(&mut self, row: usize) -> Result<(), Error> {
+        self.rows = self.rows.max(row + 1);
+        Ok(())
+    }
+}
+
+impl Assignment<Fp> for RowCounter {
+    fn enter_region<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn exit_region(&mut self) {}
+
+    fn enable_selector<A, AR>(&mut self, _: A, _: &Selector, row: usize) -> Result<(), Error>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row)
+    }
+
+    fn query_instance(&self, _: Column<Instance>, _: usize) -> Result<Value<Fp>, Error> {
+        Ok(Value::unknown())
+    }
+
+    fn assign_advice<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        column: Column<Advice>,
+        row: usize,
+        _: V,
+    ) -> Result<(), Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.advice.insert(column);
+        self.touch(row)
+    }
+
+    fn assign_fixed<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Fixed>,
+        row: usize,
+        _: V,
+    ) -> Result<(), Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row)
+    }
+
+    fn copy(&mut self, _: Column<Any>, _: usize, _: Column<Any>, _: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn fill_from_row(
+        &mut self,
+        _: Column<Fixed>,
+        _: usize,
+        _: Value<Assigned<Fp>>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _: Option<String>) {}
+}
+
+/// Generate params and keys for the shape of `empty_circuit`
+pub(crate) fn keygen<C: Circuit<Fp>>(
+    k: u32,
+    empty_circuit: &C,
+) -> Result<Halo2Setup<EqAffine>, Error> {
+    let params = halo2_proofs::poly::commitment::Params::<EqAffine>::new(k);
+    let (pk, vk) = keygen_with_params(&params, empty_circuit)?;
+
+    Ok((params, pk, vk))
+}
+
+/// Generate keys for the shape of `empty_circuit` with existing params, e.g.
+/// params loaded with [`read_params`]
+///
+/// [`read_params`]: crate::read_params
+pub fn keygen_with_params<C: Circuit<Fp>>(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    empty_circuit: &C,
+) -> Result<(ProvingKey<EqAffine>, VerifyingKey<EqAffine>), Error> {
+    let vk = keygen_vk(params, empty_circuit)?;
+    let pk = keygen_pk(params, vk.clone(), empty_circuit)?;
+
+    Ok((pk, vk))
+}
+
+/// Generate a proof for the PSI circuit, or for a [`LookupPsiCircuit`] or
+/// [`SortPsiCircuit`]
+///
+/// [`LookupPsiCircuit`]: crate::LookupPsiCircuit
+/// [`SortPsiCircuit`]: crate::SortPsiCircuit
+pub fn generate_proof<C: Circuit<Fp>>(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: C,
+    public_inputs: &[Fp],
+) -> Result<Vec<u8>, Error> {
+    prove(params, pk, circuit, public_inputs)
+}
+
+/// Generate a proof that the intersection size is at least the threshold
+pub fn prove_threshold(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: ThresholdPsiCircuit,
+    public_inputs: &[Fp],
+) -> Result<Vec<u8>, Error> {
+    prove(params, pk, circuit, public_inputs)
+}
+
+/// Generate a proof that a private set is disjoint from a public blocklist
+pub fn prove_disjointness(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: DisjointnessCircuit,
+    public_inputs: &[Fp],
+) -> Result<Vec<u8>, Error> {
+    prove(params, pk, circuit, public_inputs)
+}
+
+/// Generate a proof that set A is contained in set B
+pub fn prove_subset(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: SubsetCircuit,
+    public_inputs: &[Fp],
+) -> Result<Vec<u8>, Error> {
+    prove(params, pk, circuit, public_inputs)
+}
+
+/// Generate a proof of the number of elements shared by several sets
+pub fn prove_multi(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: MultiPsiCircuit,
+    public_inputs: &[Fp],
+) -> Result<Vec<u8>, Error> {
+    prove(params, pk, circuit, public_inputs)
+}
+
+/// Generate one proof covering several circuits proven with the same key
+///
+/// `public_inputs[i]` are the public inputs of `circuits[i]`. The proof is
+/// only accepted by [`verify_multi_proof`] with the same public inputs in
+/// the same order.
+pub fn generate_multi_proof<C: Circuit<Fp>>(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuits: Vec<C>,
+    public_inputs: &[Vec<Fp>],
+) -> Result<Vec<u8>, Error> {
+    if circuits.is_empty() || circuits.len() != public_inputs.len() {
+        return Err(Error::InvalidInstances);
+    }
+    let instances: Vec<[&[Fp]; 1]> = public_inputs.iter().map(|inputs| [&inputs[..]]).collect();
+    let instances: Vec<&[&[Fp]]> = instances.iter().map(|columns| &columns[..]).collect();
+    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+
+    create_proof(params, pk, &circuits, &instances, OsRng, &mut transcript)?;
+
+    Ok(transcript.finalize())
+}
+
+/// Create a proof for any of the PSI circuits
+pub(crate) fn prove<C: Circuit<Fp>>(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: C,
+    public_inputs: &[Fp],
+) -> Result<Vec<u8>, Error> {
+    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+
+    create_proof(
+        params,
+        pk,
+        &[circuit],
+        &[&[public_inputs]],
+        OsRng,
+        &mut transcript,
+    )?;
+
+    Ok(transcript.finalize())
+}
+
+/// Verify a proof for the PSI circuit
+pub fn verify_proof(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    public_inputs: &[Fp],
+) -> Result<(), Error> {
+    let strategy = halo2_proofs::plonk::SingleVerifier::new(params);
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+
+    halo2_verify_proof(params, vk, strategy, &[&[public_inputs]], &mut transcript)
+}
+
+/// Verify a proof from [`generate_multi_proof`] against the public inputs of
+/// every circuit it covers, in proving order
+pub fn verify_multi_proof(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    public_inputs: &[Vec<Fp>],
+) -> Result<(), Error> {
+    if public_inputs.is_empty() {
+        return Err(Error::InvalidInstances);
+    }
+    let instances: Vec<[&[Fp]; 1]> = public_inputs.iter().map(|inputs| [&inputs[..]]).collect();
+    let instances: Vec<&[&[Fp]]> = instances.iter().map(|columns| &columns[..]).collect();
+    let strategy = halo2_proofs::plonk::SingleVerifier::new(params);
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+
+    halo2_verify_proof(params, vk, strategy, &instances, &mut transcript)
+}
+
+/// Verify a threshold proof against the public threshold and set commitments
+///
+/// `vk` must come from [`setup_threshold`]; the public inputs are laid out as
+/// returned by [`ThresholdPsiCircuit::public_inputs`].
+///
+/// [`setup_threshold`]: crate::setup_threshold
+pub fn verify_threshold(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    public_inputs: &[Fp],
+) -> Result<(), Error> {
+    verify_proof(params, vk, proof, public_inputs)
+}
+
+/// Verify a disjointness proof against the set and blocklist commitments
+///
+/// `vk` must come from [`setup_disjointness`]; the public inputs are laid out
+/// as returned by [`DisjointnessCircuit::public_inputs`]. Verifiers should
+/// compute the blocklist commitment themselves with [`commit_blocklist`].
+///
+/// [`setup_disjointness`]: crate::setup_disjointness
+/// [`commit_blocklist`]: crate::commit_blocklist
+pub fn verify_disjointness(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    public_inputs: &[Fp],
+) -> Result<(), Error> {
+    verify_proof(params, vk, proof, public_inputs)
+}
+
+/// Verify a subset proof against the two set commitments
+///
+/// `vk` must come from [`setup_subset`]; the public inputs are laid out as
+/// returned by [`SubsetCircuit::public_inputs`].
+///
+/// [`setup_subset`]: crate::setup_subset
+pub fn verify_subset(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    public_inputs: &[Fp],
+) -> Result<(), Error> {
+    verify_proof(params, vk, proof, public_inputs)
+}
+
+/// Verify a multi-party proof against the count and the set commitments
+///
+/// `vk` must come from [`setup_multi`] with the number of sets and threshold
+/// of the prover; the public inputs are laid out as returned by
+/// [`MultiPsiCircuit::public_inputs`].
+///
+/// [`setup_multi`]: crate::setup_multi
+pub fn verify_multi(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    public_inputs: &[Fp],
+) -> Result<(), Error> {
+    verify_proof(params, vk, proof, public_inputs)
+}
+
+/// Proofs of a batch rejected by [`verify_proofs_batch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchVerifyError {
+    /// Indices of the rejected proofs in the batch, in ascending order
+    pub failed: Vec<usize>,
+}
+
+impl std::fmt::Display for BatchVerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Proofs {:?} of the batch failed verification",
+            self.failed
+        )
+    }
+}
+
+impl std::error::Error for BatchVerifyError {}
+
+/// Verify many proofs against the same verifying key at once
+///
+/// Each entry is a proof with its public inputs. The proofs are checked with
+/// halo2's `BatchVerifier`, which folds their final multi-scalar
+/// multiplications into one, so a batch costs much less than verifying every
+/// proof on its own. If the batch is rejected, every proof is verified
+/// separately to report the failing ones.
+pub fn verify_proofs_batch<P: AsRef<[u8]>, I: AsRef<[Fp]>>(
+    params: &halo2_proofs::poly::commitment::Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proofs: &[(P, I)],
+) -> Result<(), BatchVerifyError> {
+    let mut batch = BatchVerifier::new();
+    for (proof, public_inputs) in proofs {
+        batch.add_proof(
+            vec![vec![public_inputs.as_ref().to_vec()]],
+            proof.as_ref().to_vec(),
+        );
+    }
+    if batch.finalize(params, vk) {
+        return Ok(());
+    }
+
+    let failed: Vec<usize> = proofs
+        .iter()
+        .enumerate()
+        .filter(|(_, (proof, public_inputs))| {
+            verify_proof(params, vk, proof.as_ref(), public_inputs.as_ref()).is_err()
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    // The batch can only be rejected for valid proofs with negligible
+    // probability, in which case the separate checks are authoritative
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(BatchVerifyError { failed })
+    }
+}
@@ -0,0 +1,104 @@
+//! Intersection semantics and the cardinalities a circuit can expose
+
+use crate::CARDINALITIES_ROW;
+use ff::PrimeField;
+use pasta_curves::Fp;
+use serde::{Deserialize, Serialize};
+
+/// How repeated elements are counted in the intersection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IntersectionSemantics {
+    /// Sets must not contain duplicates, which the circuit rejects; the
+    /// intersection size is the number of common elements.
+    #[default]
+    Set,
+    /// Sets may contain duplicates; every element `x` counts
+    /// `min(mult_A(x), mult_B(x))` times.
+    Multiset,
+}
+
+impl IntersectionSemantics {
+    /// Native intersection size of two sets under these semantics
+    pub fn intersection_size(&self, set_a: &[Fp], set_b: &[Fp]) -> u64 {
+        match self {
+            Self::Set => {
+                let distinct_a = set_a
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, a)| !set_a[..*i].contains(a));
+                distinct_a.filter(|(_, a)| set_b.contains(a)).count() as u64
+            }
+            Self::Multiset => {
+                // Pair each element of A with a distinct, still unpaired equal
+                // element of B. This is the same greedy matching the circuit
+                // witnesses, and its size is the sum of min multiplicities.
+                let mut paired_b = vec![false; set_b.len()];
+                let mut count = 0u64;
+                for a in set_a {
+                    if let Some(j) = (0..set_b.len()).find(|&j| !paired_b[j] && set_b[j] == *a) {
+                        paired_b[j] = true;
+                        count += 1;
+                    }
+                }
+                count
+            }
+        }
+    }
+}
+
+/// Set sizes exposed by a [`PsiCircuit`] built with
+/// [`PsiCircuit::with_cardinalities`]
+///
+/// [`PsiCircuit`]: crate::PsiCircuit
+/// [`PsiCircuit::with_cardinalities`]: crate::PsiCircuit::with_cardinalities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cardinalities {
+    /// |A|
+    pub size_a: u64,
+    /// |B|
+    pub size_b: u64,
+    /// |A ∩ B|
+    pub intersection: u64,
+    /// |A ∪ B|
+    pub union: u64,
+}
+
+impl Cardinalities {
+    /// Read the cardinalities from the public inputs of a verified proof.
+    ///
+    /// Returns `None` if the inputs are too short, hold values that are not
+    /// small integers, or are inconsistent with `|A ∪ B| = |A| + |B| - |A ∩ B|`.
+    pub fn from_public_inputs(public_inputs: &[Fp]) -> Option<Self> {
+        let values = public_inputs.get(CARDINALITIES_ROW..CARDINALITIES_ROW + 4)?;
+        let mut sizes = [0u64; 4];
+        for (size, value) in sizes.iter_mut().zip(values) {
+            let repr = value.to_repr();
+            let (low, high) = repr.as_ref().split_at(8);
+            if high.iter().any(|b| *b != 0) {
+                return None;
+            }
+            *size = u64::from_le_bytes(low.try_into().ok()?);
+        }
+
+        let [size_a, size_b, intersection, union] = sizes;
+        (size_a.checked_add(size_b)? == union.checked_add(intersection)?).then_some(Self {
+            size_a,
+            size_b,
+            intersection,
+            union,
+        })
+    }
+
+    /// The public inputs encoding these cardinalities, in instance column order
+    pub fn to_public_inputs(&self) -> Vec<Fp> {
+        [self.size_a, self.size_b, self.intersection, self.union]
+            .into_iter()
+            .map(Fp::from)
+            .collect()
+    }
+
+    /// Jaccard similarity |A ∩ B| / |A ∪ B|, or `None` if both sets are empty
+    pub fn jaccard(&self) -> Option<f64> {
+        (self.union > 0).then(|| self.intersection as f64 / self.union as f64)
+    }
+}
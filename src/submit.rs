@@ -0,0 +1,167 @@
+//! Client of the zk-psi submission protocol, behind the `submit` feature
+//!
+//! The protocol is this crate's own, not the RPC API of any chain: a gateway
+//! serving it takes proofs, passes them on to wherever they are settled and
+//! reports their progress. It is JSON-RPC 2.0 over HTTP or HTTPS, with two
+//! methods.
+//!
+//! - [`SUBMIT_PROOF_METHOD`] takes a [`ProofJson`] as its only parameter and
+//!   returns a submission id string.
+//! - [`PROOF_STATUS_METHOD`] takes that id as its only parameter and returns
+//!   the [`SubmissionStatus`] as a string, e.g. `"Pending"`.
+//!
+//! Errors are JSON-RPC error objects. The crate ships no gateway;
+//! tests/submission.rs runs the client against an in-process mock.
+
+use crate::{ProofJson, invalid_data};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Gateway method taking a [`ProofJson`] and returning a submission id
+pub const SUBMIT_PROOF_METHOD: &str = "zkpsi_submitProof";
+
+/// Gateway method taking a submission id and returning its
+/// [`SubmissionStatus`]
+pub const PROOF_STATUS_METHOD: &str = "zkpsi_proofStatus";
+
+/// Progress of a proof submitted with [`SubmissionClient`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubmissionStatus {
+    /// Accepted by the gateway, not yet in a block
+    Pending,
+    /// Verified and included in a block
+    IncludedInBlock,
+    /// Included in a finalized block, which attests the proof
+    Finalized,
+    /// Rejected, e.g. because the proof does not verify
+    Failed,
+}
+
+impl SubmissionStatus {
+    /// Whether the status can no longer change
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Finalized | Self::Failed)
+    }
+}
+
+/// Client submitting [`ProofJson`] exports to a gateway and tracking their
+/// status, see the [module documentation](self)
+#[derive(Debug, Clone)]
+pub struct SubmissionClient {
+    url: String,
+    agent: ureq::Agent,
+    poll_interval: Duration,
+    timeout: Duration,
+}
+
+impl SubmissionClient {
+    /// Client of the gateway at `url`, e.g. `https://gateway.example/rpc`
+    pub fn new(url: &str) -> io::Result<Self> {
+        let timeout = Duration::from_secs(600);
+        let agent = agent(timeout);
+        let parsed = agent.post(url).request_url().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid endpoint {:?}: {}", url, e),
+            )
+        })?;
+        if !["http", "https"].contains(&parsed.scheme()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("endpoint {:?} is not an http:// or https:// URL", url),
+            ));
+        }
+        Ok(Self {
+            url: url.to_string(),
+            agent,
+            poll_interval: Duration::from_secs(5),
+            timeout,
+        })
+    }
+
+    /// Time between status requests in [`SubmissionClient::wait`]
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Time [`SubmissionClient::wait`] waits for a final status, also bounding
+    /// each request
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = agent(timeout);
+        self.timeout = timeout;
+        self
+    }
+
+    /// Submit a proof, returning the id to track it by
+    pub fn submit(&self, proof: &ProofJson) -> io::Result<String> {
+        let result = self.call(SUBMIT_PROOF_METHOD, serde_json::json!([proof]))?;
+        serde_json::from_value(result)
+            .map_err(|e| invalid_data(format!("malformed submission id: {}", e)))
+    }
+
+    /// Current status of a submission
+    pub fn status(&self, id: &str) -> io::Result<SubmissionStatus> {
+        let result = self.call(PROOF_STATUS_METHOD, serde_json::json!([id]))?;
+        serde_json::from_value(result)
+            .map_err(|e| invalid_data(format!("malformed submission status: {}", e)))
+    }
+
+    /// Poll the status of a submission until it is final, passing every
+    /// status to `on_status`
+    ///
+    /// Fails with [`io::ErrorKind::TimedOut`] if the status is not final
+    /// within the timeout.
+    pub fn wait(
+        &self,
+        id: &str,
+        mut on_status: impl FnMut(SubmissionStatus),
+    ) -> io::Result<SubmissionStatus> {
+        let start = Instant::now();
+        loop {
+            let status = self.status(id)?;
+            on_status(status);
+            if status.is_final() {
+                return Ok(status);
+            }
+            if start.elapsed() + self.poll_interval > self.timeout {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("submission {} is still {:?}", id, status),
+                ));
+            }
+            std::thread::sleep(self.poll_interval);
+        }
+    }
+
+    /// Send a JSON-RPC request and return its result
+    fn call(&self, method: &str, params: serde_json::Value) -> io::Result<serde_json::Value> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let response: serde_json::Value = self
+            .agent
+            .post(&self.url)
+            .send_json(request)
+            .map_err(io::Error::other)?
+            .into_json()
+            .map_err(|e| invalid_data(format!("malformed JSON-RPC response: {}", e)))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(io::Error::other(format!("{} failed: {}", method, error)));
+        }
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| invalid_data("JSON-RPC response lacks a result".to_string()))
+    }
+}
+
+/// HTTP agent whose requests each take at most `timeout`
+fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(timeout).build()
+}
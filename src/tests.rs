@@ -0,0 +1,2171 @@
+//! Unit tests of the circuits and proof functions
+
+use crate::chunked::sorted_set;
+use crate::circuits::grid::ComparisonWitness;
+use crate::circuits::lookup::{catalogue_neighbours, element_bound, sorted_catalogue};
+use crate::{
+    BLOCKLIST_COMMITMENT_ROW, BatchVerifyError, CARDINALITIES_ROW, CHUNK_BOUND_A_IN_ROW,
+    CHUNK_BOUND_A_OUT_ROW, CHUNK_BOUND_B_IN_ROW, CHUNK_BOUND_B_OUT_ROW, CHUNK_CHAIN_IN_ROW,
+    CHUNK_CHAIN_OUT_ROW, CHUNK_COUNT_IN_ROW, CHUNK_COUNT_OUT_ROW, COMMITMENT_A_ROW,
+    COMMITMENT_B_ROW, Cardinalities, ChunkedPsiError, ChunkedPsiProof, CircuitError,
+    DisjointnessCircuit, INTERSECTION_SIZE_ROW, IntersectionSemantics, LookupPsiCircuit,
+    LookupPsiConfig, MAX_K, MAX_MERGED_SIZE, MAX_SET_CAPACITY, MAX_SET_SIZE, MULTI_COUNT_ROW,
+    MultiPsiCircuit, PackedPsiCircuit, PsiChunkCircuit, PsiChunkProof, PsiCircuit, PsiConfig,
+    SetCapacity, SortPsiCircuit, SortPsiConfig, SubsetCircuit, THRESHOLD_ROW, ThresholdPsiCircuit,
+    VALUE_BITS, WEIGHTED_SUM_ROW, chunk_bound, commit_blocklist, commit_blocklist_with_capacity,
+    commit_chunks, commit_set, commit_set_with_capacity, commit_weighted_set, generate_multi_proof,
+    generate_proof, hash_to_field, layout_size, min_k, multi_intersection_size, prove_disjointness,
+    prove_multi, prove_subset, prove_threshold, setup_chunk, setup_disjointness, setup_eq,
+    setup_eq_with_capacity, setup_eq_with_semantics, setup_for, setup_lookup, setup_multi,
+    setup_sort, setup_subset, setup_threshold, verify_disjointness, verify_multi,
+    verify_multi_proof, verify_proof, verify_proofs_batch, verify_subset, verify_threshold,
+};
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error, keygen_vk};
+use pasta_curves::{EqAffine, Fp};
+
+use halo2_proofs::{
+    dev::{MockProver, VerifyFailure},
+    poly::commitment::Params,
+};
+
+#[test]
+fn test_hash_to_field() {
+    let h1 = hash_to_field(42);
+    let h2 = hash_to_field(42);
+    let h3 = hash_to_field(43);
+
+    assert_eq!(h1, h2);
+    assert_ne!(h1, h3);
+}
+
+#[test]
+fn test_intersection_computation() {
+    let set_a = vec![hash_to_field(1), hash_to_field(2), hash_to_field(3)];
+    let set_b = vec![hash_to_field(2), hash_to_field(3), hash_to_field(4)];
+
+    let circuit = PsiCircuit::new(set_a, set_b, 2).unwrap();
+    assert_eq!(circuit.compute_intersection_size(), 2);
+}
+
+#[test]
+fn test_full_proof_verification_flow() {
+    let set_a = vec![hash_to_field(1), hash_to_field(2)];
+    let set_b = vec![hash_to_field(2), hash_to_field(3)];
+
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
+    let intersection_size = circuit.compute_intersection_size();
+    assert_eq!(intersection_size, 1);
+
+    let k = 12;
+    let (params, pk, vk) = setup_eq(k).unwrap();
+
+    let circuit = PsiCircuit::new(set_a, set_b, intersection_size).unwrap();
+    let public_inputs = circuit.public_inputs();
+
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+}
+
+/// Sets of the requested sizes whose intersection is the tail of A
+fn shaped_sets(len_a: u64, len_b: u64, overlap: u64) -> (Vec<Fp>, Vec<Fp>) {
+    let set_a = (1..=len_a).map(hash_to_field).collect();
+    let start_b = len_a + 1 - overlap;
+    let set_b = (start_b..start_b + len_b).map(hash_to_field).collect();
+    (set_a, set_b)
+}
+
+#[test]
+fn test_fixed_shape_mock_prover() {
+    for (len_a, len_b, overlap) in [(1, 1, 1), (7, 13, 3), (32, 32, 16)] {
+        let (set_a, set_b) = shaped_sets(len_a, len_b, overlap);
+        let circuit = PsiCircuit::new(set_a, set_b, overlap).unwrap();
+        assert_eq!(circuit.compute_intersection_size(), overlap);
+
+        let prover = MockProver::run(12, &circuit, vec![circuit.public_inputs()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()), "{}x{} sets", len_a, len_b);
+    }
+}
+
+#[test]
+fn test_verifying_key_independent_of_witness() {
+    let params = Params::<EqAffine>::new(12);
+    let empty_vk = keygen_vk(&params, &PsiCircuit::default()).unwrap();
+
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let filled_vk = keygen_vk(&params, &PsiCircuit::new(set_a, set_b, 3).unwrap()).unwrap();
+
+    assert_eq!(
+        format!("{:?}", empty_vk.pinned()),
+        format!("{:?}", filled_vk.pinned())
+    );
+}
+
+#[test]
+fn test_all_shapes_verify_against_same_key() {
+    let (params, pk, vk) = setup_eq(12).unwrap();
+
+    for (len_a, len_b, overlap) in [(1, 1, 1), (7, 13, 3), (32, 32, 16)] {
+        let (set_a, set_b) = shaped_sets(len_a, len_b, overlap);
+        let circuit = PsiCircuit::new(set_a, set_b, overlap).unwrap();
+        let public_inputs = circuit.public_inputs();
+
+        let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+        verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+
+        let mut wrong_inputs = public_inputs.clone();
+        wrong_inputs[INTERSECTION_SIZE_ROW] += Fp::one();
+        assert!(verify_proof(&params, &vk, &proof, &wrong_inputs).is_err());
+    }
+}
+
+#[test]
+fn test_tampered_public_count_fails() {
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let circuit = PsiCircuit::new(set_a, set_b, 3).unwrap();
+
+    let mut public_inputs = circuit.public_inputs();
+    public_inputs[INTERSECTION_SIZE_ROW] = Fp::from(4u64);
+    let prover = MockProver::run(12, &circuit, vec![public_inputs]).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn test_commitments_match_native_computation() {
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 3).unwrap();
+
+    let public_inputs = circuit.public_inputs();
+    assert_eq!(
+        public_inputs[COMMITMENT_A_ROW],
+        commit_set(&set_a, circuit.salt_a)
+    );
+    assert_eq!(
+        public_inputs[COMMITMENT_B_ROW],
+        commit_set(&set_b, circuit.salt_b)
+    );
+
+    let prover = MockProver::run(12, &circuit, vec![public_inputs]).unwrap();
+    assert_eq!(prover.verify(), Ok(()));
+}
+
+#[test]
+fn test_commitment_binds_set_and_salt() {
+    let set = vec![hash_to_field(1), hash_to_field(2)];
+    let salt = Fp::from(7u64);
+    let commitment = commit_set(&set, salt);
+
+    assert_eq!(commitment, commit_set(&set, salt));
+    assert_ne!(commitment, commit_set(&set, Fp::from(8u64)));
+    assert_ne!(commitment, commit_set(&set[..1], salt));
+    assert_ne!(commitment, commit_set(&[set[0], Fp::zero()], salt));
+    assert_ne!(
+        commitment,
+        commit_set(&[hash_to_field(1), hash_to_field(3)], salt)
+    );
+}
+
+#[test]
+fn test_wrong_commitment_fails() {
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b, 3).unwrap();
+
+    // Commitments to a different set, or to the right set under another salt
+    let (other_a, _) = shaped_sets(8, 13, 3);
+    for forged in [
+        commit_set(&other_a, circuit.salt_a),
+        commit_set(&set_a, circuit.salt_a + Fp::one()),
+    ] {
+        let mut public_inputs = circuit.public_inputs();
+        public_inputs[COMMITMENT_A_ROW] = forged;
+        let prover = MockProver::run(12, &circuit, vec![public_inputs]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
+
+#[test]
+fn test_proof_bound_to_published_commitments() {
+    let (params, pk, vk) = setup_eq(12).unwrap();
+    let (set_a, set_b) = shaped_sets(4, 4, 2);
+    let salt_a = Fp::from(11u64);
+    let salt_b = Fp::from(22u64);
+
+    // Commitments published before any proof is produced
+    let published = [commit_set(&set_a, salt_a), commit_set(&set_b, salt_b)];
+
+    let circuit = PsiCircuit::new_with_salts(set_a, set_b, 2, salt_a, salt_b).unwrap();
+    let public_inputs = vec![Fp::from(2u64), published[0], published[1]];
+    assert_eq!(public_inputs, circuit.public_inputs());
+
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+
+    let swapped = vec![Fp::from(2u64), published[1], published[0]];
+    assert!(verify_proof(&params, &vk, &proof, &swapped).is_err());
+}
+
+type Tamper = Box<dyn Fn(usize, ComparisonWitness) -> ComparisonWitness>;
+
+impl ComparisonWitness {
+    /// Recompute the running values after the pick was rewritten
+    fn repick(mut self, pick: Fp) -> Self {
+        self.pick = Value::known(pick);
+        self.accumulators = self.accumulators.map(|(prev, _)| (prev, prev + self.pick));
+        self
+    }
+}
+
+/// Adversarial harness: lays out the same regions and copy constraints as
+/// `PsiCircuit`, but with hand-picked slots and usage flags and a hook that
+/// rewrites the witness of any comparison, on a grid of `LANES` lanes.
+struct AdversarialCircuit<const LANES: usize = 1> {
+    slots_a: Vec<(Fp, bool)>,
+    slots_b: Vec<(Fp, bool)>,
+    picks: Vec<Vec<bool>>,
+    used_a: Vec<bool>,
+    used_b: Vec<bool>,
+    tamper: Tamper,
+}
+
+impl AdversarialCircuit {
+    fn new(set_a: &[Fp], set_b: &[Fp], tamper: Tamper) -> Self {
+        let slots_a = PsiCircuit::padded_slots(set_a, MAX_SET_SIZE);
+        let slots_b = PsiCircuit::padded_slots(set_b, MAX_SET_SIZE);
+        let (picks, used_a, used_b) = PsiCircuit::matching(&slots_a, &slots_b);
+        Self {
+            slots_a,
+            slots_b,
+            picks,
+            used_a,
+            used_b,
+            tamper,
+        }
+    }
+
+    fn honest(set_a: &[Fp], set_b: &[Fp]) -> Self {
+        Self::new(set_a, set_b, Box::new(|_, witness| witness))
+    }
+
+    /// The same harness on a grid packing `L` comparisons per row
+    fn packed<const L: usize>(self) -> AdversarialCircuit<L> {
+        AdversarialCircuit {
+            slots_a: self.slots_a,
+            slots_b: self.slots_b,
+            picks: self.picks,
+            used_a: self.used_a,
+            used_b: self.used_b,
+            tamper: self.tamper,
+        }
+    }
+
+    /// Index of the comparison between slot `i` of A and slot `j` of B
+    fn row(i: usize, j: usize) -> usize {
+        i * MAX_SET_SIZE + j
+    }
+
+    /// Tamper with a single row, marking the slots it pairs as used
+    fn pick_row(set_a: &[Fp], set_b: &[Fp], i: usize, j: usize, match_bit: Fp) -> Self {
+        let target = Self::row(i, j);
+        let mut circuit = Self::new(
+            set_a,
+            set_b,
+            Box::new(move |row, mut witness| {
+                if row == target {
+                    witness.match_bit = Value::known(match_bit);
+                    witness = witness.repick(match_bit);
+                }
+                witness
+            }),
+        );
+        let used = match_bit == Fp::one();
+        circuit.used_a[i] = used;
+        circuit.used_b[j] = used;
+        circuit
+    }
+}
+
+impl<const LANES: usize> Circuit<Fp> for AdversarialCircuit<LANES> {
+    type Config = PsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        AdversarialCircuit::honest(&[], &[]).packed()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiConfig::configure_with_lanes(meta, LANES)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let slots_a = config.assign_set(
+            layouter.namespace(|| "set A"),
+            &self.slots_a,
+            &self.used_a,
+            &[Fp::zero(); MAX_SET_SIZE],
+            true,
+        )?;
+        let slots_b = config.assign_set(
+            layouter.namespace(|| "set B"),
+            &self.slots_b,
+            &self.used_b,
+            &[Fp::zero(); MAX_SET_SIZE],
+            false,
+        )?;
+
+        let last = config.assign_grid_with(
+            layouter.namespace(|| "grid"),
+            &slots_a,
+            &slots_b,
+            &self.picks,
+            None,
+            |row, witness| (self.tamper)(row, witness),
+        )?;
+
+        layouter.constrain_instance(last.sum.cell(), config.instance, 0)
+    }
+}
+
+/// Run MockProver and return the names of the gates it reports as failing,
+/// plus "permutation" for every failed copy constraint.
+fn gate_failures<C: Circuit<Fp>>(circuit: &C, instance: Vec<Fp>) -> Vec<String> {
+    gate_failures_at(12, circuit, instance)
+}
+
+/// [`gate_failures`] for circuits needing more than 2^12 rows
+fn gate_failures_at<C: Circuit<Fp>>(k: u32, circuit: &C, instance: Vec<Fp>) -> Vec<String> {
+    let prover = MockProver::run(k, circuit, vec![instance]).unwrap();
+    let mut gates: Vec<String> = match prover.verify() {
+        Ok(()) => vec![],
+        Err(failures) => failures
+            .iter()
+            .map(|failure| match failure {
+                VerifyFailure::ConstraintNotSatisfied { constraint, .. } => {
+                    let constraint = constraint.to_string();
+                    let start = constraint.find('\'').unwrap() + 1;
+                    let end = constraint.rfind('\'').unwrap();
+                    constraint[start..end].to_string()
+                }
+                VerifyFailure::Permutation { .. } => "permutation".to_string(),
+                VerifyFailure::Lookup { .. } => "lookup".to_string(),
+                other => format!("{:?}", other),
+            })
+            .collect(),
+    };
+    gates.sort();
+    gates.dedup();
+    gates
+}
+
+/// Run the harness against a claimed intersection size
+fn failing_gates<const LANES: usize>(
+    circuit: &AdversarialCircuit<LANES>,
+    claimed: u64,
+) -> Vec<String> {
+    gate_failures(circuit, vec![Fp::from(claimed)])
+}
+
+fn adversarial_sets() -> (Vec<Fp>, Vec<Fp>) {
+    // Intersection is {2, 3}: A[1] == B[0] and A[2] == B[1]
+    let set_a = vec![hash_to_field(1), hash_to_field(2), hash_to_field(3)];
+    let set_b = vec![hash_to_field(2), hash_to_field(3), hash_to_field(4)];
+    (set_a, set_b)
+}
+
+#[test]
+fn test_adversarial_harness_accepts_honest_witness() {
+    let (set_a, set_b) = adversarial_sets();
+    let circuit = AdversarialCircuit::honest(&set_a, &set_b);
+    assert!(failing_gates(&circuit, 2).is_empty());
+}
+
+#[test]
+fn test_match_on_unequal_pair_fails() {
+    // Claim A[0] == B[2] to inflate the count from 2 to 3
+    let (set_a, set_b) = adversarial_sets();
+    let circuit = AdversarialCircuit::pick_row(&set_a, &set_b, 0, 2, Fp::one());
+    assert_eq!(failing_gates(&circuit, 3), vec!["equality check"]);
+}
+
+#[test]
+fn test_hidden_match_fails() {
+    // Hide A[1] == B[0] to deflate the count from 2 to 1
+    let (set_a, set_b) = adversarial_sets();
+    let circuit = AdversarialCircuit::pick_row(&set_a, &set_b, 1, 0, Fp::zero());
+    assert_eq!(failing_gates(&circuit, 1), vec!["equality check"]);
+}
+
+#[test]
+fn test_forged_inverse_fails() {
+    // A zero inverse on an unequal pair would make it look equal
+    let (set_a, set_b) = adversarial_sets();
+    let target = AdversarialCircuit::row(0, 0);
+    let circuit = AdversarialCircuit::new(
+        &set_a,
+        &set_b,
+        Box::new(move |row, mut witness| {
+            if row == target {
+                witness.inverse = Value::known(Fp::zero());
+            }
+            witness
+        }),
+    );
+    assert_eq!(failing_gates(&circuit, 2), vec!["equality check"]);
+}
+
+#[test]
+fn test_forged_match_on_padding_fails() {
+    // Two padding slots both hold zero, so they compare equal
+    let (set_a, set_b) = adversarial_sets();
+    let last = MAX_SET_SIZE - 1;
+    let circuit = AdversarialCircuit::pick_row(&set_a, &set_b, last, last, Fp::one());
+    assert_eq!(failing_gates(&circuit, 3), vec!["equality check"]);
+}
+
+#[test]
+fn test_pick_without_match_fails() {
+    // Count A[0] and B[2] as a pair although their match bit is zero
+    let (set_a, set_b) = adversarial_sets();
+    let target = AdversarialCircuit::row(0, 2);
+    let mut circuit = AdversarialCircuit::new(
+        &set_a,
+        &set_b,
+        Box::new(move |row, witness| {
+            if row == target {
+                witness.repick(Fp::one())
+            } else {
+                witness
+            }
+        }),
+    );
+    circuit.used_a[0] = true;
+    circuit.used_b[2] = true;
+    assert_eq!(failing_gates(&circuit, 3), vec!["matching"]);
+}
+
+#[test]
+fn test_double_pick_fails() {
+    // A single copy in A must not pair with both copies in B
+    let x = hash_to_field(1);
+    let target = AdversarialCircuit::row(0, 1);
+    let mut circuit = AdversarialCircuit::new(
+        &[x],
+        &[x, x],
+        Box::new(move |row, witness| {
+            if row == target {
+                witness.repick(Fp::one())
+            } else {
+                witness
+            }
+        }),
+    );
+    circuit.used_b[1] = true;
+    assert_eq!(failing_gates(&circuit, 2), vec!["permutation"]);
+}
+
+#[test]
+fn test_unpicked_match_fails() {
+    // Leaving a match between two unused slots out of the matching
+    let x = hash_to_field(1);
+    let target = AdversarialCircuit::row(0, 0);
+    let mut circuit = AdversarialCircuit::new(
+        &[x],
+        &[x],
+        Box::new(move |row, witness| {
+            if row == target {
+                witness.repick(Fp::zero())
+            } else {
+                witness
+            }
+        }),
+    );
+    circuit.used_a[0] = false;
+    circuit.used_b[0] = false;
+    assert_eq!(failing_gates(&circuit, 0), vec!["matching"]);
+}
+
+#[test]
+fn test_sum_not_starting_from_zero_fails() {
+    let (set_a, set_b) = adversarial_sets();
+    let circuit = AdversarialCircuit::new(
+        &set_a,
+        &set_b,
+        Box::new(|row, mut witness| {
+            if row == 0 {
+                let prev = Value::known(Fp::from(5u64));
+                witness.accumulators[0] = (prev, prev + witness.pick);
+            }
+            witness
+        }),
+    );
+    assert_eq!(failing_gates(&circuit, 7), vec!["permutation"]);
+}
+
+#[test]
+fn test_sum_skipping_a_row_fails() {
+    let (set_a, set_b) = adversarial_sets();
+    let target = AdversarialCircuit::row(1, 0);
+    let circuit = AdversarialCircuit::new(
+        &set_a,
+        &set_b,
+        Box::new(move |row, mut witness| {
+            if row == target {
+                let (prev, _) = witness.accumulators[0];
+                witness.accumulators[0] = (prev, prev);
+            }
+            witness
+        }),
+    );
+    assert_eq!(failing_gates(&circuit, 1), vec!["sum accumulator"]);
+}
+
+#[test]
+fn test_tampered_padding_fails() {
+    let set = [hash_to_field(1), hash_to_field(2)];
+    let honest = PsiCircuit::padded_slots(&set, MAX_SET_SIZE);
+
+    // Padding slot holding a non-zero value
+    let mut nonzero_padding = honest.clone();
+    nonzero_padding[5].0 = hash_to_field(5);
+
+    // Active slot after a padding slot
+    let mut gap = honest.clone();
+    gap[5] = (hash_to_field(5), true);
+
+    for (slots_a, gate) in [(nonzero_padding, "set A slot"), (gap, "set A prefix")] {
+        let mut circuit = AdversarialCircuit::honest(&set, &set);
+        circuit.slots_a = slots_a;
+        assert_eq!(failing_gates(&circuit, 2), vec![gate]);
+    }
+}
+
+/// Sets with repeated elements: x twice in A and three times in B, y once
+/// in A and twice in B
+fn duplicated_sets() -> (Vec<Fp>, Vec<Fp>) {
+    let x = hash_to_field(1);
+    let y = hash_to_field(2);
+    let z = hash_to_field(3);
+    (vec![x, y, x, z], vec![y, x, x, y, x])
+}
+
+#[test]
+fn test_native_semantics() {
+    let (set_a, set_b) = duplicated_sets();
+    assert_eq!(
+        IntersectionSemantics::Set.intersection_size(&set_a, &set_b),
+        2
+    );
+    assert_eq!(
+        IntersectionSemantics::Multiset.intersection_size(&set_a, &set_b),
+        3
+    );
+
+    // Both agree on sets without duplicates
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    for semantics in [IntersectionSemantics::Set, IntersectionSemantics::Multiset] {
+        assert_eq!(semantics.intersection_size(&set_a, &set_b), 3);
+    }
+}
+
+#[test]
+fn test_set_semantics_rejects_duplicates() {
+    let (set_a, set_b) = duplicated_sets();
+    for claimed in [2, 3] {
+        let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), claimed).unwrap();
+        let failures = gate_failures(&circuit, circuit.public_inputs());
+        assert!(
+            failures.contains(&"distinct elements".to_string()),
+            "{:?}",
+            failures
+        );
+    }
+
+    // A duplicate in B alone is rejected too
+    let (set_a, _) = shaped_sets(3, 0, 0);
+    let set_b = vec![set_a[0], set_a[0]];
+    let circuit = PsiCircuit::new(set_a, set_b, 1).unwrap();
+    assert_eq!(
+        gate_failures(&circuit, circuit.public_inputs()),
+        vec!["distinct elements"]
+    );
+}
+
+#[test]
+fn test_multiset_semantics_counts_min_multiplicities() {
+    let (set_a, set_b) = duplicated_sets();
+    let circuit = PsiCircuit::new(set_a, set_b, 3)
+        .unwrap()
+        .with_semantics(IntersectionSemantics::Multiset)
+        .unwrap();
+    assert_eq!(circuit.compute_intersection_size(), 3);
+    assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+
+    for claimed in [2, 4, 5] {
+        let mut public_inputs = circuit.public_inputs();
+        public_inputs[INTERSECTION_SIZE_ROW] = Fp::from(claimed);
+        assert_eq!(gate_failures(&circuit, public_inputs), vec!["permutation"]);
+    }
+}
+
+#[test]
+fn test_multiset_proof_verification_flow() {
+    let (params, pk, vk) = setup_eq_with_semantics(12, IntersectionSemantics::Multiset).unwrap();
+    let (set_a, set_b) = duplicated_sets();
+
+    let circuit = PsiCircuit::new(set_a, set_b, 3)
+        .unwrap()
+        .with_semantics(IntersectionSemantics::Multiset)
+        .unwrap();
+    let public_inputs = circuit.public_inputs();
+
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+
+    // The semantics is part of the verifying key
+    let set_vk = keygen_vk(&params, &PsiCircuit::default()).unwrap();
+    assert!(verify_proof(&params, &set_vk, &proof, &public_inputs).is_err());
+}
+
+#[test]
+fn test_threshold_mock_prover() {
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let psi = PsiCircuit::new(set_a, set_b, 0).unwrap();
+
+    for threshold in 0..=3 {
+        let circuit = ThresholdPsiCircuit::new(psi.clone(), threshold);
+        assert!(
+            gate_failures(&circuit, circuit.public_inputs()).is_empty(),
+            "threshold {}",
+            threshold
+        );
+    }
+
+    // The difference wraps around to a huge field element, whose
+    // decomposition cannot end in zero within the range check
+    for threshold in [4, 5, 100, u64::MAX] {
+        let circuit = ThresholdPsiCircuit::new(psi.clone(), threshold);
+        assert_eq!(
+            gate_failures(&circuit, circuit.public_inputs()),
+            vec!["permutation"],
+            "threshold {}",
+            threshold
+        );
+    }
+}
+
+#[test]
+fn test_threshold_at_maximum_size() {
+    let (set_a, set_b) = shaped_sets(32, 32, 32);
+    let psi = PsiCircuit::new(set_a, set_b, 0).unwrap();
+
+    let circuit = ThresholdPsiCircuit::new(psi.clone(), MAX_SET_SIZE as u64);
+    assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+
+    let circuit = ThresholdPsiCircuit::new(psi, MAX_SET_SIZE as u64 + 1);
+    assert_eq!(
+        gate_failures(&circuit, circuit.public_inputs()),
+        vec!["permutation"]
+    );
+}
+
+#[test]
+fn test_threshold_proof_verification_flow() {
+    let (params, pk, vk) = setup_threshold(12, IntersectionSemantics::Set).unwrap();
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+
+    let circuit = ThresholdPsiCircuit::new(PsiCircuit::new(set_a, set_b, 0).unwrap(), 2);
+    let public_inputs = circuit.public_inputs();
+    assert_eq!(public_inputs[THRESHOLD_ROW], Fp::from(2u64));
+
+    let proof = prove_threshold(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_threshold(&params, &vk, &proof, &public_inputs).unwrap();
+
+    // The proof is bound to the threshold it was produced for
+    for threshold in [1u64, 3] {
+        let mut other_inputs = public_inputs.clone();
+        other_inputs[THRESHOLD_ROW] = Fp::from(threshold);
+        assert!(verify_threshold(&params, &vk, &proof, &other_inputs).is_err());
+    }
+}
+
+#[test]
+fn test_disjointness_mock_prover() {
+    let (set, blocklist) = shaped_sets(7, 13, 0);
+    let circuit = DisjointnessCircuit::new(set, blocklist).unwrap();
+    assert!(circuit.is_disjoint());
+    assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+
+    // Duplicates in the blocklist are fine
+    let (set, mut blocklist) = shaped_sets(4, 4, 0);
+    blocklist.push(blocklist[0]);
+    let circuit = DisjointnessCircuit::new(set, blocklist).unwrap();
+    assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+}
+
+#[test]
+fn test_overlap_with_blocklist_fails() {
+    for overlap in [1, 3] {
+        let (set, blocklist) = shaped_sets(7, 13, overlap);
+        let circuit = DisjointnessCircuit::new(set, blocklist).unwrap();
+        assert!(!circuit.is_disjoint());
+        assert_eq!(
+            gate_failures(&circuit, circuit.public_inputs()),
+            vec!["permutation"]
+        );
+    }
+}
+
+#[test]
+fn test_disjointness_capacity_mock_prover() {
+    // A blocklist larger than the default capacity needs a larger layout
+    let (set, blocklist) = shaped_sets(4, 40, 0);
+    assert_eq!(
+        DisjointnessCircuit::new(set.clone(), blocklist.clone()).unwrap_err(),
+        CircuitError::SetTooLarge {
+            set: "blocklist".to_string(),
+            size: 40,
+            max: MAX_SET_SIZE,
+        }
+    );
+
+    let capacity = SetCapacity::new(4, 40).unwrap();
+    let salt = Fp::from(9u64);
+    let circuit =
+        DisjointnessCircuit::new_with_capacity(capacity, set.clone(), blocklist.clone(), salt)
+            .unwrap();
+    assert_eq!(
+        circuit.public_inputs(),
+        vec![
+            commit_set_with_capacity(&set, salt, 4),
+            commit_blocklist_with_capacity(&blocklist, 40)
+        ]
+    );
+    let k = min_k(&circuit).unwrap();
+    assert!(gate_failures_at(k, &circuit, circuit.public_inputs()).is_empty());
+}
+
+#[test]
+fn test_disjointness_proof_verification_flow() {
+    let (params, pk, vk) = setup_disjointness(12, SetCapacity::default()).unwrap();
+    let (set, blocklist) = shaped_sets(5, 20, 0);
+    let salt = Fp::from(5u64);
+
+    let circuit = DisjointnessCircuit::new_with_salt(set.clone(), blocklist.clone(), salt).unwrap();
+    let public_inputs = circuit.public_inputs();
+    assert_eq!(
+        public_inputs,
+        vec![commit_set(&set, salt), commit_blocklist(&blocklist)]
+    );
+
+    let proof = prove_disjointness(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_disjointness(&params, &vk, &proof, &public_inputs).unwrap();
+
+    // The proof does not hold for another blocklist
+    let mut other_inputs = public_inputs.clone();
+    other_inputs[BLOCKLIST_COMMITMENT_ROW] = commit_blocklist(&blocklist[1..]);
+    assert!(verify_disjointness(&params, &vk, &proof, &other_inputs).is_err());
+}
+
+#[test]
+fn test_subset_mock_prover() {
+    // B = {1..13} and A = {11, 12, 13}
+    let set_b: Vec<Fp> = (1..=13).map(hash_to_field).collect();
+    let set_a = set_b[10..].to_vec();
+    let circuit = SubsetCircuit::new(PsiCircuit::new(set_a, set_b.clone(), 0).unwrap());
+    assert!(circuit.is_subset());
+    assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+
+    // The empty set is a subset of anything
+    let circuit = SubsetCircuit::new(PsiCircuit::new(vec![], set_b, 0).unwrap());
+    assert!(circuit.is_subset());
+    assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+}
+
+#[test]
+fn test_non_subset_fails() {
+    let (set_a, set_b) = shaped_sets(7, 13, 6);
+    let circuit = SubsetCircuit::new(PsiCircuit::new(set_a, set_b, 0).unwrap());
+    assert!(!circuit.is_subset());
+    assert_eq!(
+        gate_failures(&circuit, circuit.public_inputs()),
+        vec!["permutation"]
+    );
+}
+
+#[test]
+fn test_multiset_subset() {
+    let x = hash_to_field(1);
+    let y = hash_to_field(2);
+
+    // Two copies of x need two copies in B
+    for (set_b, contained) in [(vec![x, y, x], true), (vec![x, y], false)] {
+        let psi = PsiCircuit::new(vec![x, x], set_b, 0)
+            .unwrap()
+            .with_semantics(IntersectionSemantics::Multiset)
+            .unwrap();
+        let circuit = SubsetCircuit::new(psi);
+        assert_eq!(circuit.is_subset(), contained);
+        assert_eq!(
+            gate_failures(&circuit, circuit.public_inputs()).is_empty(),
+            contained
+        );
+    }
+}
+
+#[test]
+fn test_subset_proof_verification_flow() {
+    let (params, pk, vk) = setup_subset(12, IntersectionSemantics::Set).unwrap();
+    let set_b: Vec<Fp> = (1..=20).map(hash_to_field).collect();
+    let set_a = vec![set_b[3], set_b[17], set_b[0]];
+
+    let circuit = SubsetCircuit::new(PsiCircuit::new(set_a, set_b, 0).unwrap());
+    let public_inputs = circuit.public_inputs();
+
+    let proof = prove_subset(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_subset(&params, &vk, &proof, &public_inputs).unwrap();
+
+    let swapped = vec![public_inputs[1], public_inputs[0]];
+    assert!(verify_subset(&params, &vk, &proof, &swapped).is_err());
+}
+
+#[test]
+fn test_weighted_sum_native() {
+    let (set_a, set_b) = adversarial_sets();
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
+    assert_eq!(circuit.compute_weighted_sum(), 0);
+
+    // B = {2, 3, 4} with values 10, 20, 30 and A ∩ B = {2, 3}
+    let circuit = circuit.with_values_b(vec![10, 20, 30]).unwrap();
+    assert_eq!(circuit.compute_weighted_sum(), 30);
+    assert_eq!(circuit.public_inputs()[WEIGHTED_SUM_ROW], Fp::from(30u64));
+
+    // Multiset semantics would let the matching choose between copies of
+    // an element with different values, so weighted circuits reject it
+    let x = hash_to_field(1);
+    let multiset = PsiCircuit {
+        semantics: IntersectionSemantics::Multiset,
+        ..PsiCircuit::new(vec![x], vec![x, x], 0)
+            .unwrap()
+            .with_values_b(vec![5, 7])
+            .unwrap()
+    };
+    assert!(MockProver::run(12, &multiset, vec![multiset.public_inputs()]).is_err());
+
+    // The largest possible sum fits comfortably in a u64
+    let (set_a, set_b) = shaped_sets(32, 32, 32);
+    let circuit = PsiCircuit::new(set_a, set_b, 0)
+        .unwrap()
+        .with_values_b(vec![u32::MAX; 32])
+        .unwrap();
+    assert_eq!(circuit.compute_weighted_sum(), 32 * u32::MAX as u64);
+}
+
+#[test]
+fn test_weighted_multiset_rejected() {
+    let x = hash_to_field(1);
+    let circuit = PsiCircuit::new(vec![x, x], vec![x], 0).unwrap();
+    assert_eq!(
+        circuit
+            .clone()
+            .with_values_b(vec![5])
+            .unwrap()
+            .with_semantics(IntersectionSemantics::Multiset)
+            .unwrap_err(),
+        CircuitError::WeightedMultiset
+    );
+    assert_eq!(
+        circuit
+            .with_semantics(IntersectionSemantics::Multiset)
+            .unwrap()
+            .with_values_b(vec![5])
+            .unwrap_err(),
+        CircuitError::WeightedMultiset
+    );
+}
+
+#[test]
+fn test_constructors_reject_invalid_inputs() {
+    let set: Vec<Fp> = (1..=5).map(hash_to_field).collect();
+    let salt = Fp::zero();
+    let too_large = |set: &str, max| CircuitError::SetTooLarge {
+        set: set.to_string(),
+        size: 5,
+        max,
+    };
+
+    assert_eq!(
+        SetCapacity::new(0, 4).unwrap_err(),
+        CircuitError::InvalidCapacity {
+            max: 0,
+            limit: MAX_SET_CAPACITY
+        }
+    );
+    assert!(SetCapacity::new(4, MAX_SET_CAPACITY + 1).is_err());
+
+    let capacity = SetCapacity::new(4, 8).unwrap();
+    assert_eq!(
+        PsiCircuit::new_with_capacity(capacity, set.clone(), vec![], 0, salt, salt).unwrap_err(),
+        too_large("A", 4)
+    );
+    assert_eq!(
+        PsiCircuit::new_with_capacity(capacity, vec![], set.clone(), 0, salt, salt)
+            .unwrap()
+            .with_values_b(vec![1; 4])
+            .unwrap_err(),
+        CircuitError::LengthMismatch {
+            items: "values",
+            expected: 5,
+            found: 4
+        }
+    );
+    assert_eq!(
+        PsiChunkCircuit::new(capacity, set.clone(), vec![], salt, salt, 0, salt).unwrap_err(),
+        too_large("A", 4)
+    );
+
+    assert_eq!(
+        SortPsiCircuit::new_with_capacity(capacity, vec![], set.clone(), 0, salt, salt)
+            .unwrap()
+            .capacity,
+        capacity
+    );
+    assert_eq!(
+        SortPsiCircuit::new_with_capacity(capacity, set.clone(), vec![], 0, salt, salt)
+            .unwrap_err(),
+        too_large("A", 4)
+    );
+    assert_eq!(
+        SortPsiCircuit::new_with_capacity(
+            SetCapacity::new(32, 33).unwrap(),
+            vec![],
+            vec![],
+            0,
+            salt,
+            salt
+        )
+        .unwrap_err(),
+        CircuitError::InvalidCapacity {
+            max: 65,
+            limit: MAX_MERGED_SIZE
+        }
+    );
+    assert_eq!(
+        SortPsiCircuit::new(vec![element_bound()], vec![], 0).unwrap_err(),
+        CircuitError::ElementOutOfRange("A".to_string())
+    );
+
+    assert_eq!(
+        LookupPsiCircuit::new_with_capacity(4, set.clone(), vec![], 0, salt).unwrap_err(),
+        too_large("A", 4)
+    );
+    assert_eq!(
+        LookupPsiCircuit::new(vec![], vec![element_bound()], 0).unwrap_err(),
+        CircuitError::ElementOutOfRange("catalogue".to_string())
+    );
+
+    let sets = vec![set.clone(), vec![], vec![]];
+    assert_eq!(
+        MultiPsiCircuit::new_with_capacity(4, sets.clone(), vec![salt; 3], 0).unwrap_err(),
+        too_large("0", 4)
+    );
+    assert_eq!(
+        MultiPsiCircuit::new(sets[..1].to_vec(), 0).unwrap_err(),
+        CircuitError::InvalidPartyCount(1)
+    );
+    assert_eq!(
+        MultiPsiCircuit::new_with_salts(sets.clone(), vec![salt; 2], 0).unwrap_err(),
+        CircuitError::LengthMismatch {
+            items: "salts",
+            expected: 3,
+            found: 2
+        }
+    );
+    assert_eq!(
+        MultiPsiCircuit::new(sets, 0)
+            .unwrap()
+            .with_threshold(4)
+            .unwrap_err(),
+        CircuitError::InvalidThreshold {
+            threshold: 4,
+            parties: 3
+        }
+    );
+}
+
+#[test]
+fn test_weighted_mock_prover() {
+    let (set_a, set_b) = shaped_sets(32, 32, 16);
+    let values = (0..32).map(|j| 1000 * j + 7).collect();
+    let circuit = PsiCircuit::new(set_a, set_b, 0)
+        .unwrap()
+        .with_values_b(values)
+        .unwrap();
+    assert_eq!(
+        circuit.compute_weighted_sum(),
+        (0..16).map(|j| 1000 * j + 7).sum::<u64>()
+    );
+    assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+
+    // Neither the count nor a shifted sum is accepted
+    for output in [16, circuit.compute_weighted_sum() + 1] {
+        let mut public_inputs = circuit.public_inputs();
+        public_inputs[WEIGHTED_SUM_ROW] = Fp::from(output);
+        assert_eq!(gate_failures(&circuit, public_inputs), vec!["permutation"]);
+    }
+}
+
+#[test]
+fn test_weighted_commitment_binds_values() {
+    let (set_a, set_b) = adversarial_sets();
+    let circuit = PsiCircuit::new(set_a, set_b.clone(), 0)
+        .unwrap()
+        .with_values_b(vec![10, 20, 30])
+        .unwrap();
+
+    assert_eq!(
+        circuit.commitment_b(),
+        commit_weighted_set(&set_b, &[10, 20, 30], circuit.salt_b)
+    );
+    assert_ne!(circuit.commitment_b(), commit_set(&set_b, circuit.salt_b));
+
+    // Inflating a value changes the commitment the proof must match
+    let mut public_inputs = circuit.public_inputs();
+    public_inputs[COMMITMENT_B_ROW] = commit_weighted_set(&set_b, &[11, 20, 30], circuit.salt_b);
+    assert_eq!(gate_failures(&circuit, public_inputs), vec!["permutation"]);
+}
+
+/// Range checks a single witnessed value
+struct RangeCircuit {
+    value: Fp,
+    bits: usize,
+}
+
+impl Circuit<Fp> for RangeCircuit {
+    type Config = PsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            value: Fp::zero(),
+            bits: self.bits,
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        PsiConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let value = layouter.assign_region(
+            || "value",
+            |mut region| {
+                region.assign_advice(|| "value", config.weight, 0, || Value::known(self.value))
+            },
+        )?;
+        config.assert_range(layouter.namespace(|| "range"), &value, self.bits)
+    }
+}
+
+#[test]
+fn test_range_check_bounds() {
+    let max = Fp::from(u32::MAX as u64);
+    for (value, in_range) in [
+        (Fp::zero(), true),
+        (max, true),
+        (max + Fp::one(), false),
+        (-Fp::one(), false),
+    ] {
+        let circuit = RangeCircuit {
+            value,
+            bits: VALUE_BITS,
+        };
+        assert_eq!(gate_failures(&circuit, vec![]).is_empty(), in_range);
+    }
+}
+
+#[test]
+fn test_cardinalities_mock_prover() {
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let circuit = PsiCircuit::new(set_a, set_b, 3)
+        .unwrap()
+        .with_cardinalities();
+
+    let cardinalities = circuit.cardinalities();
+    assert_eq!(
+        cardinalities,
+        Cardinalities {
+            size_a: 7,
+            size_b: 13,
+            intersection: 3,
+            union: 17,
+        }
+    );
+
+    let public_inputs = circuit.public_inputs();
+    assert_eq!(public_inputs.len(), CARDINALITIES_ROW + 4);
+    assert!(gate_failures(&circuit, public_inputs.clone()).is_empty());
+
+    // Every exposed size is bound to the sets
+    for row in CARDINALITIES_ROW..CARDINALITIES_ROW + 4 {
+        let mut tampered = public_inputs.clone();
+        tampered[row] += Fp::one();
+        assert_eq!(
+            gate_failures(&circuit, tampered),
+            vec!["permutation"],
+            "row {}",
+            row
+        );
+    }
+}
+
+#[test]
+fn test_multiset_cardinalities() {
+    let (set_a, set_b) = duplicated_sets();
+    let circuit = PsiCircuit::new(set_a, set_b, 3)
+        .unwrap()
+        .with_semantics(IntersectionSemantics::Multiset)
+        .unwrap()
+        .with_cardinalities();
+
+    // Union takes the larger multiplicity: x three times, y twice, z once
+    assert_eq!(circuit.cardinalities().union, 6);
+    assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+}
+
+#[test]
+fn test_jaccard_from_public_inputs() {
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let circuit = PsiCircuit::new(set_a, set_b, 3)
+        .unwrap()
+        .with_cardinalities();
+    let cardinalities = Cardinalities::from_public_inputs(&circuit.public_inputs()).unwrap();
+    assert_eq!(cardinalities, circuit.cardinalities());
+    assert_eq!(cardinalities.jaccard(), Some(3.0 / 17.0));
+
+    let empty = PsiCircuit::new(vec![], vec![], 0)
+        .unwrap()
+        .with_cardinalities();
+    let cardinalities = Cardinalities::from_public_inputs(&empty.public_inputs()).unwrap();
+    assert_eq!(cardinalities.jaccard(), None);
+
+    // Missing, inconsistent or out of range sizes are rejected
+    let mut public_inputs = circuit.public_inputs();
+    assert!(Cardinalities::from_public_inputs(&public_inputs[..CARDINALITIES_ROW + 3]).is_none());
+    public_inputs[CARDINALITIES_ROW + 3] += Fp::one();
+    assert!(Cardinalities::from_public_inputs(&public_inputs).is_none());
+    public_inputs[CARDINALITIES_ROW + 3] = -Fp::one();
+    assert!(Cardinalities::from_public_inputs(&public_inputs).is_none());
+}
+
+#[test]
+fn test_cardinalities_proof_verification_flow() {
+    let (set_a, set_b) = shaped_sets(4, 6, 2);
+    let circuit = PsiCircuit::new(set_a, set_b, 2)
+        .unwrap()
+        .with_cardinalities();
+    let (params, pk, vk) = setup_for(12, &circuit).unwrap();
+
+    let public_inputs = circuit.public_inputs();
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+
+    let cardinalities = Cardinalities::from_public_inputs(&public_inputs).unwrap();
+    assert_eq!(cardinalities.jaccard(), Some(0.25));
+}
+
+/// Three parties: 1..=8, 5..=12 and {2, 4, .., 16}
+fn three_sets() -> Vec<Vec<Fp>> {
+    vec![
+        (1..=8).map(hash_to_field).collect(),
+        (5..=12).map(hash_to_field).collect(),
+        (1..=8).map(|i| hash_to_field(2 * i)).collect(),
+    ]
+}
+
+#[test]
+fn test_multi_intersection_native() {
+    let sets = three_sets();
+    // {6, 8} are in all three sets
+    assert_eq!(multi_intersection_size(&sets, 3), 2);
+    // 5..=8, {2, 4} and {10, 12} are in at least two
+    assert_eq!(multi_intersection_size(&sets, 2), 8);
+    // The union
+    assert_eq!(multi_intersection_size(&sets, 1), 14);
+
+    let circuit = MultiPsiCircuit::new(sets[..2].to_vec(), 0).unwrap();
+    assert_eq!(
+        circuit.compute_intersection_size(),
+        IntersectionSemantics::Set.intersection_size(&sets[0], &sets[1])
+    );
+}
+
+#[test]
+fn test_multi_mock_prover() {
+    for (threshold, count) in [(3, 2), (2, 8), (1, 14)] {
+        let circuit = MultiPsiCircuit::new(three_sets(), count)
+            .unwrap()
+            .with_threshold(threshold)
+            .unwrap();
+        assert_eq!(circuit.compute_intersection_size(), count);
+        assert!(gate_failures_at(13, &circuit, circuit.public_inputs()).is_empty());
+
+        let mut wrong = circuit.public_inputs();
+        wrong[MULTI_COUNT_ROW] = Fp::from(count + 1);
+        assert_eq!(gate_failures_at(13, &circuit, wrong), vec!["permutation"]);
+    }
+}
+
+#[test]
+fn test_multi_rejects_duplicates() {
+    let mut sets = three_sets();
+    let repeated = sets[1][0];
+    sets[1].push(repeated);
+    let circuit = MultiPsiCircuit::new(sets, 0).unwrap();
+    let count = circuit.compute_intersection_size();
+    let circuit = MultiPsiCircuit {
+        intersection_size: count,
+        ..circuit
+    };
+    assert_eq!(
+        gate_failures_at(13, &circuit, circuit.public_inputs()),
+        vec!["distinct elements"]
+    );
+}
+
+#[test]
+fn test_multi_proof_verification_flow() {
+    let (params, pk, vk) = setup_multi(13, MAX_SET_SIZE, 3, 2).unwrap();
+    let circuit = MultiPsiCircuit::new(three_sets(), 8)
+        .unwrap()
+        .with_threshold(2)
+        .unwrap();
+    let public_inputs = circuit.public_inputs();
+
+    let proof = prove_multi(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_multi(&params, &vk, &proof, &public_inputs).unwrap();
+
+    let mut wrong = public_inputs.clone();
+    wrong[MULTI_COUNT_ROW] = Fp::from(2);
+    assert!(verify_multi(&params, &vk, &proof, &wrong).is_err());
+
+    // The threshold is part of the circuit shape
+    let (_, _, vk_all) = setup_multi(13, MAX_SET_SIZE, 3, 3).unwrap();
+    assert!(verify_multi(&params, &vk_all, &proof, &public_inputs).is_err());
+}
+
+/// A catalogue of 1..=300 and a set sharing 7 of its 20 elements with it
+fn catalogue_sets() -> (Vec<Fp>, Vec<Fp>) {
+    let catalogue = (1..=300).map(hash_to_field).collect();
+    let set_a = (294..=313).map(hash_to_field).collect();
+    (set_a, catalogue)
+}
+
+/// Lookup circuit whose membership witness is rewritten for one slot
+#[derive(Clone)]
+struct TamperedLookupCircuit {
+    circuit: LookupPsiCircuit,
+    slot: usize,
+    witness: (bool, Fp, Fp),
+}
+
+impl Circuit<Fp> for TamperedLookupCircuit {
+    type Config = LookupPsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        LookupPsiConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.circuit
+            .synthesize_with(&config, layouter, |slot, honest| {
+                if slot == self.slot {
+                    self.witness
+                } else {
+                    honest
+                }
+            })
+    }
+}
+
+#[test]
+fn test_lookup_mock_prover() {
+    let (set_a, catalogue) = catalogue_sets();
+    let circuit = LookupPsiCircuit::new(set_a.clone(), catalogue.clone(), 7).unwrap();
+    assert_eq!(circuit.compute_intersection_size(), 7);
+    assert!(gate_failures_at(13, &circuit, circuit.public_inputs()).is_empty());
+
+    let mut wrong = circuit.public_inputs();
+    wrong[INTERSECTION_SIZE_ROW] = Fp::from(6);
+    assert_eq!(gate_failures_at(13, &circuit, wrong), vec!["permutation"]);
+
+    // An empty catalogue and an empty set
+    for (set_a, catalogue) in [(set_a, vec![]), (vec![], catalogue)] {
+        let circuit = LookupPsiCircuit::new(set_a, catalogue, 0).unwrap();
+        assert!(gate_failures_at(13, &circuit, circuit.public_inputs()).is_empty());
+    }
+}
+
+#[test]
+fn test_lookup_rejects_false_membership_claims() {
+    let (set_a, catalogue) = catalogue_sets();
+    let sorted = sorted_catalogue(&catalogue);
+    let circuit = LookupPsiCircuit::new(set_a.clone(), catalogue, 0).unwrap();
+
+    // Slot 0 holds a member: hiding it between its true neighbours fails
+    // the range check of hi - a - 1 = -1, whose byte decomposition does
+    // not end at zero
+    let (member, lo, _) = catalogue_neighbours(&sorted, &set_a[0]);
+    assert!(member);
+    let tampered = TamperedLookupCircuit {
+        circuit: LookupPsiCircuit {
+            intersection_size: 6,
+            ..circuit.clone()
+        },
+        slot: 0,
+        witness: (false, lo, set_a[0]),
+    };
+    let instance = tampered.circuit.public_inputs();
+    assert_eq!(
+        gate_failures_at(13, &tampered, instance),
+        vec!["permutation"]
+    );
+
+    // Slot 19 holds a non-member: claiming it is not in the table
+    let (member, lo, _) = catalogue_neighbours(&sorted, &set_a[19]);
+    assert!(!member);
+    let tampered = TamperedLookupCircuit {
+        circuit: LookupPsiCircuit {
+            intersection_size: 8,
+            ..circuit.clone()
+        },
+        slot: 19,
+        witness: (true, lo, set_a[19]),
+    };
+    let instance = tampered.circuit.public_inputs();
+    assert_eq!(gate_failures_at(13, &tampered, instance), vec!["lookup"]);
+
+    // The upper sentinel is not an element
+    let tampered = TamperedLookupCircuit {
+        circuit: LookupPsiCircuit {
+            intersection_size: 8,
+            ..circuit
+        },
+        slot: 19,
+        witness: (true, *sorted.last().unwrap(), element_bound()),
+    };
+    let instance = tampered.circuit.public_inputs();
+    assert!(!gate_failures_at(13, &tampered, instance).is_empty());
+}
+
+#[test]
+fn test_lookup_proof_verification_flow() {
+    // A catalogue far beyond the comparison grid's MAX_SET_SIZE
+    let catalogue: Vec<Fp> = (1..=5000).map(hash_to_field).collect();
+    let set_a: Vec<Fp> = (4990..5010).map(hash_to_field).collect();
+    let (params, pk, vk) = setup_lookup(13, MAX_SET_SIZE, &catalogue).unwrap();
+
+    let circuit = LookupPsiCircuit::new(set_a.clone(), catalogue.clone(), 11).unwrap();
+    assert_eq!(circuit.compute_intersection_size(), 11);
+    let public_inputs = circuit.public_inputs();
+
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+
+    let mut wrong = public_inputs.clone();
+    wrong[INTERSECTION_SIZE_ROW] = Fp::from(10);
+    assert!(verify_proof(&params, &vk, &proof, &wrong).is_err());
+
+    // The catalogue is part of the keys
+    let (_, _, other_vk) = setup_lookup(13, MAX_SET_SIZE, &catalogue[1..]).unwrap();
+    assert!(verify_proof(&params, &other_vk, &proof, &public_inputs).is_err());
+}
+
+type MergeTamper = fn(&mut Vec<(Fp, bool, bool)>);
+
+/// Sort circuit whose merged list is rewritten before it is laid out
+#[derive(Clone)]
+struct TamperedSortCircuit {
+    circuit: SortPsiCircuit,
+    tamper: MergeTamper,
+}
+
+impl Circuit<Fp> for TamperedSortCircuit {
+    type Config = SortPsiConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        SortPsiConfig::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.circuit
+            .synthesize_with(&config, layouter, |mut entries| {
+                (self.tamper)(&mut entries);
+                entries
+            })
+    }
+}
+
+#[test]
+fn test_sort_mock_prover() {
+    for (len_a, len_b, overlap) in [(0, 0, 0), (1, 1, 1), (7, 13, 3), (32, 32, 16)] {
+        let (set_a, set_b) = shaped_sets(len_a, len_b, overlap);
+        let circuit = SortPsiCircuit::new(set_a, set_b, overlap).unwrap();
+        assert_eq!(circuit.compute_intersection_size(), overlap);
+        assert!(
+            gate_failures_at(13, &circuit, circuit.public_inputs()).is_empty(),
+            "{}x{} sets",
+            len_a,
+            len_b
+        );
+
+        let mut wrong = circuit.public_inputs();
+        wrong[INTERSECTION_SIZE_ROW] += Fp::one();
+        assert_eq!(gate_failures_at(13, &circuit, wrong), vec!["permutation"]);
+    }
+
+    // Duplicates end up next to each other with the same tag, and are
+    // counted as well
+    let (mut set_a, set_b) = shaped_sets(7, 13, 3);
+    set_a.push(set_a[0]);
+    let circuit = SortPsiCircuit::new(set_a, set_b, 3).unwrap();
+    assert_eq!(
+        gate_failures_at(13, &circuit, circuit.public_inputs()),
+        vec!["merged step", "permutation"]
+    );
+}
+
+#[test]
+fn test_sort_rejects_forged_merges() {
+    // 7 and 13 elements sharing 3: the merged list holds 44 padding
+    // entries, then 17 distinct elements with the 3 shared ones paired
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let circuit = SortPsiCircuit::new(set_a, set_b, 3).unwrap();
+    let with_count = |count| SortPsiCircuit {
+        intersection_size: count,
+        ..circuit.clone()
+    };
+    /// Position of the B entry of the first pair of equal elements
+    fn first_pair(entries: &[(Fp, bool, bool)]) -> usize {
+        (1..entries.len())
+            .find(|&i| entries[i].1 && entries[i].0 == entries[i - 1].0)
+            .unwrap()
+    }
+
+    let cases: [(u64, MergeTamper, &[&str]); 4] = [
+        // Splitting a pair apart breaks the ascending order: the range
+        // check of a negative step does not end at zero
+        (
+            2,
+            |entries| {
+                let (i, last) = (first_pair(entries), entries.len() - 1);
+                entries.swap(i, last);
+            },
+            &["permutation"],
+        ),
+        // Putting the B entry of a pair first
+        (
+            3,
+            |entries| {
+                let i = first_pair(entries);
+                entries.swap(i - 1, i);
+            },
+            &["merged step"],
+        ),
+        // Hiding an element of a pair as padding
+        (
+            2,
+            |entries| {
+                let i = first_pair(entries);
+                let (_, _, tag) = entries.remove(i);
+                entries.insert(0, (Fp::zero(), false, tag));
+            },
+            &["permutation"],
+        ),
+        // Duplicating an element of B to pair it with one of A
+        (
+            4,
+            |entries| {
+                let i = (1..entries.len() - 1)
+                    .find(|&i| {
+                        entries[i].1
+                            && !entries[i].2
+                            && entries[i - 1].0 != entries[i].0
+                            && entries[i + 1].0 != entries[i].0
+                    })
+                    .unwrap();
+                entries.remove(0);
+                entries.insert(i, (entries[i - 1].0, true, true));
+            },
+            &["permutation"],
+        ),
+    ];
+
+    for (count, tamper, failures) in cases {
+        let tampered = TamperedSortCircuit {
+            circuit: with_count(count),
+            tamper,
+        };
+        let instance = tampered.circuit.public_inputs();
+        assert_eq!(gate_failures_at(13, &tampered, instance), failures);
+    }
+}
+
+#[test]
+fn test_sort_proof_verification_flow() {
+    let (params, pk, vk) = setup_sort(13, SetCapacity::default()).unwrap();
+
+    for (len_a, len_b, overlap) in [(1, 1, 1), (32, 32, 16)] {
+        let (set_a, set_b) = shaped_sets(len_a, len_b, overlap);
+        let circuit = SortPsiCircuit::new(set_a.clone(), set_b.clone(), overlap).unwrap();
+        let public_inputs = circuit.public_inputs();
+
+        let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+        verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+
+        let mut wrong = public_inputs.clone();
+        wrong[INTERSECTION_SIZE_ROW] += Fp::one();
+        assert!(verify_proof(&params, &vk, &proof, &wrong).is_err());
+    }
+
+    // Same public inputs as the grid, but not the same keys
+    let (_, _, grid_vk) = setup_eq(13).unwrap();
+    let (set_a, set_b) = shaped_sets(7, 13, 3);
+    let circuit = SortPsiCircuit::new(set_a, set_b, 3).unwrap();
+    let public_inputs = circuit.public_inputs();
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    assert!(verify_proof(&params, &grid_vk, &proof, &public_inputs).is_err());
+}
+
+#[test]
+fn test_min_k_matches_layouts() {
+    assert_eq!(min_k(&PsiCircuit::default()).unwrap(), 12);
+    assert_eq!(min_k(&SortPsiCircuit::default()).unwrap(), 13);
+
+    for (max_a, max_b) in [(1, 1), (8, 40), (40, 8)] {
+        let capacity = SetCapacity::new(max_a, max_b).unwrap();
+        let k = min_k(&PsiCircuit::empty(capacity)).unwrap();
+
+        let (set_a, set_b) = shaped_sets(max_a as u64, max_b as u64, 1);
+        let circuit = PsiCircuit::new_with_capacity(
+            capacity,
+            set_a,
+            set_b,
+            1,
+            Fp::from(3u64),
+            Fp::from(5u64),
+        )
+        .unwrap();
+        let public_inputs = circuit.public_inputs();
+        assert!(gate_failures_at(k, &circuit, public_inputs.clone()).is_empty());
+        assert!(matches!(
+            MockProver::run(k - 1, &circuit, vec![public_inputs]),
+            Err(Error::NotEnoughRowsAvailable { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_capacity_mock_prover() {
+    let capacity = SetCapacity::new(40, 24).unwrap();
+    let (set_a, set_b) = shaped_sets(40, 24, 20);
+    let (salt_a, salt_b) = (Fp::from(3u64), Fp::from(5u64));
+    let psi =
+        PsiCircuit::new_with_capacity(capacity, set_a.clone(), set_b.clone(), 20, salt_a, salt_b)
+            .unwrap();
+    assert_eq!(
+        psi.commitment_a(),
+        commit_set_with_capacity(&set_a, salt_a, 40)
+    );
+
+    let k = min_k(&ThresholdPsiCircuit::new(psi.clone(), 0)).unwrap();
+    assert!(gate_failures_at(k, &psi, psi.public_inputs()).is_empty());
+
+    // Commitments are over the slots of the capacity
+    let mut public_inputs = psi.public_inputs();
+    public_inputs[COMMITMENT_B_ROW] = commit_set(&set_b, salt_b);
+    assert_eq!(
+        gate_failures_at(k, &psi, public_inputs),
+        vec!["permutation"]
+    );
+
+    // Counts above MAX_SET_SIZE still fit the threshold range check
+    for (threshold, holds) in [(20, true), (21, false)] {
+        let circuit = ThresholdPsiCircuit::new(psi.clone(), threshold);
+        let failures = gate_failures_at(k, &circuit, circuit.public_inputs());
+        assert_eq!(failures.is_empty(), holds, "threshold {}", threshold);
+    }
+}
+
+#[test]
+fn test_capacity_of_other_modes_mock_prover() {
+    let capacity = SetCapacity::new(12, 8).unwrap();
+    let (set_a, set_b) = shaped_sets(12, 8, 5);
+    let salt = Fp::from(3u64);
+
+    let sort =
+        SortPsiCircuit::new_with_capacity(capacity, set_a.clone(), set_b.clone(), 5, salt, salt)
+            .unwrap();
+    assert_eq!(
+        sort.commitment_b(),
+        commit_set_with_capacity(&set_b, salt, 8)
+    );
+    let k = min_k(&sort).unwrap();
+    assert!(gate_failures_at(k, &sort, sort.public_inputs()).is_empty());
+
+    let lookup =
+        LookupPsiCircuit::new_with_capacity(12, set_a.clone(), set_b.clone(), 5, salt).unwrap();
+    let k = min_k(&lookup).unwrap();
+    assert!(gate_failures_at(k, &lookup, lookup.public_inputs()).is_empty());
+
+    let sets = vec![set_a, set_b, (1..=10).map(hash_to_field).collect()];
+    let multi = MultiPsiCircuit::new_with_capacity(12, sets, vec![salt; 3], 5)
+        .unwrap()
+        .with_threshold(2)
+        .unwrap();
+    let multi = MultiPsiCircuit {
+        intersection_size: multi.compute_intersection_size(),
+        ..multi
+    };
+    let k = min_k(&multi).unwrap();
+    assert!(gate_failures_at(k, &multi, multi.public_inputs()).is_empty());
+
+    // An unsupported shape set through the public fields fails at synthesis
+    let mut invalid = MultiPsiCircuit::empty(12, 3, 2);
+    invalid.threshold = 4;
+    assert!(min_k(&invalid).is_err());
+}
+
+#[test]
+fn test_max_k_holds_max_capacity() {
+    let capacity = SetCapacity::new(MAX_SET_CAPACITY, MAX_SET_CAPACITY).unwrap();
+    let psi = PsiCircuit::empty(capacity);
+    assert_eq!(
+        min_k(&ThresholdPsiCircuit::new(psi.clone(), 0)).unwrap(),
+        MAX_K
+    );
+    assert!(min_k(&SubsetCircuit::new(psi)).unwrap() <= MAX_K);
+}
+
+#[test]
+fn test_capacity_proof_verification_flow() {
+    let capacity = SetCapacity::new(4, 6).unwrap();
+    let k = min_k(&PsiCircuit::empty(capacity)).unwrap();
+    let (params, pk, vk) = setup_eq_with_capacity(k, capacity).unwrap();
+
+    let (set_a, set_b) = shaped_sets(4, 6, 2);
+    let circuit =
+        PsiCircuit::new_with_capacity(capacity, set_a, set_b, 2, Fp::from(3u64), Fp::zero())
+            .unwrap();
+    let public_inputs = circuit.public_inputs();
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+
+    // Keys for the swapped capacity describe another circuit
+    let (_, _, swapped_vk) = setup_eq_with_capacity(k, SetCapacity::new(6, 4).unwrap()).unwrap();
+    assert!(verify_proof(&params, &swapped_vk, &proof, &public_inputs).is_err());
+}
+
+/// Run a packed circuit over sets of several shapes and semantics
+fn assert_packed_mock_prover<const LANES: usize>() {
+    for (len_a, len_b, overlap) in [(1, 1, 1), (7, 13, 3), (32, 32, 16)] {
+        let (set_a, set_b) = shaped_sets(len_a, len_b, overlap);
+        let circuit =
+            PackedPsiCircuit::<LANES>::new(PsiCircuit::new(set_a, set_b, overlap).unwrap());
+        let public_inputs = circuit.public_inputs();
+        assert!(
+            gate_failures(&circuit, public_inputs.clone()).is_empty(),
+            "{} lanes, {}x{} sets",
+            LANES,
+            len_a,
+            len_b
+        );
+
+        let mut wrong = public_inputs;
+        wrong[INTERSECTION_SIZE_ROW] += Fp::one();
+        assert_eq!(gate_failures(&circuit, wrong), vec!["permutation"]);
+    }
+
+    let (set_a, set_b) = duplicated_sets();
+    let multiset = PsiCircuit::new(set_a, set_b, 3)
+        .unwrap()
+        .with_semantics(IntersectionSemantics::Multiset)
+        .unwrap();
+    let (set_a, set_b) = shaped_sets(5, 9, 3);
+    let weighted = PsiCircuit::new(set_a.clone(), set_b.clone(), 0)
+        .unwrap()
+        .with_values_b((1..=set_b.len() as u32).collect())
+        .unwrap();
+    let cardinalities = PsiCircuit::new(set_a, set_b, 3)
+        .unwrap()
+        .with_cardinalities();
+    for psi in [multiset, weighted, cardinalities] {
+        let circuit = PackedPsiCircuit::<LANES>::new(psi);
+        assert!(gate_failures(&circuit, circuit.public_inputs()).is_empty());
+    }
+}
+
+#[test]
+fn test_packed_mock_prover() {
+    assert_packed_mock_prover::<2>();
+    assert_packed_mock_prover::<3>();
+    assert_packed_mock_prover::<4>();
+}
+
+#[test]
+fn test_packed_rows() {
+    // Below 64 x 64 the rows are dominated by the commitments
+    let capacity = SetCapacity::new(64, 64).unwrap();
+    let psi = PsiCircuit::empty(capacity);
+    let size = layout_size(&psi).unwrap();
+    assert_eq!(
+        layout_size(&PackedPsiCircuit::<1>::new(psi.clone())).unwrap(),
+        size
+    );
+
+    // Every extra lane takes comparisons off the 64 x 64 grid rows
+    let grid = 64 * 64;
+    let packed = [
+        layout_size(&PackedPsiCircuit::<2>::new(psi.clone())).unwrap(),
+        layout_size(&PackedPsiCircuit::<3>::new(psi.clone())).unwrap(),
+    ];
+    for (lanes, packed) in [(2, packed[0]), (3, packed[1])] {
+        assert_eq!(packed.advice_columns, size.advice_columns + (lanes - 1) * 9);
+        assert_eq!(
+            size.rows - packed.rows,
+            grid - 64 * 64usize.div_ceil(lanes),
+            "{} lanes",
+            lanes
+        );
+    }
+
+    assert_eq!(min_k(&psi).unwrap(), 14);
+    assert_eq!(min_k(&PackedPsiCircuit::<2>::new(psi)).unwrap(), 13);
+}
+
+#[test]
+fn test_packed_adversarial_rows_fail() {
+    let (set_a, set_b) = adversarial_sets();
+    let packed = |circuit: AdversarialCircuit| circuit.packed::<3>();
+
+    let honest = packed(AdversarialCircuit::honest(&set_a, &set_b));
+    assert!(failing_gates(&honest, 2).is_empty());
+
+    // A forged match in the last lane of a row
+    let circuit = packed(AdversarialCircuit::pick_row(
+        &set_a,
+        &set_b,
+        0,
+        2,
+        Fp::one(),
+    ));
+    assert_eq!(failing_gates(&circuit, 3), vec!["equality check"]);
+
+    // A hidden match in the first lane of a row
+    let circuit = packed(AdversarialCircuit::pick_row(
+        &set_a,
+        &set_b,
+        1,
+        0,
+        Fp::zero(),
+    ));
+    assert_eq!(failing_gates(&circuit, 1), vec!["equality check"]);
+
+    // A lane skipped by the single accumulator step of its row
+    let target = AdversarialCircuit::row(1, 0);
+    let circuit = packed(AdversarialCircuit::new(
+        &set_a,
+        &set_b,
+        Box::new(move |row, mut witness| {
+            if row == target {
+                let (prev, _) = witness.accumulators[0];
+                witness.accumulators[0] = (prev, prev);
+            }
+            witness
+        }),
+    ));
+    assert_eq!(failing_gates(&circuit, 1), vec!["sum accumulator"]);
+
+    // Two lanes of one row picking the same A slot
+    let x = hash_to_field(1);
+    let target = AdversarialCircuit::row(0, 1);
+    let mut circuit = AdversarialCircuit::new(
+        &[x],
+        &[x, x],
+        Box::new(move |row, witness| {
+            if row == target {
+                witness.repick(Fp::one())
+            } else {
+                witness
+            }
+        }),
+    );
+    circuit.used_b[1] = true;
+    assert_eq!(failing_gates(&packed(circuit), 2), vec!["permutation"]);
+}
+
+#[test]
+fn test_packed_proof_verification_flow() {
+    let (set_a, set_b) = shaped_sets(12, 20, 5);
+    let circuit = PackedPsiCircuit::<4>::new(
+        PsiCircuit::new_with_salts(set_a, set_b, 5, Fp::from(3u64), Fp::from(5u64)).unwrap(),
+    );
+    let k = min_k(&circuit).unwrap();
+    let (params, pk, vk) = setup_for(k, &circuit).unwrap();
+
+    let public_inputs = circuit.public_inputs();
+    assert_eq!(public_inputs, circuit.psi.public_inputs());
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+
+    // The lane count is part of the circuit shape
+    let (_, _, other_vk) = setup_for(k, &PackedPsiCircuit::<2>::default()).unwrap();
+    assert!(verify_proof(&params, &other_vk, &proof, &public_inputs).is_err());
+}
+
+#[test]
+fn test_chunk_circuit_mock_prover() {
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let (set_a, set_b) = shaped_sets(4, 3, 2);
+    let (set_a, set_b) = (sorted_set(&set_a), sorted_set(&set_b));
+    let chain_in = hash_to_field(9);
+    let lower_a = (set_a[0], Fp::from(11u64));
+    let circuit = PsiChunkCircuit::new(
+        capacity,
+        set_a.clone(),
+        set_b.clone(),
+        Fp::from(3u64),
+        Fp::from(5u64),
+        7,
+        chain_in,
+    )
+    .unwrap()
+    .with_lower_bounds(lower_a, (Fp::zero(), Fp::zero()));
+    assert_eq!(circuit.count_out(), 9);
+    assert_eq!(
+        circuit.upper_bounds(),
+        (set_a[3] + Fp::one(), set_b[2] + Fp::one())
+    );
+    let public_inputs = circuit.public_inputs();
+    let k = min_k(&circuit).unwrap();
+    assert!(gate_failures_at(k, &circuit, public_inputs.clone()).is_empty());
+
+    // The count must continue from count_in, the chain from chain_in, and
+    // the bounds must open to the witnessed ones
+    for (row, value) in [
+        (CHUNK_COUNT_IN_ROW, Fp::from(8u64)),
+        (CHUNK_COUNT_OUT_ROW, Fp::from(2u64)),
+        (CHUNK_CHAIN_IN_ROW, Fp::zero()),
+        (CHUNK_CHAIN_OUT_ROW, chain_in),
+        (CHUNK_BOUND_A_IN_ROW, chunk_bound(Fp::zero(), Fp::zero())),
+        (CHUNK_BOUND_A_OUT_ROW, chunk_bound(set_a[3], Fp::from(3u64))),
+        (CHUNK_BOUND_B_IN_ROW, chunk_bound(Fp::one(), Fp::zero())),
+        (CHUNK_BOUND_B_OUT_ROW, chunk_bound(set_b[2], Fp::from(5u64))),
+    ] {
+        let mut wrong = public_inputs.clone();
+        wrong[row] = value;
+        assert_eq!(
+            gate_failures_at(k, &circuit, wrong),
+            vec!["permutation"],
+            "row {}",
+            row
+        );
+    }
+
+    // An element below the lower bound, or out of order, gives a negative
+    // step whose range check cannot end in zero
+    let mut above = circuit.clone();
+    above.lower_a.0 = set_a[0] + Fp::one();
+    assert_eq!(
+        gate_failures_at(k, &above, above.public_inputs()),
+        vec!["permutation"]
+    );
+
+    let mut unsorted = circuit.clone();
+    unsorted.psi.set_b.swap(0, 1);
+    assert_eq!(
+        gate_failures_at(k, &unsorted, unsorted.public_inputs()),
+        vec!["permutation"]
+    );
+}
+
+#[test]
+fn test_chunked_proof_flow() {
+    // 10 elements against 7, sharing 5, in chunks of 4: 3 x 2 chunk proofs
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let (set_a, set_b) = shaped_sets(10, 7, 5);
+    let salts_a: Vec<Fp> = (1..=3).map(hash_to_field).collect();
+    let salts_b: Vec<Fp> = (4..=5).map(hash_to_field).collect();
+
+    let k = min_k(&PsiChunkCircuit::empty(capacity)).unwrap();
+    let (params, pk, vk) = setup_chunk(k, capacity).unwrap();
+    let proof =
+        ChunkedPsiProof::prove(&params, &pk, capacity, &set_a, &set_b, &salts_a, &salts_b).unwrap();
+    assert_eq!(proof.chunks.len(), 6);
+    assert_eq!(proof.count(), 5);
+
+    // Verifiers only see the published chunk commitments
+    let commitments_a = commit_chunks(&set_a, &salts_a, 4);
+    let commitments_b = commit_chunks(&set_b, &salts_b, 4);
+    assert_eq!(
+        proof
+            .verify(&params, &vk, &commitments_a, &commitments_b)
+            .unwrap(),
+        5
+    );
+
+    // Another chunking of B gives another chain
+    let other_b = commit_chunks(&set_b[1..], &salts_b, 4);
+    assert!(matches!(
+        proof.verify(&params, &vk, &commitments_a, &other_b),
+        Err(ChunkedPsiError::ChainMismatch)
+    ));
+
+    // Dropped, reordered or forged chunks break continuity
+    let mut dropped = proof.clone();
+    dropped.chunks.pop();
+    assert!(matches!(
+        dropped.verify(&params, &vk, &commitments_a, &commitments_b),
+        Err(ChunkedPsiError::ChainMismatch)
+    ));
+
+    let mut reordered = proof.clone();
+    reordered.chunks.swap(1, 2);
+    assert!(matches!(
+        reordered.verify(&params, &vk, &commitments_a, &commitments_b),
+        Err(ChunkedPsiError::Discontinuity(1))
+    ));
+
+    // A consistently inflated count only fails the chunk proofs
+    let mut inflated = proof.clone();
+    inflated.chunks[4].count_out += 1;
+    inflated.chunks[5].count_in += 1;
+    inflated.chunks[5].count_out += 1;
+    assert!(matches!(
+        inflated.verify(&params, &vk, &commitments_a, &commitments_b),
+        Err(ChunkedPsiError::Proof { chunk: 4, .. })
+    ));
+
+    let empty = ChunkedPsiProof {
+        capacity,
+        chunks: vec![],
+    };
+    assert!(matches!(
+        empty.verify(&params, &vk, &commitments_a, &commitments_b),
+        Err(ChunkedPsiError::NoChunks)
+    ));
+
+    // An element repeated in another chunk would be counted twice
+    let mut repeated = set_a.clone();
+    repeated[9] = repeated[0];
+    assert!(matches!(
+        ChunkedPsiProof::prove(
+            &params, &pk, capacity, &repeated, &set_b, &salts_a, &salts_b
+        ),
+        Err(ChunkedPsiError::DuplicateElement { set: 'A', index: 9 })
+    ));
+
+    let mut oversized = set_b.clone();
+    oversized[2] = element_bound();
+    assert!(matches!(
+        ChunkedPsiProof::prove(
+            &params, &pk, capacity, &set_a, &oversized, &salts_a, &salts_b
+        ),
+        Err(ChunkedPsiError::ElementOutOfRange { set: 'B', index: 2 })
+    ));
+}
+
+#[test]
+fn test_chunked_proof_rejects_repeated_elements() {
+    // A cheating prover repeats the shared element of A in a second chunk
+    // to count it twice
+    let capacity = SetCapacity::new(2, 2).unwrap();
+    let shared = hash_to_field(1);
+    let chunks_a = [sorted_set(&[shared, hash_to_field(2)]), vec![shared]];
+    let set_b = sorted_set(&[shared, hash_to_field(3)]);
+    let salts_a = [hash_to_field(4), hash_to_field(5)];
+    let salt_b = hash_to_field(6);
+    let commitments_a: Vec<Fp> = chunks_a
+        .iter()
+        .zip(&salts_a)
+        .map(|(chunk, salt)| commit_set_with_capacity(chunk, *salt, 2))
+        .collect();
+    let commitments_b = [commit_set_with_capacity(&set_b, salt_b, 2)];
+
+    let k = min_k(&PsiChunkCircuit::empty(capacity)).unwrap();
+    let (params, pk, vk) = setup_chunk(k, capacity).unwrap();
+
+    let chunk_circuit = |i: usize, count_in, chain_in, lower_a| {
+        PsiChunkCircuit::new(
+            capacity,
+            chunks_a[i].clone(),
+            set_b.clone(),
+            salts_a[i],
+            salt_b,
+            count_in,
+            chain_in,
+        )
+        .unwrap()
+        .with_lower_bounds(lower_a, (Fp::zero(), Fp::zero()))
+    };
+    let first = chunk_circuit(0, 0, Fp::zero(), (Fp::zero(), Fp::zero()));
+
+    // Starting the second chunk above the first one cannot be proven
+    let honest_lower = (first.upper_bounds().0, salts_a[0]);
+    let second = chunk_circuit(1, first.count_out(), first.chain_out(), honest_lower);
+    assert!(!gate_failures_at(k, &second, second.public_inputs()).is_empty());
+
+    // Restarting it from zero proves, but the verifier sees the gap
+    let second = chunk_circuit(
+        1,
+        first.count_out(),
+        first.chain_out(),
+        (Fp::zero(), Fp::zero()),
+    );
+    let chunks = [first, second]
+        .into_iter()
+        .map(|circuit| {
+            let public_inputs = circuit.public_inputs();
+            let [bound_a_in, bound_a_out, bound_b_in, bound_b_out] = circuit.bound_commitments();
+            PsiChunkProof {
+                count_in: circuit.count_in,
+                count_out: circuit.count_out(),
+                chain_in: circuit.chain_in,
+                chain_out: circuit.chain_out(),
+                bound_a_in,
+                bound_a_out,
+                bound_b_in,
+                bound_b_out,
+                proof: generate_proof(&params, &pk, circuit, &public_inputs).unwrap(),
+            }
+        })
+        .collect();
+    let cheat = ChunkedPsiProof { capacity, chunks };
+    assert_eq!(cheat.count(), 2);
+    assert!(
+        cheat.chunks.iter().all(|chunk| verify_proof(
+            &params,
+            &vk,
+            &chunk.proof,
+            &chunk.public_inputs()
+        )
+        .is_ok())
+    );
+    assert!(matches!(
+        cheat.verify(&params, &vk, &commitments_a, &commitments_b),
+        Err(ChunkedPsiError::Unordered(1))
+    ));
+}
+
+#[test]
+fn test_verify_proofs_batch() {
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let k = min_k(&PsiCircuit::empty(capacity)).unwrap();
+    let (params, pk, vk) = setup_eq_with_capacity(k, capacity).unwrap();
+
+    let mut batch: Vec<(Vec<u8>, Vec<Fp>)> = (0..4)
+        .map(|overlap| {
+            let (set_a, set_b) = shaped_sets(4, 4, overlap);
+            let circuit = PsiCircuit::new_with_capacity(
+                capacity,
+                set_a,
+                set_b,
+                overlap,
+                Fp::from(3u64),
+                Fp::from(5u64),
+            )
+            .unwrap();
+            let public_inputs = circuit.public_inputs();
+            let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+            (proof, public_inputs)
+        })
+        .collect();
+    assert_eq!(verify_proofs_batch(&params, &vk, &batch), Ok(()));
+    assert_eq!(
+        verify_proofs_batch::<Vec<u8>, Vec<Fp>>(&params, &vk, &[]),
+        Ok(())
+    );
+
+    // A wrong count and a corrupted transcript are pinpointed
+    batch[1].1[INTERSECTION_SIZE_ROW] += Fp::one();
+    let last = batch[3].0.len() - 1;
+    batch[3].0[last] ^= 1;
+    assert_eq!(
+        verify_proofs_batch(&params, &vk, &batch),
+        Err(BatchVerifyError { failed: vec![1, 3] })
+    );
+
+    // Proofs from another circuit fail against this key
+    let (_, _, other_vk) = setup_eq_with_capacity(k, SetCapacity::new(3, 4).unwrap()).unwrap();
+    assert_eq!(
+        verify_proofs_batch(&params, &other_vk, &batch[..1]),
+        Err(BatchVerifyError { failed: vec![0] })
+    );
+}
+
+#[test]
+fn test_generate_multi_proof_flow() {
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let k = min_k(&PsiCircuit::empty(capacity)).unwrap();
+    let (params, pk, vk) = setup_eq_with_capacity(k, capacity).unwrap();
+
+    let circuits: Vec<PsiCircuit> = (0..3)
+        .map(|overlap| {
+            let (set_a, set_b) = shaped_sets(4, 3, overlap);
+            PsiCircuit::new_with_capacity(
+                capacity,
+                set_a,
+                set_b,
+                overlap,
+                Fp::from(3u64),
+                Fp::from(5u64),
+            )
+            .unwrap()
+        })
+        .collect();
+    let public_inputs: Vec<Vec<Fp>> = circuits.iter().map(|c| c.public_inputs()).collect();
+    let proof = generate_multi_proof(&params, &pk, circuits, &public_inputs).unwrap();
+    assert!(verify_multi_proof(&params, &vk, &proof, &public_inputs).is_ok());
+
+    // The proof is bound to every instance and to their order
+    let mut swapped = public_inputs.clone();
+    swapped.swap(0, 2);
+    assert!(verify_multi_proof(&params, &vk, &proof, &swapped).is_err());
+    assert!(verify_multi_proof(&params, &vk, &proof, &public_inputs[..2]).is_err());
+    assert!(verify_proof(&params, &vk, &proof, &public_inputs[0]).is_err());
+
+    let mut forged = public_inputs.clone();
+    forged[1][INTERSECTION_SIZE_ROW] += Fp::one();
+    assert!(verify_multi_proof(&params, &vk, &proof, &forged).is_err());
+
+    // Mismatched or missing instances are rejected up front
+    let circuit = PsiCircuit::empty(capacity);
+    assert!(matches!(
+        generate_multi_proof(&params, &pk, vec![circuit], &public_inputs),
+        Err(Error::InvalidInstances)
+    ));
+    assert!(matches!(
+        generate_multi_proof::<PsiCircuit>(&params, &pk, vec![], &[]),
+        Err(Error::InvalidInstances)
+    ));
+    assert!(matches!(
+        verify_multi_proof(&params, &vk, &proof, &[]),
+        Err(Error::InvalidInstances)
+    ));
+}
@@ -1,5 +1,8 @@
 use pasta_curves::Fp;
-use zk_psi_verifier::{generate_proof, hash_to_field, setup_eq, verify_proof, PsiCircuit};
+use zk_psi_verifier::{
+    PsiCircuit, WEIGHTED_SUM_ROW, generate_proof, hash_to_field, setup_eq, setup_weighted,
+    verify_proof,
+};
 
 #[test]
 fn test_full_proof_verification_flow() {
@@ -18,19 +21,19 @@ fn test_full_proof_verification_flow() {
         hash_to_field(6),
     ];
 
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
     let intersection_size = circuit.compute_intersection_size();
     assert_eq!(intersection_size, 2, "Intersection should be {{2, 3}}");
 
     // Setup
-    let k = 10;
+    let k = 12;
     let (params, pk, vk) = setup_eq(k).expect("Setup failed");
 
     // Create circuit with correct intersection size
-    let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
+    let circuit = PsiCircuit::new(set_a, set_b, intersection_size).unwrap();
 
     // Generate proof
-    let public_inputs = vec![Fp::from(intersection_size)];
+    let public_inputs = circuit.public_inputs();
     let proof =
         generate_proof(&params, &pk, circuit, &public_inputs).expect("Proof generation failed");
 
@@ -45,15 +48,15 @@ fn test_empty_intersection() {
     let set_a = vec![hash_to_field(1), hash_to_field(2)];
     let set_b = vec![hash_to_field(3), hash_to_field(4)];
 
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
     let intersection_size = circuit.compute_intersection_size();
     assert_eq!(intersection_size, 0);
 
-    let k = 10;
+    let k = 12;
     let (params, pk, vk) = setup_eq(k).expect("Setup failed");
 
-    let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
-    let public_inputs = vec![Fp::from(intersection_size)];
+    let circuit = PsiCircuit::new(set_a, set_b, intersection_size).unwrap();
+    let public_inputs = circuit.public_inputs();
 
     let proof =
         generate_proof(&params, &pk, circuit, &public_inputs).expect("Proof generation failed");
@@ -66,15 +69,15 @@ fn test_full_intersection() {
     let set_a = vec![hash_to_field(1), hash_to_field(2), hash_to_field(3)];
     let set_b = set_a.clone();
 
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
     let intersection_size = circuit.compute_intersection_size();
     assert_eq!(intersection_size, 3);
 
-    let k = 10;
+    let k = 12;
     let (params, pk, vk) = setup_eq(k).expect("Setup failed");
 
-    let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
-    let public_inputs = vec![Fp::from(intersection_size)];
+    let circuit = PsiCircuit::new(set_a, set_b, intersection_size).unwrap();
+    let public_inputs = circuit.public_inputs();
 
     let proof =
         generate_proof(&params, &pk, circuit, &public_inputs).expect("Proof generation failed");
@@ -90,11 +93,11 @@ fn test_invalid_proof_fails() {
     // Actual intersection is 1, but we claim it's 0
     let claimed_intersection = 0u64;
 
-    let k = 10;
+    let k = 12;
     let (params, pk, vk) = setup_eq(k).expect("Setup failed");
 
-    let circuit = PsiCircuit::new(set_a, set_b, claimed_intersection);
-    let public_inputs = vec![Fp::from(claimed_intersection)];
+    let circuit = PsiCircuit::new(set_a, set_b, claimed_intersection).unwrap();
+    let public_inputs = circuit.public_inputs();
 
     // This should fail during proof generation or create an invalid proof
     let proof_result = generate_proof(&params, &pk, circuit, &public_inputs);
@@ -112,15 +115,15 @@ fn test_large_sets() {
     let set_a: Vec<Fp> = (1..=16).map(hash_to_field).collect();
     let set_b: Vec<Fp> = (10..=25).map(hash_to_field).collect();
 
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
     let intersection_size = circuit.compute_intersection_size();
     assert_eq!(intersection_size, 7, "Intersection should be {{10..=16}}");
 
     let k = 12; // Need more rows for larger sets
     let (params, pk, vk) = setup_eq(k).expect("Setup failed");
 
-    let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
-    let public_inputs = vec![Fp::from(intersection_size)];
+    let circuit = PsiCircuit::new(set_a, set_b, intersection_size).unwrap();
+    let public_inputs = circuit.public_inputs();
 
     let proof =
         generate_proof(&params, &pk, circuit, &public_inputs).expect("Proof generation failed");
@@ -133,18 +136,65 @@ fn test_single_element_sets() {
     let set_a = vec![hash_to_field(42)];
     let set_b = vec![hash_to_field(42)];
 
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0);
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 0).unwrap();
     let intersection_size = circuit.compute_intersection_size();
     assert_eq!(intersection_size, 1);
 
-    let k = 10;
+    let k = 12;
     let (params, pk, vk) = setup_eq(k).expect("Setup failed");
 
-    let circuit = PsiCircuit::new(set_a, set_b, intersection_size);
-    let public_inputs = vec![Fp::from(intersection_size)];
+    let circuit = PsiCircuit::new(set_a, set_b, intersection_size).unwrap();
+    let public_inputs = circuit.public_inputs();
 
     let proof =
         generate_proof(&params, &pk, circuit, &public_inputs).expect("Proof generation failed");
 
     verify_proof(&params, &vk, &proof, &public_inputs).expect("Proof verification failed");
 }
+
+#[test]
+fn test_weighted_sum_proof() {
+    // Conversions (set A) against ad impressions with spend (set B)
+    let set_a: Vec<Fp> = [1, 2, 3, 4, 5].iter().map(|&x| hash_to_field(x)).collect();
+    let set_b: Vec<Fp> = [4, 5, 6, 1].iter().map(|&x| hash_to_field(x)).collect();
+    let values = vec![250, 1_000, 75, 40];
+
+    let circuit = PsiCircuit::new(set_a, set_b, 0)
+        .unwrap()
+        .with_values_b(values)
+        .unwrap();
+    assert_eq!(circuit.compute_intersection_size(), 3);
+    assert_eq!(circuit.compute_weighted_sum(), 250 + 1_000 + 40);
+
+    let (params, pk, vk) = setup_weighted(12).expect("Setup failed");
+
+    let public_inputs = circuit.public_inputs();
+    assert_eq!(public_inputs[WEIGHTED_SUM_ROW], Fp::from(1_290u64));
+
+    let proof =
+        generate_proof(&params, &pk, circuit, &public_inputs).expect("Proof generation failed");
+    verify_proof(&params, &vk, &proof, &public_inputs).expect("Proof verification failed");
+
+    let mut wrong_inputs = public_inputs.clone();
+    wrong_inputs[WEIGHTED_SUM_ROW] = Fp::from(1_291u64);
+    assert!(verify_proof(&params, &vk, &proof, &wrong_inputs).is_err());
+}
+
+#[test]
+fn test_weighted_sum_with_no_matches() {
+    let set_a = vec![hash_to_field(1), hash_to_field(2)];
+    let set_b = vec![hash_to_field(3)];
+
+    let circuit = PsiCircuit::new(set_a, set_b, 0)
+        .unwrap()
+        .with_values_b(vec![u32::MAX])
+        .unwrap();
+    assert_eq!(circuit.compute_weighted_sum(), 0);
+
+    let (params, pk, vk) = setup_weighted(12).expect("Setup failed");
+
+    let public_inputs = circuit.public_inputs();
+    let proof =
+        generate_proof(&params, &pk, circuit, &public_inputs).expect("Proof generation failed");
+    verify_proof(&params, &vk, &proof, &public_inputs).expect("Proof verification failed");
+}
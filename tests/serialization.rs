@@ -1,18 +1,24 @@
-use pasta_curves::Fp;
-use zk_psi_verifier::{generate_proof, hash_to_field, setup_eq, verify_proof, PsiCircuit};
+use halo2_proofs::poly::commitment::Params;
+use pasta_curves::EqAffine;
+use zk_psi_verifier::{
+    IntersectionSemantics, KeyCircuit, KeyConfig, MAX_K, PROOF_ENVELOPE_VERSION, ProofEnvelope,
+    ProofJson, SetCapacity, min_k, params_digest, params_k, read_params, read_proving_key,
+    read_verifying_key, vk_digest, write_params, write_proving_key, write_verifying_key,
+};
+use zk_psi_verifier::{PsiCircuit, generate_proof, hash_to_field, setup_eq, verify_proof};
 
 #[test]
 fn test_key_regeneration() {
     // Test that we can regenerate keys with the same k value
-    let k = 10;
+    let k = 12;
     let (params1, pk1, vk1) = setup_eq(k).unwrap();
     let (params2, pk2, vk2) = setup_eq(k).unwrap();
 
     // Use first set of keys
     let set_a = vec![hash_to_field(1), hash_to_field(2)];
     let set_b = vec![hash_to_field(2), hash_to_field(3)];
-    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 1);
-    let public_inputs = vec![Fp::from(1u64)];
+    let circuit = PsiCircuit::new(set_a.clone(), set_b.clone(), 1).unwrap();
+    let public_inputs = circuit.public_inputs();
 
     let proof1 = generate_proof(&params1, &pk1, circuit.clone(), &public_inputs).unwrap();
     verify_proof(&params1, &vk1, &proof1, &public_inputs).unwrap();
@@ -27,14 +33,14 @@ fn test_key_regeneration() {
 
 #[test]
 fn test_proof_portability() {
-    let k = 10;
+    let k = 12;
     let (params, pk, vk) = setup_eq(k).unwrap();
 
     let set_a = vec![hash_to_field(10), hash_to_field(20), hash_to_field(30)];
     let set_b = vec![hash_to_field(20), hash_to_field(30), hash_to_field(40)];
 
-    let circuit = PsiCircuit::new(set_a, set_b, 2);
-    let public_inputs = vec![Fp::from(2u64)];
+    let circuit = PsiCircuit::new(set_a, set_b, 2).unwrap();
+    let public_inputs = circuit.public_inputs();
 
     // Generate proof
     let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
@@ -46,3 +52,282 @@ fn test_proof_portability() {
     verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
     verify_proof(&params, &vk, &proof_copy, &public_inputs).unwrap();
 }
+
+#[test]
+fn test_key_persistence() {
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let k = min_k(&PsiCircuit::empty(capacity)).unwrap();
+    let config = KeyConfig::new(k, capacity).with_semantics(IntersectionSemantics::Multiset);
+    let (params, pk, vk) = config.setup().unwrap();
+
+    let mut pk_bytes = vec![];
+    write_proving_key(&mut pk_bytes, &params, &config, &pk).unwrap();
+    let mut vk_bytes = vec![];
+    write_verifying_key(&mut vk_bytes, &params, &config, &vk).unwrap();
+
+    // Keys read back with the same params prove and verify like the originals
+    let (pk_config, loaded_pk) = read_proving_key(&mut &pk_bytes[..], &params).unwrap();
+    let (vk_config, loaded_vk) = read_verifying_key(&mut &vk_bytes[..], &params).unwrap();
+    assert_eq!(pk_config, config);
+    assert_eq!(vk_config, config);
+    assert_eq!(vk_digest(&loaded_vk), vk_digest(&vk));
+
+    let set_a = vec![hash_to_field(1), hash_to_field(1), hash_to_field(2)];
+    let set_b = vec![hash_to_field(1), hash_to_field(1)];
+    let circuit = PsiCircuit::new_with_capacity(
+        capacity,
+        set_a,
+        set_b,
+        2,
+        hash_to_field(7),
+        hash_to_field(8),
+    )
+    .unwrap()
+    .with_semantics(IntersectionSemantics::Multiset)
+    .unwrap();
+    let public_inputs = circuit.public_inputs();
+    let proof = generate_proof(&params, &loaded_pk, circuit, &public_inputs).unwrap();
+    verify_proof(&params, &vk, &proof, &public_inputs).unwrap();
+    verify_proof(&params, &loaded_vk, &proof, &public_inputs).unwrap();
+}
+
+#[test]
+fn test_key_mismatch_rejected() {
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let k = min_k(&PsiCircuit::empty(capacity)).unwrap();
+    let config = KeyConfig::new(k, capacity);
+    let (params, pk, vk) = config.setup().unwrap();
+
+    let mut pk_bytes = vec![];
+    write_proving_key(&mut pk_bytes, &params, &config, &pk).unwrap();
+    let mut vk_bytes = vec![];
+    write_verifying_key(&mut vk_bytes, &params, &config, &vk).unwrap();
+
+    // A proving key is not a verifying key, and vice versa
+    assert!(read_verifying_key(&mut &pk_bytes[..], &params).is_err());
+    assert!(read_proving_key(&mut &vk_bytes[..], &params).is_err());
+
+    // Keys do not load against other params
+    let other_params = Params::<EqAffine>::new(k + 1);
+    assert!(read_verifying_key(&mut &vk_bytes[..], &other_params).is_err());
+
+    // A key stored for another circuit fails the digest check
+    let mut forged = vec![];
+    let other = config.with_threshold(true);
+    write_verifying_key(&mut forged, &params, &other, &vk).unwrap();
+    assert!(read_verifying_key(&mut &forged[..], &params).is_err());
+
+    assert!(read_verifying_key(&mut &b"VK_PLACEHOLDER"[..], &params).is_err());
+}
+
+#[test]
+fn test_circuit_key_persistence() {
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let circuits = [
+        KeyCircuit::Disjoint,
+        KeyCircuit::Subset,
+        KeyCircuit::Multi {
+            parties: 3,
+            threshold: 2,
+        },
+    ];
+    let configs = circuits.map(|circuit| {
+        let config = KeyConfig::new(0, capacity).with_circuit(circuit);
+        KeyConfig {
+            k: config.min_k().unwrap(),
+            ..config
+        }
+    });
+
+    for config in configs {
+        let (params, _, vk) = config.setup().unwrap();
+        let mut vk_bytes = vec![];
+        write_verifying_key(&mut vk_bytes, &params, &config, &vk).unwrap();
+        let (loaded_config, loaded_vk) = read_verifying_key(&mut &vk_bytes[..], &params).unwrap();
+        assert_eq!(loaded_config, config);
+        assert_eq!(vk_digest(&loaded_vk), vk_digest(&vk));
+
+        // The digest of a key does not depend on the run that generated it
+        assert_eq!(
+            vk_digest(&config.keygen_vk(&params).unwrap()),
+            vk_digest(&vk)
+        );
+
+        // The key of one circuit does not pass for another
+        for other in configs
+            .iter()
+            .filter(|other| other.circuit != config.circuit)
+        {
+            let mut forged = vec![];
+            let other = KeyConfig {
+                k: config.k,
+                ..*other
+            };
+            write_verifying_key(&mut forged, &params, &other, &vk).unwrap();
+            assert!(read_verifying_key(&mut &forged[..], &params).is_err());
+        }
+    }
+
+    // Only PSI keys have a threshold variant
+    let config = KeyConfig::new(configs[0].k, capacity)
+        .with_circuit(KeyCircuit::Disjoint)
+        .with_threshold(true);
+    assert!(config.min_k().is_err());
+}
+
+#[test]
+fn test_params_round_trip() {
+    let params = Params::<EqAffine>::new(8);
+    let mut bytes = vec![];
+    write_params(&mut bytes, &params).unwrap();
+
+    let loaded = read_params(&mut &bytes[..]).unwrap();
+    assert_eq!(params_k(&loaded), 8);
+    assert_eq!(params_digest(&loaded), params_digest(&params));
+    let mut rewritten = vec![];
+    write_params(&mut rewritten, &loaded).unwrap();
+    assert_eq!(rewritten, bytes);
+
+    // Other params have another digest
+    assert_ne!(
+        params_digest(&Params::<EqAffine>::new(9)),
+        params_digest(&params)
+    );
+
+    // Truncated files and absurd sizes are rejected
+    assert!(read_params(&mut &bytes[..bytes.len() - 1]).is_err());
+    assert!(read_params(&mut &40u32.to_le_bytes()[..]).is_err());
+    let oversized = read_params(&mut &(MAX_K + 1).to_le_bytes()[..]).unwrap_err();
+    assert_eq!(oversized.kind(), std::io::ErrorKind::InvalidData);
+    assert!(read_params(&mut &b"k"[..]).is_err());
+}
+
+#[test]
+fn test_proof_envelope() {
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let k = min_k(&PsiCircuit::empty(capacity)).unwrap();
+    let (params, pk, vk) = KeyConfig::new(k, capacity).setup().unwrap();
+
+    let set_a = vec![hash_to_field(1), hash_to_field(2)];
+    let set_b = vec![hash_to_field(2), hash_to_field(3)];
+    let circuit = PsiCircuit::new_with_capacity(
+        capacity,
+        set_a,
+        set_b,
+        1,
+        hash_to_field(7),
+        hash_to_field(8),
+    )
+    .unwrap();
+    let public_inputs = circuit.public_inputs();
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+
+    let envelope = ProofEnvelope::new("psi", &params, &vk, &public_inputs, proof);
+    let mut bytes = vec![];
+    envelope.write(&mut bytes).unwrap();
+    assert_eq!(&bytes[..4], b"ZPSI");
+
+    // The envelope round-trips and verifies against the same params and key
+    let loaded = ProofEnvelope::read(&mut &bytes[..]).unwrap();
+    assert_eq!(loaded, envelope);
+    assert_eq!(loaded.k, k);
+    assert_eq!(loaded.public_inputs().unwrap(), public_inputs);
+    loaded.check(&params, &vk).unwrap();
+    verify_proof(
+        &params,
+        &vk,
+        &loaded.proof,
+        &loaded.public_inputs().unwrap(),
+    )
+    .unwrap();
+
+    // Another key or other params are rejected before verifying
+    let (_, _, other_vk) = KeyConfig::new(k, SetCapacity::new(3, 4).unwrap())
+        .setup()
+        .unwrap();
+    assert!(loaded.check(&params, &other_vk).is_err());
+    assert!(loaded.check(&Params::<EqAffine>::new(k + 1), &vk).is_err());
+
+    // So are other files and other format versions
+    let mut other_version = bytes.clone();
+    other_version[4..6].copy_from_slice(&(PROOF_ENVELOPE_VERSION + 1).to_le_bytes());
+    assert!(ProofEnvelope::read(&mut &other_version[..]).is_err());
+    assert!(ProofEnvelope::read(&mut &loaded.proof[..]).is_err());
+    assert!(ProofEnvelope::read(&mut &bytes[..bytes.len() - 1]).is_err());
+
+    let mut other_curve = envelope.clone();
+    other_curve.curve = "bn254-kzg".to_string();
+    let mut other_curve_bytes = vec![];
+    other_curve.write(&mut other_curve_bytes).unwrap();
+    assert!(ProofEnvelope::read(&mut &other_curve_bytes[..]).is_err());
+}
+
+#[test]
+fn test_proof_json_export() {
+    let capacity = SetCapacity::new(4, 4).unwrap();
+    let k = min_k(&PsiCircuit::empty(capacity)).unwrap();
+    let (params, pk, vk) = KeyConfig::new(k, capacity).setup().unwrap();
+
+    let set_a = vec![hash_to_field(1), hash_to_field(2), hash_to_field(3)];
+    let set_b = vec![hash_to_field(2), hash_to_field(3)];
+    let circuit = PsiCircuit::new_with_capacity(
+        capacity,
+        set_a,
+        set_b,
+        2,
+        hash_to_field(7),
+        hash_to_field(8),
+    )
+    .unwrap();
+    let public_inputs = circuit.public_inputs();
+    let proof = generate_proof(&params, &pk, circuit, &public_inputs).unwrap();
+    let envelope = ProofEnvelope::new("psi", &params, &vk, &public_inputs, proof);
+
+    let export = ProofJson::from(&envelope);
+    assert!(export.proof.starts_with("0x"));
+    assert_eq!(export.public_inputs.len(), public_inputs.len());
+
+    // The JSON decodes back to the envelope, which still verifies
+    let loaded = ProofJson::from_json(&export.to_json()).unwrap();
+    assert_eq!(loaded, export);
+    let decoded = loaded.to_envelope().unwrap();
+    assert_eq!(decoded, envelope);
+    decoded.check(&params, &vk).unwrap();
+    verify_proof(
+        &params,
+        &vk,
+        &decoded.proof,
+        &decoded.public_inputs().unwrap(),
+    )
+    .unwrap();
+
+    // Altered public inputs no longer verify
+    let mut altered = loaded.clone();
+    altered.public_inputs[0] = format!("0x03{}", "00".repeat(31));
+    let altered = altered.to_envelope().unwrap();
+    assert!(
+        verify_proof(
+            &params,
+            &vk,
+            &altered.proof,
+            &altered.public_inputs().unwrap()
+        )
+        .is_err()
+    );
+
+    // Malformed hex, other versions and other keys are rejected
+    let mut malformed = loaded.clone();
+    malformed.proof = malformed.proof.trim_start_matches("0x").to_string();
+    assert!(malformed.to_envelope().is_err());
+    let mut short = loaded.clone();
+    short.vk_digest = "0x00".to_string();
+    assert!(short.to_envelope().is_err());
+    let mut version = loaded.clone();
+    version.version += 1;
+    assert!(ProofJson::from_json(&version.to_json()).is_err());
+    assert!(ProofJson::from_json("{}").is_err());
+    let (_, _, other_vk) = KeyConfig::new(k, SetCapacity::new(3, 4).unwrap())
+        .setup()
+        .unwrap();
+    assert!(decoded.check(&params, &other_vk).is_err());
+}
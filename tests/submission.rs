@@ -0,0 +1,194 @@
+#![cfg(feature = "submit")]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+use zk_psi_verifier::submit::{
+    PROOF_STATUS_METHOD, SUBMIT_PROOF_METHOD, SubmissionClient, SubmissionStatus,
+};
+use zk_psi_verifier::{PROOF_CURVE, PROOF_ENVELOPE_VERSION, ProofJson};
+
+/// In-process gateway: every submission moves from pending to included to
+/// finalized, one step per status request, except proofs without public
+/// inputs, which fail, and "slow" circuits, which stay pending. Status replies
+/// use chunked transfer encoding.
+fn spawn_mock_node() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/rpc", listener.local_addr().unwrap());
+    let submissions = Arc::new(Mutex::new(HashMap::<String, (ProofJson, usize)>::new()));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let submissions = submissions.clone();
+            thread::spawn(move || serve(stream.unwrap(), &submissions));
+        }
+    });
+    url
+}
+
+fn serve(stream: TcpStream, submissions: &Mutex<HashMap<String, (ProofJson, usize)>>) {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+
+    let mut stream = reader.into_inner();
+    if !request_line.starts_with("POST /rpc ") {
+        write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        return;
+    }
+
+    let request: Value = serde_json::from_slice(&body).unwrap();
+    let chunked = request["method"] == PROOF_STATUS_METHOD;
+    let params = &request["params"];
+    let mut submissions = submissions.lock().unwrap();
+    let outcome = match request["method"].as_str().unwrap() {
+        SUBMIT_PROOF_METHOD => {
+            let proof: ProofJson = serde_json::from_value(params[0].clone()).unwrap();
+            let id = format!("0x{:04x}", submissions.len());
+            submissions.insert(id.clone(), (proof, 0));
+            Ok(json!(id))
+        }
+        PROOF_STATUS_METHOD => match submissions.get_mut(params[0].as_str().unwrap()) {
+            Some((proof, polls)) => {
+                *polls += 1;
+                let status = if proof.public_inputs.is_empty() {
+                    SubmissionStatus::Failed
+                } else if proof.circuit == "slow" {
+                    SubmissionStatus::Pending
+                } else {
+                    [
+                        SubmissionStatus::Pending,
+                        SubmissionStatus::IncludedInBlock,
+                        SubmissionStatus::Finalized,
+                    ][(*polls - 1).min(2)]
+                };
+                Ok(json!(status))
+            }
+            None => Err(json!({ "code": -32602, "message": "unknown submission" })),
+        },
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    };
+    let response = match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": error }),
+    }
+    .to_string();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n"
+    )
+    .unwrap();
+    if chunked {
+        let (head, tail) = response.split_at(response.len() / 2);
+        write!(
+            stream,
+            "Transfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            head.len(),
+            head,
+            tail.len(),
+            tail
+        )
+        .unwrap();
+    } else {
+        write!(
+            stream,
+            "Content-Length: {}\r\n\r\n{}",
+            response.len(),
+            response
+        )
+        .unwrap();
+    }
+}
+
+fn export(circuit: &str, public_inputs: usize) -> ProofJson {
+    let zero = format!("0x{}", "00".repeat(32));
+    ProofJson {
+        version: PROOF_ENVELOPE_VERSION,
+        curve: PROOF_CURVE.to_string(),
+        circuit: circuit.to_string(),
+        k: 9,
+        params_digest: zero.clone(),
+        vk_digest: zero.clone(),
+        public_inputs: vec![zero; public_inputs],
+        proof: "0x0102".to_string(),
+    }
+}
+
+#[test]
+fn test_submit_and_wait_for_finalization() {
+    let url = spawn_mock_node();
+    let client = SubmissionClient::new(&url)
+        .unwrap()
+        .with_poll_interval(Duration::from_millis(10));
+
+    let id = client.submit(&export("psi", 3)).unwrap();
+    assert_eq!(client.status(&id).unwrap(), SubmissionStatus::Pending);
+
+    let mut seen = vec![];
+    let status = client.wait(&id, |status| seen.push(status)).unwrap();
+    assert_eq!(status, SubmissionStatus::Finalized);
+    assert_eq!(
+        seen,
+        vec![
+            SubmissionStatus::IncludedInBlock,
+            SubmissionStatus::Finalized
+        ]
+    );
+
+    // Submissions are tracked independently
+    let other = client.submit(&export("psi", 3)).unwrap();
+    assert_ne!(other, id);
+    assert_eq!(client.status(&other).unwrap(), SubmissionStatus::Pending);
+}
+
+#[test]
+fn test_submission_failures() {
+    let url = spawn_mock_node();
+    let client = SubmissionClient::new(&url)
+        .unwrap()
+        .with_poll_interval(Duration::from_millis(10));
+
+    // A rejected proof ends in a final failed status
+    let id = client.submit(&export("psi", 0)).unwrap();
+    assert_eq!(client.wait(&id, |_| {}).unwrap(), SubmissionStatus::Failed);
+
+    // A submission that never becomes final times out
+    let slow = client.clone().with_timeout(Duration::from_millis(100));
+    let id = slow.submit(&export("slow", 3)).unwrap();
+    assert_eq!(
+        slow.wait(&id, |_| {}).unwrap_err().kind(),
+        ErrorKind::TimedOut
+    );
+
+    // JSON-RPC and HTTP errors surface as errors
+    assert!(client.status("0xffff").is_err());
+    let wrong_path = SubmissionClient::new(&url.replace("/rpc", "/other")).unwrap();
+    assert!(wrong_path.submit(&export("psi", 3)).is_err());
+
+    // Endpoints must be http:// or https:// URLs
+    assert!(SubmissionClient::new("https://127.0.0.1:8080/rpc").is_ok());
+    assert!(SubmissionClient::new("ftp://127.0.0.1/rpc").is_err());
+    assert!(SubmissionClient::new("not a url").is_err());
+}